@@ -0,0 +1,128 @@
+/// Whether the char ends a line. Lines end with `\n`, `\r\n` or a lone `\r`. Other Unicode line
+/// terminators, such as U+2028 LINE SEPARATOR, are whitespace within a line, as in Rust. The lexer
+/// and `SourceMap` share this so that the lines of tokens and errors agree.
+pub fn is_line_break(char: char) -> bool {
+    char == '\n' || char == '\r'
+}
+
+/// Maps char offsets (not byte offsets) in a source to lines and columns. Line starts are computed once up front,
+/// so each lookup is a binary search rather than a scan of the source.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    /// Char position at which each line starts, the first line always starting at 0.
+    line_starts: Vec<usize>,
+    length: usize,
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        let mut length = 0;
+        let mut chars = source.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            length += 1;
+
+            // `\r\n` is a single line break, so the line starts after the `\n`
+            if is_line_break(ch) && !(ch == '\r' && chars.peek() == Some(&'\n')) {
+                line_starts.push(length);
+            }
+        }
+
+        Self { line_starts, length }
+    }
+
+    /// The 1-based line and column of a char position. Positions past the end of the source
+    /// resolve to the end of the source.
+    pub fn line_col(&self, position: usize) -> (usize, usize) {
+        let position = position.min(self.length);
+        let line = self.line_starts.partition_point(|&start| start <= position);
+
+        (line, position - self.line_starts[line - 1] + 1)
+    }
+
+    /// The text of the 1-based line without its line break. Empty for lines past the end of the
+    /// source.
+    pub fn line_text<'a>(&self, source: &'a str, line: usize) -> &'a str {
+        let start = match self.line_starts.get(line.wrapping_sub(1)) {
+            Some(&start) => start,
+            None => return "",
+        };
+        let rest = match source.char_indices().nth(start) {
+            Some((offset, _)) => &source[offset..],
+            None => "",
+        };
+
+        match rest.find(is_line_break) {
+            Some(end) => &rest[..end],
+            None => rest,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_line_break, SourceMap};
+
+    /// The 1-based line and column of a position by scanning the source from the start.
+    fn naive_line_col(source: &str, position: usize) -> (usize, usize) {
+        let chars: Vec<char> = source.chars().collect();
+        let (mut line, mut column) = (1, 1);
+
+        for (index, &ch) in chars.iter().enumerate().take(position.min(chars.len())) {
+            if is_line_break(ch) && !(ch == '\r' && chars.get(index + 1) == Some(&'\n')) {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+
+        (line, column)
+    }
+
+    #[test]
+    fn line_col_matches_a_naive_scan() {
+        let sources = ["", "a", "\n", "ab\ncd\n\nef", "a\r\nb\rc\r\r\nd", "é\nü€\n", "\n\n\n x"];
+
+        for source in sources {
+            let source_map = SourceMap::new(source);
+
+            for position in 0..source.chars().count() + 3 {
+                assert_eq!(source_map.line_col(position), naive_line_col(source, position), "{source:?} at {position}");
+            }
+        }
+    }
+
+    #[test]
+    fn returns_line_text_and_count() {
+        let source = "one\r\ntwo\rthree\n";
+        let source_map = SourceMap::new(source);
+
+        assert_eq!(source_map.line_count(), 4);
+        assert_eq!(source_map.line_text(source, 1), "one");
+        assert_eq!(source_map.line_text(source, 2), "two");
+        assert_eq!(source_map.line_text(source, 3), "three");
+        assert_eq!(source_map.line_text(source, 4), "");
+        assert_eq!(source_map.line_text(source, 0), "");
+        assert_eq!(source_map.line_text(source, 9), "");
+    }
+
+    #[test]
+    fn unicode_line_terminators_and_spaces_do_not_end_lines() {
+        for separator in ['\u{2028}', '\u{2029}', '\u{85}', '\u{a0}', '\u{b}', '\u{c}'] {
+            let source = format!("a{separator}b\nc");
+            let source_map = SourceMap::new(&source);
+
+            assert!(!is_line_break(separator), "{separator:?}");
+            assert_eq!(source_map.line_count(), 2, "{separator:?}");
+            assert_eq!(source_map.line_col(2), (1, 3), "{separator:?}");
+            assert_eq!(source_map.line_col(4), (2, 1), "{separator:?}");
+            assert_eq!(source_map.line_text(&source, 1), format!("a{separator}b"));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt::Display;
+
+use crate::source_map::SourceMap;
+
+/// A range of the source from `start` up to, but not including, `end`. Both are char offsets, not
+/// byte offsets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn start_from(start: usize) -> Self {
+        Self::new(start, start)
+    }
+
+    pub fn extend(mut self, end: usize) -> Self {
+        self.end = end;
+        self
+    }
+
+    /// True if the span covers no characters, e.g. one created with [`Span::start_from`] to mark
+    /// where something is missing.
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// Resolves the start and end positions to lines and columns within the source.
+    pub fn resolve(&self, source: &str) -> ResolvedSpan {
+        self.resolve_with(&SourceMap::new(source))
+    }
+
+    /// Like [`Span::resolve`], but reuses a source map when resolving many spans in one source.
+    pub fn resolve_with(&self, source_map: &SourceMap) -> ResolvedSpan {
+        let (start_line, start_column) = source_map.line_col(self.start);
+        let (end_line, end_column) = source_map.line_col(self.end);
+
+        ResolvedSpan {
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+/// A span as 1-based lines and columns, displayed as `line:column-line:column`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResolvedSpan {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Display for ResolvedSpan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}-{}:{}", self.start_line, self.start_column, self.end_line, self.end_column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResolvedSpan, Span};
+
+    #[test]
+    fn spans_covering_no_characters_are_empty() {
+        assert!(Span::start_from(3).is_empty());
+        assert!(Span::new(3, 3).is_empty());
+        assert!(Span::new(4, 3).is_empty());
+        assert!(!Span::new(3, 4).is_empty());
+        assert!(!Span::start_from(3).extend(5).is_empty());
+    }
+
+    #[test]
+    fn resolves_spans_across_multiple_lines() {
+        let source = "let x = 1\nlet y = x +\n  2\n";
+
+        assert_eq!(Span::new(4, 5).resolve(source).to_string(), "1:5-1:6");
+        assert_eq!(Span::new(14, 19).resolve(source).to_string(), "2:5-2:10");
+        assert_eq!(Span::new(8, 25).resolve(source), ResolvedSpan { start_line: 1, start_column: 9, end_line: 3, end_column: 4 });
+    }
+
+    #[test]
+    fn resolves_positions_at_line_breaks_and_the_end() {
+        let source = "a\r\nb\rc";
+
+        assert_eq!(Span::new(1, 3).resolve(source).to_string(), "1:2-2:1");
+        assert_eq!(Span::new(3, 5).resolve(source).to_string(), "2:1-3:1");
+        assert_eq!(Span::new(6, 100).resolve(source).to_string(), "3:2-3:2");
+        assert_eq!(Span::start_from(0).resolve("").to_string(), "1:1-1:1");
+    }
+}
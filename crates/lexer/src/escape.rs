@@ -0,0 +1,94 @@
+use common::span::Span;
+
+use crate::{reader_error::ReaderError, reader_state::ReaderState};
+
+/// Reads an escape sequence, starting at its backslash, and returns the character it stands for.
+/// Every reader which supports escapes uses this so that they decode the same sequences: `\n`,
+/// `\r`, `\t`, `\0`, `\\`, `\"`, `\'`, `\$`, `\x41` for characters up to `\x7F`, and `\u{1F600}`
+/// for any Unicode scalar value.
+///
+/// An invalid sequence is consumed as far as it could be read, and the error spans it.
+pub fn read_escape(state: &mut ReaderState) -> Result<char, ReaderError> {
+    let start = state.get_position();
+
+    if state.read() != Some(&'\\') {
+        return Err(escape_error("Expected '\\' to start an escape sequence", start, state));
+    }
+
+    let char = match state.read() {
+        Some(&char) => char,
+        None => return Err(escape_error("Unterminated escape sequence", start, state)),
+    };
+
+    let value = match char {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        '0' => '\0',
+        '\\' => '\\',
+        '"' => '"',
+        '\'' => '\'',
+        '$' => '$',
+        'x' => return read_hex_escape(state, start),
+        'u' => return read_unicode_escape(state, start),
+        _ => return Err(escape_error(format!("Unknown escape sequence '\\{}'", char), start, state)),
+    };
+
+    Ok(value)
+}
+
+fn read_hex_escape(state: &mut ReaderState, start: usize) -> Result<char, ReaderError> {
+    let mut value = 0;
+
+    for _ in 0..2 {
+        match state.peek().and_then(|char| char.to_digit(16)) {
+            Some(digit) => {
+                state.read();
+                value = value * 16 + digit;
+            }
+            None => return Err(escape_error("Expected two hex digits after '\\x'", start, state)),
+        }
+    }
+
+    // As in Rust, higher values must be written as `\u{...}`
+    if value > 0x7F {
+        return Err(escape_error("Escapes with '\\x' must be at most '\\x7F'", start, state));
+    }
+
+    Ok(char::from(value as u8))
+}
+
+fn read_unicode_escape(state: &mut ReaderState, start: usize) -> Result<char, ReaderError> {
+    if state.read_str("{").is_none() {
+        return Err(escape_error("Expected '{' after '\\u'", start, state));
+    }
+
+    let mut value: u32 = 0;
+    let mut digits = 0;
+
+    while let Some(digit) = state.peek().and_then(|char| char.to_digit(16)) {
+        state.read();
+        digits += 1;
+
+        if digits > 6 {
+            return Err(escape_error("Escapes with '\\u' have at most 6 hex digits", start, state));
+        }
+
+        value = value * 16 + digit;
+    }
+
+    if digits == 0 {
+        return Err(escape_error("Expected hex digits after '\\u{'", start, state));
+    }
+
+    if state.read_str("}").is_none() {
+        return Err(escape_error("Expected '}' to close '\\u{'", start, state));
+    }
+
+    char::from_u32(value)
+        .ok_or_else(|| escape_error(format!("'\\u{{{:X}}}' is not a valid character", value), start, state))
+}
+
+fn escape_error(message: impl Into<String>, start: usize, state: &ReaderState) -> ReaderError {
+    ReaderError::new(message, start).with_span(Span::new(start, state.get_position()))
+}
@@ -1,16 +1,71 @@
-use crate::{lexer_result, lexer_state::LexerState, reader::Reader, reader_result::ReaderResult};
+use common::span::Span;
+
+use crate::{
+    lexer_result::{self, LexEvent}, lexer_state::LexerState, reader::Reader, reader_error::ReaderError,
+    reader_position_error::ReaderPositionError, reader_result::ReaderResult,
+};
 
 pub struct Lexer {
     pub readers: Vec<Box<dyn Reader>>,
+    fallback: Option<Box<dyn Reader>>,
+    trace: bool,
+    byte_spans: bool,
+    max_tokens: Option<usize>,
+    max_source_length: Option<usize>,
 }
 
 impl Lexer {
     pub fn new() -> Self {
         Self {
             readers: Vec::new(),
+            fallback: None,
+            trace: false,
+            byte_spans: false,
+            max_tokens: None,
+            max_source_length: None,
         }
     }
 
+    /// When enabled, the result records the name of the reader which produced each token.
+    pub fn with_trace(mut self, enabled: bool) -> Self {
+        self.trace = enabled;
+
+        self
+    }
+
+    /// When enabled, the result records the byte offsets of each token alongside its char offsets.
+    pub fn with_byte_spans(mut self, enabled: bool) -> Self {
+        self.byte_spans = enabled;
+
+        self
+    }
+
+    /// Stops lexing with an error once the given number of tokens have been read, which bounds the
+    /// memory used for untrusted input. Unlimited by default.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+
+        self
+    }
+
+    /// Refuses to lex sources longer than the given number of chars. Unlimited by default.
+    pub fn with_max_source_length(mut self, max_source_length: usize) -> Self {
+        self.max_source_length = Some(max_source_length);
+
+        self
+    }
+
+    /// Sets the reader to run when no other reader matches, e.g. to report the unexpected character
+    /// or to collect unknown text into a token. Without one, an error is reported for the character.
+    pub fn with_fallback<R>(mut self, reader: R) -> Self
+    where
+        R: 'static + Reader,
+    {
+        self.fallback = Some(Box::new(reader));
+
+        self
+    }
+
     pub fn add_reader<R>(mut self, reader: R) -> Self
     where
         R: 'static + Reader,
@@ -20,51 +75,745 @@ impl Lexer {
         self
     }
 
+    /// Inserts a reader at the given index, so that it is tried before the readers after it.
+    /// Fails if the index is greater than the number of readers.
+    pub fn insert_reader<R>(mut self, index: usize, reader: R) -> Result<Self, ReaderPositionError>
+    where
+        R: 'static + Reader,
+    {
+        if index > self.readers.len() {
+            return Err(ReaderPositionError::IndexOutOfBounds {
+                index,
+                len: self.readers.len(),
+            });
+        }
+
+        self.readers.insert(index, Box::new(reader));
+
+        Ok(self)
+    }
+
+    /// Inserts a reader immediately before the first reader with the given name, e.g. a keyword
+    /// reader before `"IdentifierReader"`. Fails if there is no reader with that name.
+    pub fn add_reader_before<R>(self, name: &str, reader: R) -> Result<Self, ReaderPositionError>
+    where
+        R: 'static + Reader,
+    {
+        let index = self
+            .readers
+            .iter()
+            .position(|existing| existing.name() == name)
+            .ok_or_else(|| ReaderPositionError::UnknownReader(name.to_string()))?;
+
+        self.insert_reader(index, reader)
+    }
+
     pub fn lex(&mut self, source: &str) -> lexer_result::LexerResult {
-        let mut state = LexerState {
-            chars: source.chars().collect(),
-            length: source.chars().count(),
-            position: 0,
-        };
+        self.lex_from(source, 0)
+    }
+
+    /// Lexes each of the sources separately with the same readers. Nothing carries over from one
+    /// source to the next, so each result is the same as lexing that source alone.
+    pub fn lex_all(&mut self, sources: &[&str]) -> Vec<lexer_result::LexerResult> {
+        sources.iter().map(|source| self.lex(source)).collect()
+    }
+
+    /// Lexes the source starting from the given char offset through to the end of the source.
+    /// Token positions are relative to the start of the whole source, which allows an edited
+    /// region to be re-lexed without re-lexing the unchanged prefix.
+    pub fn lex_from(&mut self, source: &str, start: usize) -> lexer_result::LexerResult {
+        self.lex_ordered(source, start).0
+    }
+
+    /// Lexes the source into a single stream of tokens and errors in the order they were read,
+    /// unlike `lex` which returns them separately.
+    pub fn lex_events(&mut self, source: &str) -> Vec<LexEvent> {
+        let (result, tokens_before_errors) = self.lex_ordered(source, 0);
+        let mut events = Vec::with_capacity(result.tokens.len() + result.errors.len());
+        let mut tokens = result.tokens.into_iter();
+        let mut emitted = 0;
+
+        for (error, tokens_before) in result.errors.into_iter().zip(tokens_before_errors) {
+            events.extend(tokens.by_ref().take(tokens_before - emitted).map(LexEvent::Token));
+            events.push(LexEvent::Error(error));
+            emitted = tokens_before;
+        }
+
+        events.extend(tokens.map(LexEvent::Token));
+        events
+    }
+
+    /// Lexes as `lex_from`, also returning the number of tokens which were read before each error.
+    fn lex_ordered(&mut self, source: &str, start: usize) -> (lexer_result::LexerResult, Vec<usize>) {
+        let length = source.chars().count();
         let mut result = lexer_result::LexerResult {
             tokens: Vec::new(),
             errors: Vec::new(),
+            trace: if self.trace { Some(Vec::new()) } else { None },
+            byte_spans: if self.byte_spans { Some(Vec::new()) } else { None },
         };
+        let mut tokens_before_errors = Vec::new();
 
-        if source.len() == 0 {
-            return result;
+        macro_rules! push_error {
+            ($error:expr) => {{
+                tokens_before_errors.push(result.tokens.len());
+                result.errors.push($error);
+            }};
         }
 
-        loop {
-            for reader in &mut self.readers {
-                let mut reader_state = (&state).into();
+        if let Some(max_source_length) = self.max_source_length.filter(|max| length > *max) {
+            push_error!(
+                ReaderError::new(
+                    format!("Source is {} characters long, exceeding the limit of {}", length, max_source_length),
+                    max_source_length,
+                )
+                .with_span(Span::new(max_source_length, length))
+            );
 
-                let reader_result = reader.read(&mut reader_state);
+            return (result, tokens_before_errors);
+        }
 
-                match reader_result {
-                    ReaderResult::Token(token) => {
-                        result.tokens.push(token);
-                        state.position = reader_state.get_position();
+        let mut state = LexerState {
+            chars: source.chars().collect(),
+            length,
+            position: start,
+            byte_position: source.chars().take(start).map(char::len_utf8).sum(),
+        };
 
-                        break;
-                    }
+        while !state.at_end() {
+            let position = state.position;
+            let mut handled = false;
+
+            // Readers are tried in order, falling back to the fallback reader if none match
+            for reader in self.readers.iter().chain(self.fallback.iter()) {
+                let mut reader_state = (&state).into();
+
+                let tokens = match reader.read(&mut reader_state) {
+                    ReaderResult::Token(token) => vec![token],
+                    ReaderResult::Tokens(tokens) => tokens,
                     ReaderResult::None => {
                         // Continue to the next reader.
                         continue;
                     }
                     ReaderResult::Err(error) => {
-                        result.errors.push(error);
-                        state.position = reader_state.get_position();
+                        handled = true;
+                        push_error!(error);
+                        state.advance_to(reader_state.get_position());
                         break;
                     }
+                };
+
+                handled = true;
+
+                // A token which consumed no input would cause the lexer to loop forever
+                if reader_state.get_position() == position {
+                    push_error!(ReaderError::new(
+                        format!("{} returned a token without consuming any input", reader.name()),
+                        position,
+                    ));
+
+                    break;
+                }
+
+                for token in tokens {
+                    // Every token covers some of the source, so an empty one is a bug in the reader
+                    if token.start == token.end {
+                        push_error!(
+                            ReaderError::new(format!("{} returned an empty {} token", reader.name(), token.kind), token.start)
+                                .with_span(Span::new(token.start, token.end))
+                        );
+
+                        continue;
+                    }
+
+                    if self.max_tokens.is_some_and(|max| result.tokens.len() >= max) {
+                        push_error!(
+                            ReaderError::new(format!("Too many tokens, the limit is {}", result.tokens.len()), token.start)
+                                .with_span(Span::new(token.start, token.end))
+                        );
+
+                        return (result, tokens_before_errors);
+                    }
+
+                    if let Some(trace) = &mut result.trace {
+                        trace.push(reader.name());
+                    }
+
+                    if let Some(byte_spans) = &mut result.byte_spans {
+                        byte_spans.push(Span::new(state.byte_offset(token.start), state.byte_offset(token.end)));
+                    }
+
+                    result.tokens.push(token);
                 }
-            }
 
-            if state.at_end() {
+                state.advance_to(reader_state.get_position());
+
                 break;
             }
+
+            if !handled {
+                push_error!(
+                    ReaderError::new(format!("No reader matched the character '{}'", state.chars[position]), position)
+                        .with_span(Span::new(position, position + 1))
+                );
+            }
+
+            // Skip the current character if no reader consumed any input, so that the lexer
+            // always makes progress
+            if state.position == position {
+                state.advance_to(position + 1);
+            }
+        }
+
+        (result, tokens_before_errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+
+    use common::span::Span;
+
+    use crate::{
+        default_lexer,
+        lexer::Lexer,
+        lexer_result::LexEvent,
+        reader::Reader,
+        reader_position_error::ReaderPositionError,
+        reader_result::ReaderResult,
+        reader_state::ReaderState,
+        token::{Token, TokenKind, TokenValue},
+    };
+
+    /// Reads `unless` as a reserved word, which the identifier reader would otherwise take.
+    struct UnlessReader;
+
+    impl Reader for UnlessReader {
+        fn name(&self) -> &'static str {
+            "UnlessReader"
+        }
+
+        fn read(&self, state: &mut ReaderState) -> ReaderResult {
+            if state.read_word("unless").is_none() {
+                return ReaderResult::None;
+            }
+
+            ReaderResult::Token(Token {
+                kind: TokenKind::Reserved,
+                start: state.get_start(),
+                end: state.get_position(),
+                value: TokenValue::String("unless".to_string()),
+            })
+        }
+    }
+
+    /// Returns a token for each character without consuming it.
+    struct ZeroWidthReader;
+
+    impl Reader for ZeroWidthReader {
+        fn name(&self) -> &'static str {
+            "ZeroWidthReader"
+        }
+
+        fn read(&self, state: &mut ReaderState) -> ReaderResult {
+            ReaderResult::Token(Token {
+                kind: TokenKind::Identifier,
+                start: state.get_start(),
+                end: state.get_start() + 1,
+                value: TokenValue::None,
+            })
+        }
+    }
+
+    /// Consumes a character but returns an empty token for it.
+    struct EmptyTokenReader;
+
+    impl Reader for EmptyTokenReader {
+        fn name(&self) -> &'static str {
+            "EmptyTokenReader"
+        }
+
+        fn read(&self, state: &mut ReaderState) -> ReaderResult {
+            state.read();
+
+            ReaderResult::Token(Token {
+                kind: TokenKind::Identifier,
+                start: state.get_start(),
+                end: state.get_start(),
+                value: TokenValue::None,
+            })
+        }
+    }
+
+    fn errors(mut lexer: Lexer, source: &str) -> Vec<(String, usize)> {
+        lexer.lex(source).errors.into_iter().map(|error| (error.message, error.position)).collect()
+    }
+
+    fn first_kind(lexer: Lexer, source: &str) -> TokenKind {
+        let mut lexer = lexer;
+        lexer.lex(source).tokens[0].kind.clone()
+    }
+
+    #[test]
+    fn traces_the_reader_of_each_token() {
+        let result = default_lexer().with_trace(true).lex("123 + x");
+
+        assert_eq!(result.trace, Some(vec![
+            "NumberReader",
+            "WhitespaceReader",
+            "OperatorReader",
+            "WhitespaceReader",
+            "IdentifierReader",
+        ]));
+    }
+
+    #[test]
+    fn does_not_trace_by_default() {
+        assert_eq!(default_lexer().lex("123 + x").trace, None);
+    }
+
+    #[test]
+    fn terminates_when_a_reader_consumes_no_input() {
+        assert_eq!(errors(Lexer::new().add_reader(ZeroWidthReader), "ab"), [
+            ("ZeroWidthReader returned a token without consuming any input".to_string(), 0),
+            ("ZeroWidthReader returned a token without consuming any input".to_string(), 1),
+        ]);
+    }
+
+    #[test]
+    fn reports_empty_tokens() {
+        let mut lexer = Lexer::new().add_reader(EmptyTokenReader);
+        let result = lexer.lex("a");
+
+        assert!(result.tokens.is_empty());
+        assert_eq!(errors(lexer, "a"), [("EmptyTokenReader returned an empty Identifier token".to_string(), 0)]);
+    }
+
+    /// Consumes a run of spaces, as `WhitespaceReader` does, but returns an empty token for it.
+    struct EmptyWhitespaceReader;
+
+    impl Reader for EmptyWhitespaceReader {
+        fn name(&self) -> &'static str {
+            "EmptyWhitespaceReader"
+        }
+
+        fn read(&self, state: &mut ReaderState) -> ReaderResult {
+            while state.peek() == Some(&' ') {
+                state.read();
+            }
+
+            match state.did_advance() {
+                true => ReaderResult::Token(Token {
+                    kind: TokenKind::Whitespace,
+                    start: state.get_start(),
+                    end: state.get_start(),
+                    value: TokenValue::None,
+                }),
+                false => ReaderResult::None,
+            }
+        }
+    }
+
+    #[test]
+    fn drops_empty_tokens_and_keeps_lexing() {
+        let lexer = || default_lexer().add_reader_before("WhitespaceReader", EmptyWhitespaceReader).unwrap();
+        let tokens = lexer().lex("a  b").tokens;
+
+        assert_eq!(tokens.iter().map(|token| token.kind.clone()).collect::<Vec<_>>(), [TokenKind::Identifier, TokenKind::Identifier]);
+        assert_eq!((tokens[1].start, tokens[1].end), (3, 4));
+        assert!(tokens.iter().all(|token| token.start < token.end));
+        assert_eq!(errors(lexer(), "a  b"), [("EmptyWhitespaceReader returned an empty Whitespace token".to_string(), 1)]);
+    }
+
+    /// Reads a raw string between two of its configured delimiter.
+    struct RawStringReader {
+        delimiter: char,
+    }
+
+    impl Reader for RawStringReader {
+        fn name(&self) -> &'static str {
+            "RawStringReader"
+        }
+
+        fn read(&self, state: &mut ReaderState) -> ReaderResult {
+            if state.peek() != Some(&self.delimiter) {
+                return ReaderResult::None;
+            }
+
+            state.read();
+            let mut value = String::new();
+
+            while let Some(&char) = state.read() {
+                if char == self.delimiter {
+                    return ReaderResult::Token(Token {
+                        kind: TokenKind::String,
+                        start: state.get_start(),
+                        end: state.get_position(),
+                        value: TokenValue::String(value),
+                    });
+                }
+
+                value.push(char);
+            }
+
+            ReaderResult::None
+        }
+    }
+
+    /// Collects a run of characters which no other reader matches into a single string token.
+    struct RawTextReader;
+
+    impl Reader for RawTextReader {
+        fn name(&self) -> &'static str {
+            "RawTextReader"
+        }
+
+        fn read(&self, state: &mut ReaderState) -> ReaderResult {
+            let mut text = String::new();
+
+            while let Some(&char) = state.peek() {
+                if char.is_alphanumeric() || char.is_whitespace() {
+                    break;
+                }
+
+                text.push(char);
+                state.read();
+            }
+
+            ReaderResult::Token(Token {
+                kind: TokenKind::String,
+                start: state.get_start(),
+                end: state.get_position(),
+                value: TokenValue::String(text),
+            })
+        }
+    }
+
+    /// Reads `key:value` pairs of single chars as an identifier, a colon and another identifier.
+    struct PairReader;
+
+    impl Reader for PairReader {
+        fn name(&self) -> &'static str {
+            "PairReader"
+        }
+
+        fn read(&self, state: &mut ReaderState) -> ReaderResult {
+            if state.peek_nth(1) != Some(&':') || state.peek_nth(2).is_none() {
+                return ReaderResult::None;
+            }
+
+            let start = state.get_start();
+            let key = *state.read().unwrap();
+            state.read();
+            let value = *state.read().unwrap();
+
+            let identifier = |text: char, start| Token {
+                kind: TokenKind::Identifier,
+                start,
+                end: start + 1,
+                value: TokenValue::String(text.to_string()),
+            };
+
+            ReaderResult::Tokens(vec![
+                identifier(key, start),
+                Token { kind: TokenKind::Colon, start: start + 1, end: start + 2, value: TokenValue::None },
+                identifier(value, start + 2),
+            ])
+        }
+    }
+
+    /// Counts how often it is asked to read, without ever matching.
+    struct CountingReader(Rc<Cell<usize>>);
+
+    impl Reader for CountingReader {
+        fn name(&self) -> &'static str {
+            "CountingReader"
+        }
+
+        fn read(&self, _state: &mut ReaderState) -> ReaderResult {
+            self.0.set(self.0.get() + 1);
+
+            ReaderResult::None
+        }
+    }
+
+    #[test]
+    fn readers_can_hold_configuration() {
+        for delimiter in ['#', '%'] {
+            let source = format!("x {delimiter}a \"b\"{delimiter}");
+            let result = default_lexer()
+                .add_reader_before("StringReader", RawStringReader { delimiter })
+                .unwrap()
+                .with_trace(true)
+                .lex(&source);
+
+            assert!(result.errors.is_empty(), "{:?}", result.errors);
+            assert_eq!(result.tokens[2].value, TokenValue::String("a \"b\"".to_string()));
+            assert_eq!(result.trace.unwrap()[2], "RawStringReader");
+        }
+    }
+
+    #[test]
+    fn runs_the_fallback_only_when_no_reader_matches() {
+        let result = default_lexer().with_fallback(RawTextReader).with_trace(true).lex("x = @€ + y");
+
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let significant: Vec<(&str, &TokenKind)> = result
+            .trace
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(&result.tokens)
+            .filter(|(_, token)| token.kind != TokenKind::Whitespace)
+            .map(|(reader, token)| (*reader, &token.kind))
+            .collect();
+        assert_eq!(significant, [
+            ("IdentifierReader", &TokenKind::Identifier),
+            ("OperatorReader", &TokenKind::Equals),
+            ("RawTextReader", &TokenKind::String),
+            ("OperatorReader", &TokenKind::Plus),
+            ("IdentifierReader", &TokenKind::Identifier),
+        ]);
+        assert_eq!(result.tokens[4].value, TokenValue::String("@€".to_string()));
+    }
+
+    #[test]
+    fn records_every_token_a_reader_returns() {
+        let result = default_lexer()
+            .insert_reader(0, PairReader)
+            .unwrap()
+            .with_trace(true)
+            .with_byte_spans(true)
+            .lex("é:ü x");
+
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let tokens: Vec<String> = result.tokens.iter().map(Token::summary).collect();
+        assert_eq!(tokens, ["Identifier(0..1, \"é\")", "Colon(1..2)", "Identifier(2..3, \"ü\")", "Whitespace(3..4)", "Identifier(4..5, \"x\")"]);
+        assert_eq!(result.trace.unwrap()[..4], ["PairReader", "PairReader", "PairReader", "WhitespaceReader"]);
+        assert_eq!(result.byte_spans.unwrap()[..3], [Span::new(0, 2), Span::new(2, 3), Span::new(3, 5)]);
+    }
+
+    #[test]
+    fn stops_at_the_token_limit_within_a_reader_result() {
+        let result = Lexer::new().add_reader(PairReader).with_max_tokens(2).lex("a:b");
+
+        assert_eq!(result.tokens.len(), 2);
+        assert_eq!(result.errors[0].message, "Too many tokens, the limit is 2");
+    }
+
+    #[test]
+    fn does_not_read_empty_input() {
+        let reads = Rc::new(Cell::new(0));
+        let result = Lexer::new().add_reader(CountingReader(reads.clone())).lex("");
+
+        assert!(result.tokens.is_empty());
+        assert!(result.errors.is_empty());
+        assert_eq!(reads.get(), 0);
+    }
+
+    #[test]
+    fn lexes_blank_and_comment_only_input_to_trivia() {
+        for source in ["", "   \n  ", "\t\r\n", "// comment", "/// doc\n// line\n"] {
+            let result = default_lexer().lex(source);
+
+            assert!(result.errors.is_empty(), "{source:?}: {:?}", result.errors);
+            assert!(result.tokens.iter().all(|token| token.kind.is_trivia()), "{source:?}: {:?}", result.tokens);
+            assert_eq!(result.tokens.last().map_or(0, |token| token.end), source.len(), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn skips_characters_which_no_reader_matches() {
+        assert_eq!(errors(Lexer::new(), "ab"), [
+            ("No reader matched the character 'a'".to_string(), 0),
+            ("No reader matched the character 'b'".to_string(), 1),
+        ]);
+    }
+
+    #[test]
+    fn lex_from_matches_lexing_the_suffix() {
+        let source = "x = \"héllo\"\ny = [1, 2.5] // ok";
+        let start = source.chars().position(|ch| ch == '\n').unwrap() + 1;
+        let suffix: String = source.chars().skip(start).collect();
+
+        let from = default_lexer().lex_from(source, start).tokens;
+        let mut shifted = default_lexer().lex(&suffix).tokens;
+        shifted.iter_mut().for_each(|token| {
+            token.start += start;
+            token.end += start;
+        });
+
+        assert!(!from.is_empty());
+        assert_eq!(from, shifted);
+
+        let whole = default_lexer().lex(source).tokens;
+        assert_eq!(from, whole.into_iter().filter(|token| token.start >= start).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn lex_from_tracks_byte_offsets_after_multibyte_chars() {
+        let result = default_lexer().with_byte_spans(true).lex_from("é = 1", 1);
+
+        assert_eq!(result.tokens[0].start, 1);
+        assert_eq!(result.byte_spans.unwrap()[0], Span::new(2, 3));
+    }
+
+    #[test]
+    fn records_byte_spans_of_tokens_after_multibyte_chars() {
+        let source = "é = \"😀\" + x";
+        let result = default_lexer().with_byte_spans(true).lex(source);
+        let byte_spans = result.byte_spans.as_ref().unwrap();
+
+        let significant: Vec<(usize, usize, Span)> = result
+            .tokens
+            .iter()
+            .zip(byte_spans)
+            .filter(|(token, _)| token.kind != TokenKind::Whitespace)
+            .map(|(token, span)| (token.start, token.end, *span))
+            .collect();
+        assert_eq!(significant, [(0, 1, Span::new(0, 2)), (2, 3, Span::new(3, 4)), (4, 7, Span::new(5, 11)), (8, 9, Span::new(12, 13)), (10, 11, Span::new(14, 15))]);
+
+        for ((_, text), span) in result.tokens_with_text(source).zip(byte_spans) {
+            assert_eq!(&source[span.start..span.end], text);
         }
 
-        result
+        assert_eq!(default_lexer().lex(source).byte_spans, None);
+    }
+
+    #[test]
+    fn lex_all_lexes_each_source_independently() {
+        let sources = ["x = 1", "é ''", "\"${y}\"\n$ echo"];
+        let mut lexer = default_lexer();
+        let results = lexer.lex_all(&sources);
+
+        assert_eq!(results.len(), 3);
+        for (source, result) in sources.iter().zip(&results) {
+            let alone = default_lexer().lex(source);
+
+            assert_eq!(result.tokens, alone.tokens, "{source:?}");
+            assert_eq!(format!("{:?}", result.errors), format!("{:?}", alone.errors), "{source:?}");
+            assert_eq!(result.tokens.first().map(|token| token.start), Some(0), "{source:?}");
+        }
+
+        assert!(results[0].is_ok());
+        assert!(results[1].has_errors());
+        assert!(results[2].is_ok());
+        assert_eq!(lexer.lex(sources[0]).tokens, results[0].tokens);
+    }
+
+    #[test]
+    fn lex_events_interleaves_errors_in_source_order() {
+        let source = "a '' b '' c";
+        let events: Vec<String> = default_lexer()
+            .lex_events(source)
+            .into_iter()
+            .map(|event| match event {
+                LexEvent::Token(token) => format!("{:?}@{}", token.kind, token.start),
+                LexEvent::Error(error) => format!("error@{}", error.position),
+            })
+            .collect();
+
+        assert_eq!(
+            events,
+            [
+                "Identifier@0",
+                "Whitespace@1",
+                "error@2",
+                "Whitespace@4",
+                "Identifier@5",
+                "Whitespace@6",
+                "error@7",
+                "Whitespace@9",
+                "Identifier@10",
+            ]
+        );
+
+        let result = default_lexer().lex(source);
+        assert_eq!(result.tokens.len(), 7);
+        assert_eq!(result.errors.len(), 2);
+    }
+
+    #[test]
+    fn lex_events_without_errors_are_the_tokens() {
+        let tokens = default_lexer().lex("x = 1").tokens;
+        let events = default_lexer().lex_events("x = 1");
+
+        assert_eq!(events.len(), tokens.len());
+        for (event, token) in events.iter().zip(&tokens) {
+            assert!(matches!(event, LexEvent::Token(event_token) if event_token == token));
+        }
+        assert!(default_lexer().lex_events("").is_empty());
+    }
+
+    #[test]
+    fn lex_from_the_end_is_empty() {
+        let result = default_lexer().lex_from("a b", 3);
+
+        assert!(result.tokens.is_empty());
+        assert!(result.errors.is_empty());
+    }
+
+    #[test]
+    fn appended_readers_lose_to_identifier_reader() {
+        let lexer = default_lexer().add_reader(UnlessReader);
+
+        assert_eq!(first_kind(lexer, "unless x"), TokenKind::Identifier);
+    }
+
+    #[test]
+    fn add_reader_before_wins_over_identifier_reader() {
+        let lexer = default_lexer().add_reader_before("IdentifierReader", UnlessReader).unwrap();
+
+        assert_eq!(first_kind(lexer, "unless x"), TokenKind::Reserved);
+    }
+
+    #[test]
+    fn insert_reader_wins_over_later_readers() {
+        let lexer = || default_lexer().insert_reader(0, UnlessReader).unwrap();
+
+        assert_eq!(first_kind(lexer(), "unless x"), TokenKind::Reserved);
+        assert_eq!(first_kind(lexer(), "unlessx"), TokenKind::Identifier);
+    }
+
+    #[test]
+    fn positioning_relative_to_a_missing_reader_fails() {
+        let result = default_lexer().add_reader_before("MissingReader", UnlessReader);
+
+        assert_eq!(result.err(), Some(ReaderPositionError::UnknownReader("MissingReader".to_string())));
+    }
+
+    #[test]
+    fn inserting_out_of_bounds_fails() {
+        let result = Lexer::new().add_reader(UnlessReader).insert_reader(2, UnlessReader);
+
+        assert_eq!(result.err(), Some(ReaderPositionError::IndexOutOfBounds { index: 2, len: 1 }));
+    }
+
+    #[test]
+    fn lexes_large_sources_in_linear_time() {
+        let line = "let x = foo(1, \"str ${a}\") + 2.5 // comment\n$ echo ${b} hi\n";
+        let source = line.repeat(500_000 / line.len());
+
+        let start = Instant::now();
+        let result = default_lexer().lex(&source);
+
+        assert!(result.errors.is_empty());
+        assert_eq!(result.tokens.last().map(|token| token.end), Some(source.chars().count()));
+        // Copying the source per reader attempt took minutes at this size
+        assert!(start.elapsed() < Duration::from_secs(10), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn lexes_long_tokens() {
+        let source = format!("$ {}", "${a} ".repeat(20_000));
+        let result = default_lexer().lex(&source);
+
+        assert!(result.errors.is_empty());
+        assert_eq!(result.tokens.len(), 1);
     }
 }
@@ -1,7 +1,95 @@
+use common::span::Span;
+
 use crate::{reader_error, token};
 
 #[derive(Debug)]
 pub struct LexerResult {
     pub tokens: Vec<token::Token>,
     pub errors: Vec<reader_error::ReaderError>,
+    /// Names of the readers which produced each token, in the same order as `tokens`. Only
+    /// populated when tracing is enabled with `Lexer::with_trace`.
+    pub trace: Option<Vec<&'static str>>,
+    /// Byte offsets of each token in the source, in the same order as `tokens`. Only populated when
+    /// enabled with `Lexer::with_byte_spans`.
+    pub byte_spans: Option<Vec<Span>>,
+}
+
+impl LexerResult {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// Pairs each token with its text in the source which was lexed. Token positions are char
+    /// offsets, so they're converted to byte offsets before slicing.
+    pub fn tokens_with_text<'a>(&'a self, source: &'a str) -> impl Iterator<Item = (&'a token::Token, &'a str)> {
+        let byte_offsets: Vec<usize> = source
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(std::iter::once(source.len()))
+            .collect();
+
+        self.tokens.iter().map(move |token| (token, &source[byte_offsets[token.start]..byte_offsets[token.end]]))
+    }
+
+    /// The tokens if lexing succeeded, otherwise the errors.
+    pub fn into_result(self) -> Result<Vec<token::Token>, Vec<reader_error::ReaderError>> {
+        match self.errors.is_empty() {
+            true => Ok(self.tokens),
+            false => Err(self.errors),
+        }
+    }
+}
+
+/// A token or an error, see `Lexer::lex_events`.
+#[derive(Debug)]
+pub enum LexEvent {
+    Token(token::Token),
+    Error(reader_error::ReaderError),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{default_lexer, token::TokenKind};
+
+    #[test]
+    fn clean_lex_is_ok() {
+        let result = default_lexer().lex("x = 1");
+
+        assert!(result.is_ok());
+        assert!(!result.has_errors());
+
+        let tokens = result.into_result().expect("lexing should succeed");
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[0].kind, TokenKind::Identifier);
+    }
+
+    #[test]
+    fn slices_token_text_from_multibyte_sources() {
+        let source = "café = \"🎉 ü\" + naïve // ✓";
+        let result = default_lexer().lex(source);
+
+        let texts: Vec<&str> = result
+            .tokens_with_text(source)
+            .filter(|(token, _)| token.kind != TokenKind::Whitespace)
+            .map(|(_, text)| text)
+            .collect();
+
+        assert_eq!(texts, ["café", "=", "\"🎉 ü\"", "+", "naïve", "// ✓"]);
+    }
+
+    #[test]
+    fn erroring_lex_is_not_ok() {
+        let result = default_lexer().lex("x = ''");
+
+        assert!(!result.is_ok());
+        assert!(result.has_errors());
+
+        let errors = result.into_result().expect_err("lexing should fail");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position, 4);
+    }
 }
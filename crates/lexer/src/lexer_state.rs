@@ -1,12 +1,28 @@
+use std::rc::Rc;
+
 #[derive(Debug, Clone)]
 pub struct LexerState {
-  pub chars: Vec<char>,
+  /// Shared with every `ReaderState`, so trying a reader doesn't copy the source.
+  pub chars: Rc<[char]>,
   pub length: usize,
   pub position: usize,
+  /// Byte offset of `position` in the source.
+  pub byte_position: usize,
 }
 
 impl LexerState {
   pub fn at_end(&self) -> bool {
     self.position >= self.length
   }
+
+  /// Byte offset of the given char position, which must be at or after the current one.
+  pub fn byte_offset(&self, position: usize) -> usize {
+    self.byte_position + self.chars[self.position..position].iter().map(|ch| ch.len_utf8()).sum::<usize>()
+  }
+
+  /// Moves to the given char position, which must be at or after the current one.
+  pub fn advance_to(&mut self, position: usize) {
+    self.byte_position = self.byte_offset(position);
+    self.position = position;
+  }
 }
\ No newline at end of file
@@ -1,19 +1,24 @@
+pub mod escape;
 pub mod lexer;
 pub mod lexer_result;
 pub mod lexer_state;
 pub mod reader;
 pub mod reader_error;
+pub mod reader_position_error;
 pub mod reader_result;
 pub mod reader_state;
 pub mod token;
+pub mod token_stream;
+pub mod token_stream_error;
 
-use common::error::format_error_message_inline;
+use common::{source_map::is_line_break, span::Span};
+use escape::read_escape;
 use lexer::Lexer;
 use reader::Reader;
 use reader_error::ReaderError;
 use reader_result::ReaderResult;
 use reader_state::ReaderState;
-use token::{Token, TokenKind, TokenValue};
+use token::{CommandValue, Interpolation, TemplateSegment, Token, TokenKind, TokenValue};
 use unicode_id_start::{is_id_continue, is_id_start};
 
 /// Reads one character from the state and asserts that it is equal to the given character when
@@ -25,7 +30,8 @@ use unicode_id_start::{is_id_continue, is_id_start};
 /// ```
 macro_rules! read_char {
     ($state:ident, $char:expr) => {{
-        debug_assert!($state.read() == Some(&$char));
+        let _read = $state.read().copied();
+        debug_assert!(_read == Some($char));
         $char
     }};
 }
@@ -43,11 +49,43 @@ macro_rules! peek_char {
     };
 }
 
+/// Reads a `#!` line at the start of the source, so that scripts can be run directly. A `#`
+/// anywhere else is an unexpected character.
+struct ShebangReader;
+
+impl Reader for ShebangReader {
+    fn name(&self) -> &'static str {
+        "ShebangReader"
+    }
+
+    fn read(&self, state: &mut ReaderState) -> ReaderResult {
+        if state.get_start() != 0 || state.read_str("#!").is_none() {
+            return ReaderResult::None;
+        }
+
+        let mut value = String::new();
+
+        while let Some(&ch) = state.peek() {
+            if is_line_break(ch) {
+                break;
+            }
+            value.push(read_char!(state, ch));
+        }
+
+        return ReaderResult::Token(Token {
+            kind: TokenKind::Shebang,
+            start: state.get_start(),
+            end: state.get_position(),
+            value: TokenValue::String(value),
+        });
+    }
+}
+
 struct CommentReader;
 
 impl Reader for CommentReader {
-    fn name(&self) -> String {
-        "CommentReader".to_string()
+    fn name(&self) -> &'static str {
+        "CommentReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
@@ -55,19 +93,37 @@ impl Reader for CommentReader {
             return ReaderResult::None;
         }
 
-        state.consume_whitespace();
+        // As in Rust, `////` and beyond is an ordinary comment
+        let kind = match state.peek_str("/") && !state.peek_str("//") {
+            true => TokenKind::DocComment,
+            false => TokenKind::Comment,
+        };
+
+        if kind == TokenKind::DocComment {
+            state.read();
+
+            // Only the space separating the text from the slashes is dropped, so that indentation
+            // within the doc text is kept
+            if matches!(state.peek(), Some(' ')) {
+                state.read();
+            }
+        } else {
+            while matches!(state.peek(), Some(&char) if char.is_whitespace() && !is_line_break(char)) {
+                state.read();
+            }
+        }
 
         let mut value = String::new();
 
-        while let Some(ch) = state.clone().peek() {
-            if ch == &'\n' {
+        while let Some(&ch) = state.peek() {
+            if is_line_break(ch) {
                 break;
             }
-            value.push(read_char!(state, *ch));
+            value.push(read_char!(state, ch));
         }
         
         return ReaderResult::Token(Token {
-            kind: TokenKind::Comment,
+            kind,
             start: state.get_start(),
             end: state.get_position(),
             value: TokenValue::String(value),
@@ -75,36 +131,79 @@ impl Reader for CommentReader {
     }
 }
 
-struct KeywordReader;
+/// The keywords of the language and the kinds of token they are read as.
+pub const DEFAULT_KEYWORDS: &[(&str, TokenKind)] = &[
+    ("if", TokenKind::If),
+    ("else", TokenKind::Else),
+    ("for", TokenKind::For),
+    ("in", TokenKind::In),
+    ("while", TokenKind::While),
+    ("loop", TokenKind::Loop),
+    ("break", TokenKind::Break),
+    ("continue", TokenKind::Continue),
+    ("return", TokenKind::Return),
+    ("fn", TokenKind::Function),
+    ("match", TokenKind::Match),
+];
+
+/// Reads keywords, which must be whole words so that e.g. `iffy` is left to the identifier reader.
+pub struct KeywordReader {
+    keywords: Vec<(&'static str, TokenKind)>,
+}
+
+impl KeywordReader {
+    /// A reader for the default keywords.
+    pub fn new() -> Self {
+        Self::with_keywords(DEFAULT_KEYWORDS.iter().cloned())
+    }
+
+    /// A reader for exactly the given keywords, in place of the defaults.
+    pub fn with_keywords(keywords: impl IntoIterator<Item = (&'static str, TokenKind)>) -> Self {
+        Self {
+            keywords: keywords.into_iter().collect(),
+        }
+    }
+
+    pub fn add_keyword(mut self, keyword: &'static str, kind: TokenKind) -> Self {
+        self.keywords.push((keyword, kind));
+
+        self
+    }
+
+    /// Reserves a keyword so that it can't be used as an identifier. It's read as a token of kind
+    /// `Reserved`, which the parser rejects.
+    pub fn reserve(self, keyword: &'static str) -> Self {
+        self.add_keyword(keyword, TokenKind::Reserved)
+    }
+}
+
+impl Default for KeywordReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Reader for KeywordReader {
-    fn name(&self) -> String {
-        "KeywordReader".to_string()
+    fn name(&self) -> &'static str {
+        "KeywordReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
-        macro_rules! read_keyword {
-            ($state:ident, $keyword:expr, $kind:expr) => {{
-                if let Some(_) = $state.read_str($keyword) {
-                    return ReaderResult::Token(Token {
-                        kind: $kind,
-                        start: $state.get_start(),
-                        end: $state.get_position(),
-                        value: TokenValue::None,
-                    });
-                }
-            }};
-        }
+        for (keyword, kind) in &self.keywords {
+            if state.read_word(keyword).is_some() {
+                let value = match kind {
+                    TokenKind::Reserved => TokenValue::String(keyword.to_string()),
+                    _ => TokenValue::None,
+                };
 
-        read_keyword!(state, "if", TokenKind::If);
-        read_keyword!(state, "else", TokenKind::Else);
-        read_keyword!(state, "for", TokenKind::For);
-        read_keyword!(state, "while", TokenKind::While);
-        read_keyword!(state, "loop", TokenKind::Loop);
-        read_keyword!(state, "break", TokenKind::Break);
-        read_keyword!(state, "continue", TokenKind::Continue);
-        read_keyword!(state, "return", TokenKind::Return);
-        read_keyword!(state, "fn", TokenKind::Function);
+                return ReaderResult::Token(Token {
+                    kind: kind.clone(),
+                    start: state.get_start(),
+                    end: state.get_position(),
+                    value,
+                });
+            }
+        }
 
         return ReaderResult::None;
     }
@@ -113,12 +212,12 @@ impl Reader for KeywordReader {
 struct BooleanReader;
 
 impl Reader for BooleanReader {
-    fn name(&self) -> String {
-        "BooleanReader".to_string()
+    fn name(&self) -> &'static str {
+        "BooleanReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
-        if let Some(_) = state.read_str("true") {
+        if let Some(_) = state.read_word("true") {
             return ReaderResult::Token(Token {
                 kind: TokenKind::Boolean,
                 start: state.get_start(),
@@ -127,7 +226,7 @@ impl Reader for BooleanReader {
             });
         }
 
-        if let Some(_) = state.read_str("false") {
+        if let Some(_) = state.read_word("false") {
             return ReaderResult::Token(Token {
                 kind: TokenKind::Boolean,
                 start: state.get_start(),
@@ -143,8 +242,8 @@ impl Reader for BooleanReader {
 struct IdentifierReader;
 
 impl Reader for IdentifierReader {
-    fn name(&self) -> String {
-        "IdentifierReader".to_string()
+    fn name(&self) -> &'static str {
+        "IdentifierReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
@@ -154,13 +253,13 @@ impl Reader for IdentifierReader {
         // Unicode Standard Annex #31: Unicode Identifier and Pattern Syntax
         // See https://www.unicode.org/reports/tr31/
         if matches!(state.peek(), Some(char) if is_id_start(*char)) {
-            value += &state.read().unwrap().to_string();
+            value.push(*state.read().unwrap());
         } else {
             return ReaderResult::None;
         }
 
         while matches!(state.peek(), Some(char) if is_id_continue(*char)) {
-            value += &state.read().unwrap().to_string();
+            value.push(*state.read().unwrap());
         }
 
         return ReaderResult::Token(Token {
@@ -172,19 +271,27 @@ impl Reader for IdentifierReader {
     }
 }
 
+/// Reads integers and floats. Numbers always start with a digit: `.5` is a `.` followed by `5`, as
+/// `.` is reserved for member access, e.g. `a.b`.
 struct NumberReader;
 
 impl Reader for NumberReader {
-    fn name(&self) -> String {
-        "NumberReader".to_string()
+    fn name(&self) -> &'static str {
+        "NumberReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
         let mut value = String::new();
+        let mut is_float = false;
 
-        // Read all numeric characters
-        while matches!(state.peek(), Some(char) if char.is_numeric()) {
-            value += &state.read().unwrap().to_string();
+        if let Some(radix) = self.peek_radix(state) {
+            return self.read_integer_with_radix(state, radix);
+        }
+
+        // Read all numeric characters. Leading zeros are allowed and don't change the base, so
+        // `007` is 7.
+        while matches!(state.peek(), Some(char) if char.is_ascii_digit()) {
+            value.push(*state.read().unwrap());
         }
 
         // There must be at least one numeric character before a dot
@@ -192,21 +299,106 @@ impl Reader for NumberReader {
             return ReaderResult::None;
         }
 
-        // Check if the next character is a dot
-        if matches!(state.peek(), Some('.')) {
-            value += &state.read().unwrap().to_string();
+        // Check if the next character is a dot, other than the start of a range such as `0..5`
+        if matches!(state.peek(), Some('.')) && !matches!(state.peek_nth(1), Some('.')) {
+            is_float = true;
+            value.push(*state.read().unwrap());
 
             // Read all numeric characters after the dot
-            while matches!(state.peek(), Some(char) if char.is_numeric()) {
-                value += &state.read().unwrap().to_string();
+            while matches!(state.peek(), Some(char) if char.is_ascii_digit()) {
+                value.push(*state.read().unwrap());
+            }
+        }
+
+        // Check for an exponent, which must be followed by at least one digit (e.g. 1e3, 1E-3)
+        let exponent_digit_offset = match state.peek_nth(1) {
+            Some('+') | Some('-') => 2,
+            _ => 1,
+        };
+        if matches!(state.peek(), Some('e') | Some('E'))
+            && matches!(state.peek_nth(exponent_digit_offset), Some(char) if char.is_ascii_digit())
+        {
+            is_float = true;
+
+            for _ in 0..exponent_digit_offset {
+                value.push(*state.read().unwrap());
+            }
+
+            while matches!(state.peek(), Some(char) if char.is_ascii_digit()) {
+                value.push(*state.read().unwrap());
             }
         }
 
+        // Integers which overflow an i64 fall back to a float
+        let value = match is_float {
+            false => match value.parse() {
+                Ok(integer) => TokenValue::Integer { value: integer, raw: value },
+                Err(_) => TokenValue::Number { value: value.parse().unwrap(), raw: value },
+            },
+            true => TokenValue::Number { value: value.parse().unwrap(), raw: value },
+        };
+
+        return ReaderResult::Token(Token {
+            kind: TokenKind::Number,
+            start: state.get_start(),
+            end: state.get_position(),
+            value,
+        });
+    }
+}
+
+impl NumberReader {
+    /// The base of an integer written with a prefix, i.e. `0x` for hexadecimal, `0o` for octal and
+    /// `0b` for binary.
+    fn peek_radix(&self, state: &ReaderState) -> Option<u32> {
+        if !peek_char!(state, '0') {
+            return None;
+        }
+
+        match state.peek_nth(1) {
+            Some('x') => Some(16),
+            Some('o') => Some(8),
+            Some('b') => Some(2),
+            _ => None,
+        }
+    }
+
+    fn read_integer_with_radix(&self, state: &mut ReaderState, radix: u32) -> ReaderResult {
+        let mut raw = String::new();
+
+        raw.push(read_char!(state, '0'));
+        raw.push(*state.read().unwrap());
+
+        // Everything which could continue the literal is read, so that e.g. the `2` in `0b12` is
+        // reported rather than lexed as a separate number
+        let digits_start = raw.len();
+
+        while matches!(state.peek(), Some(char) if is_id_continue(*char)) {
+            raw.push(*state.read().unwrap());
+        }
+
+        let digits = &raw[digits_start..];
+        let span = Span::new(state.get_start(), state.get_position());
+        let error = |message: String| ReaderResult::Err(ReaderError::new(message, span.start).with_span(span));
+
+        if digits.is_empty() {
+            return error(format!("Expected digits after '{}'", raw));
+        }
+
+        if let Some(invalid) = digits.chars().find(|char| !char.is_digit(radix)) {
+            return error(format!("Invalid digit '{}' in base {} literal '{}'", invalid, radix, raw));
+        }
+
+        let value = match i64::from_str_radix(digits, radix) {
+            Ok(value) => value,
+            Err(_) => return error(format!("Integer literal '{}' is too large", raw)),
+        };
+
         return ReaderResult::Token(Token {
             kind: TokenKind::Number,
             start: state.get_start(),
             end: state.get_position(),
-            value: TokenValue::Number(value.parse().unwrap()),
+            value: TokenValue::Integer { value, raw },
         });
     }
 }
@@ -214,12 +406,15 @@ impl Reader for NumberReader {
 struct StringReader;
 
 impl Reader for StringReader {
-    fn name(&self) -> String {
-        "StringReader".to_string()
+    fn name(&self) -> &'static str {
+        "StringReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
+        let mut segments = vec![];
         let mut value = String::new();
+        let mut escape_error = None;
+        let mut terminated = false;
 
         // Check if the first character is a double quote
         if !peek_char!(state, '"') {
@@ -229,188 +424,345 @@ impl Reader for StringReader {
         read_char!(state, '"');
 
         // Read all characters until the next unescaped double quote
-        while let Some(char) = state.clone().peek() {
-            read_char!(state, *char);
-            match char {
-                '\\' if peek_char!(state, '"') => {
-                    value.push(read_char!(state, '"'));
-                },
-                '"' => break,
-                _ => value.push(*char),
+        while let Some(&char) = state.peek() {
+            // The rest of the string is still read after an invalid escape, so that lexing carries
+            // on after it
+            if char == '\\' {
+                match read_escape(state) {
+                    Ok(char) => value.push(char),
+                    Err(error) => {
+                        escape_error.get_or_insert(error);
+                    }
+                }
+
+                continue;
             }
-        }
 
-        return ReaderResult::Token(Token {
-            kind: TokenKind::String,
-            start: state.get_start(),
-            end: state.get_position(),
-            value: TokenValue::String(value),
-        });
-    }
-}
+            // Interpolated expression, e.g. "${a + b}"
+            if state.peek_str("${") {
+                let position = state.get_position();
 
-struct OperatorReader;
+                read_char!(state, '$');
+                read_char!(state, '{');
 
-impl OperatorReader {
-    fn get_readers_result(&self, kind: TokenKind, state: &mut ReaderState) -> ReaderResult {
-        state.read();
+                let start = state.get_position();
 
-        return ReaderResult::Token(Token {
-            kind,
-            start: state.get_start(),
-            end: state.get_position(),
-            value: TokenValue::None,
-        });
-    }
+                let source = match read_interpolation_body(state) {
+                    Some(source) => source,
+                    None => {
+                        return ReaderResult::Err(
+                            ReaderError::new("Unterminated string interpolation", position)
+                                .with_span(Span::new(position, start))
+                                .with_label("interpolation is never closed"),
+                        )
+                    }
+                };
 
-    fn read_equals(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, '=');
+                let end = state.get_position();
 
-        if peek_char!(state, '=') {
-            state.read();
+                read_char!(state, '}');
+
+                segments.push(TemplateSegment::Literal(std::mem::take(&mut value)));
+                segments.push(TemplateSegment::Interpolation(Interpolation { start, end, source }));
 
+                continue;
+            }
+
+            // Interpolated identifier, e.g. "$name"
+            if char == '$' && matches!(state.peek_nth(1), Some(char) if is_id_start(*char)) {
+                read_char!(state, '$');
+
+                let start = state.get_position();
+                let mut source = String::new();
+
+                while matches!(state.peek(), Some(char) if is_id_continue(*char)) {
+                    source.push(*state.read().unwrap());
+                }
+
+                let end = state.get_position();
+
+                segments.push(TemplateSegment::Literal(std::mem::take(&mut value)));
+                segments.push(TemplateSegment::Interpolation(Interpolation { start, end, source }));
+
+                continue;
+            }
+
+            read_char!(state, char);
+
+            if char == '"' {
+                terminated = true;
+                break;
+            }
+
+            value.push(char);
+        }
+
+        if !terminated {
+            return ReaderResult::Err(
+                ReaderError::new("Unterminated string", state.get_start())
+                    .with_span(Span::new(state.get_start(), state.get_position()))
+                    .with_label("no closing quote"),
+            );
+        }
+
+        if let Some(error) = escape_error {
+            return ReaderResult::Err(error);
+        }
+
+        if segments.is_empty() {
             return ReaderResult::Token(Token {
-                kind: TokenKind::EqualsEquals,
+                kind: TokenKind::String,
                 start: state.get_start(),
                 end: state.get_position(),
-                value: TokenValue::None,
+                value: TokenValue::String(value),
             });
         }
 
+        segments.push(TemplateSegment::Literal(value));
+
+        // Drop the empty literals left between adjacent interpolations
+        segments.retain(|segment| {
+            !matches!(segment, TemplateSegment::Literal(literal) if literal.is_empty())
+        });
+
         return ReaderResult::Token(Token {
-            kind: TokenKind::Equals,
+            kind: TokenKind::TemplateString,
             start: state.get_start(),
             end: state.get_position(),
-            value: TokenValue::None,
+            value: TokenValue::Template(segments),
         });
     }
+}
 
-    fn read_minus(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, '-');
+/// Reads a loop label, e.g. `'outer`. A `'` directly after the name makes it a char literal
+/// instead, e.g. `'a'`, which is left to the CharReader.
+struct LabelReader;
 
-        if peek_char!(state, '=') {
-            state.read();
+impl Reader for LabelReader {
+    fn name(&self) -> &'static str {
+        "LabelReader"
+    }
 
-            return ReaderResult::Token(Token {
-                kind: TokenKind::MinusEquals,
-                start: state.get_start(),
-                end: state.get_position(),
-                value: TokenValue::None,
-            });
+    fn read(&self, state: &mut ReaderState) -> ReaderResult {
+        if !peek_char!(state, '\'') || !matches!(state.peek_nth(1), Some(char) if is_id_start(*char)) {
+            return ReaderResult::None;
         }
 
-        return ReaderResult::Token(Token {
-            kind: TokenKind::Minus,
-            start: state.get_start(),
-            end: state.get_position(),
-            value: TokenValue::None,
-        });
-    }
+        read_char!(state, '\'');
 
-    fn read_star(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, '*');
+        let mut name = String::new();
 
-        if peek_char!(state, '=') {
-            state.read();
+        while matches!(state.peek(), Some(char) if is_id_continue(*char)) {
+            name.push(*state.read().unwrap());
+        }
 
-            return ReaderResult::Token(Token {
-                kind: TokenKind::StarEquals,
-                start: state.get_start(),
-                end: state.get_position(),
-                value: TokenValue::None,
-            });
+        if peek_char!(state, '\'') {
+            return ReaderResult::None;
         }
 
         return ReaderResult::Token(Token {
-            kind: TokenKind::Star,
+            kind: TokenKind::Label,
             start: state.get_start(),
             end: state.get_position(),
-            value: TokenValue::None,
+            value: TokenValue::String(name),
         });
     }
+}
 
-    fn read_slash(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, '/');
+/// Reads a char literal, e.g. `'a'` or `'\n'`.
+struct CharReader;
 
-        if peek_char!(state, '=') {
-            state.read();
+impl Reader for CharReader {
+    fn name(&self) -> &'static str {
+        "CharReader"
+    }
 
-            return ReaderResult::Token(Token {
-                kind: TokenKind::SlashEquals,
-                start: state.get_start(),
-                end: state.get_position(),
-                value: TokenValue::None,
-            });
+    fn read(&self, state: &mut ReaderState) -> ReaderResult {
+        if !peek_char!(state, '\'') {
+            return ReaderResult::None;
+        }
+
+        read_char!(state, '\'');
+
+        let value = match state.peek() {
+            Some('\\') => match read_escape(state) {
+                Ok(char) => char,
+                Err(error) => {
+                    // Skip the closing quote so it isn't read as the start of another char literal
+                    state.read_str("'");
+
+                    return ReaderResult::Err(error);
+                }
+            },
+            Some('\'') => {
+                state.read();
+
+                return ReaderResult::Err(
+                    ReaderError::new("Empty char literal", state.get_start())
+                        .with_span(Span::new(state.get_start(), state.get_position())),
+                );
+            }
+            Some(&char) if !is_line_break(char) => read_char!(state, char),
+            _ => {
+                return ReaderResult::Err(
+                    ReaderError::new("Unterminated char literal", state.get_start())
+                        .with_span(Span::new(state.get_start(), state.get_position())),
+                )
+            }
+        };
+
+        if state.read_str("'").is_none() {
+            // Skip past the closing quote of e.g. `'ab'` so that lexing carries on after it
+            let mut offset = 0;
+
+            while let Some(&char) = state.peek_nth(offset) {
+                if is_line_break(char) {
+                    break;
+                }
+
+                if char == '\'' {
+                    for _ in 0..=offset {
+                        state.read();
+                    }
+
+                    return ReaderResult::Err(
+                        ReaderError::new("Char literals must contain exactly one character", state.get_start())
+                            .with_span(Span::new(state.get_start(), state.get_position())),
+                    );
+                }
+
+                offset += 1;
+            }
+
+            return ReaderResult::Err(
+                ReaderError::new("Unterminated char literal", state.get_start())
+                    .with_span(Span::new(state.get_start(), state.get_position()))
+                    .with_label("expected a closing '"),
+            );
         }
 
         return ReaderResult::Token(Token {
-            kind: TokenKind::Slash,
+            kind: TokenKind::Char,
             start: state.get_start(),
             end: state.get_position(),
-            value: TokenValue::None,
+            value: TokenValue::Char(value),
         });
     }
+}
 
-    fn read_plus(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, '+');
+/// Reads the body of a `${...}` interpolation up to, but not including, the closing brace. Braces
+/// and strings nested inside the expression are skipped over so they don't end it early. Returns
+/// None if the source ends before the closing brace.
+fn read_interpolation_body(state: &mut ReaderState) -> Option<String> {
+    let mut body = String::new();
+    // Tracked with an explicit stack rather than recursion so that deeply nested strings
+    // can't overflow the stack
+    let mut scopes = vec![InterpolationScope::Interpolation(0)];
 
-        if peek_char!(state, '=') {
-            state.read();
+    loop {
+        let char = *state.peek()?;
 
-            return ReaderResult::Token(Token {
-                kind: TokenKind::PlusEquals,
-                start: state.get_start(),
-                end: state.get_position(),
-                value: TokenValue::None,
-            });
+        match scopes.last_mut()? {
+            InterpolationScope::Interpolation(0) if char == '}' => {
+                scopes.pop();
+
+                if scopes.is_empty() {
+                    return Some(body);
+                }
+            }
+            InterpolationScope::Interpolation(depth) => match char {
+                '{' => *depth += 1,
+                '}' => *depth -= 1,
+                '"' => scopes.push(InterpolationScope::String),
+                _ => {}
+            },
+            InterpolationScope::String => {
+                if state.peek_str("${") {
+                    body.push(read_char!(state, '$'));
+                    body.push(read_char!(state, '{'));
+                    scopes.push(InterpolationScope::Interpolation(0));
+                    continue;
+                }
+
+                match char {
+                    '\\' => {
+                        body.push(read_char!(state, '\\'));
+                        body.push(*state.read()?);
+                        continue;
+                    }
+                    '"' => {
+                        scopes.pop();
+                    }
+                    _ => {}
+                }
+            }
         }
 
+        body.push(read_char!(state, char));
+    }
+}
+
+/// What an interpolation body is currently nested in, along with the brace depth for interpolations.
+enum InterpolationScope {
+    Interpolation(usize),
+    String,
+}
+
+struct OperatorReader;
+
+impl OperatorReader {
+    fn get_readers_result(&self, kind: TokenKind, state: &mut ReaderState) -> ReaderResult {
+        state.read();
+
         return ReaderResult::Token(Token {
-            kind: TokenKind::Plus,
+            kind,
             start: state.get_start(),
             end: state.get_position(),
             value: TokenValue::None,
         });
     }
 
-    fn read_percent(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, '%');
+    /// Reads a single character operator, or its variant suffixed with `=` if the next character is
+    /// `=` (e.g. `+` or `+=`).
+    fn read_simple_or_equals(
+        &self,
+        base: TokenKind,
+        with_equals: TokenKind,
+        state: &mut ReaderState,
+    ) -> ReaderResult {
+        state.read();
 
-        if peek_char!(state, '=') {
+        let kind = if peek_char!(state, '=') {
             state.read();
-
-            return ReaderResult::Token(Token {
-                kind: TokenKind::PercentEquals,
-                start: state.get_start(),
-                end: state.get_position(),
-                value: TokenValue::None,
-            });
-        }
+            with_equals
+        } else {
+            base
+        };
 
         return ReaderResult::Token(Token {
-            kind: TokenKind::Percent,
+            kind,
             start: state.get_start(),
             end: state.get_position(),
             value: TokenValue::None,
         });
     }
 
-    fn read_caret(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, '^');
+    fn read_equals(&self, state: &mut ReaderState) -> ReaderResult {
+        read_char!(state, '=');
 
-        if peek_char!(state, '=') {
-            state.read();
+        // `==` and `=>` are distinct two character operators, anything else leaves a lone `=`
+        let kind = match state.peek() {
+            Some('=') => TokenKind::EqualsEquals,
+            Some('>') => TokenKind::FatArrow,
+            _ => TokenKind::Equals,
+        };
 
-            return ReaderResult::Token(Token {
-                kind: TokenKind::CaretEquals,
-                start: state.get_start(),
-                end: state.get_position(),
-                value: TokenValue::None,
-            });
+        if kind != TokenKind::Equals {
+            state.read();
         }
 
         return ReaderResult::Token(Token {
-            kind: TokenKind::Caret,
+            kind,
             start: state.get_start(),
             end: state.get_position(),
             value: TokenValue::None,
@@ -424,12 +776,24 @@ impl OperatorReader {
             Some('&') => {
                 state.read();
 
-                return ReaderResult::Token(Token {
-                    kind: TokenKind::AmpersandAmpersand,
-                    start: state.get_start(),
-                    end: state.get_position(),
-                    value: TokenValue::None,
-                });
+                return match state.peek() {
+                    Some('=') => {
+                        state.read();
+
+                        ReaderResult::Token(Token {
+                            kind: TokenKind::AmpersandAmpersandEquals,
+                            start: state.get_start(),
+                            end: state.get_position(),
+                            value: TokenValue::None,
+                        })
+                    }
+                    _ => ReaderResult::Token(Token {
+                        kind: TokenKind::AmpersandAmpersand,
+                        start: state.get_start(),
+                        end: state.get_position(),
+                        value: TokenValue::None,
+                    }),
+                };
             }
             Some('=') => {
                 state.read();
@@ -499,28 +863,6 @@ impl OperatorReader {
         }
     }
 
-    fn read_bang(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, '!');
-
-        if peek_char!(state, '=') {
-            state.read();
-
-            return ReaderResult::Token(Token {
-                kind: TokenKind::BangEquals,
-                start: state.get_start(),
-                end: state.get_position(),
-                value: TokenValue::None,
-            });
-        }
-
-        return ReaderResult::Token(Token {
-            kind: TokenKind::Bang,
-            start: state.get_start(),
-            end: state.get_position(),
-            value: TokenValue::None,
-        });
-    }
-
     fn read_less_than(&self, state: &mut ReaderState) -> ReaderResult {
         read_char!(state, '<');
 
@@ -576,6 +918,25 @@ impl OperatorReader {
                 state.read();
 
                 return match state.peek() {
+                    Some('>') => {
+                        state.read();
+
+                        let kind = match state.peek() {
+                            Some('=') => {
+                                state.read();
+
+                                TokenKind::GreaterThanGreaterThanGreaterThanEquals
+                            }
+                            _ => TokenKind::GreaterThanGreaterThanGreaterThan,
+                        };
+
+                        ReaderResult::Token(Token {
+                            kind,
+                            start: state.get_start(),
+                            end: state.get_position(),
+                            value: TokenValue::None,
+                        })
+                    }
                     Some('=') => {
                         state.read();
 
@@ -639,55 +1000,31 @@ impl OperatorReader {
             }
         }
     }
-
-    fn read_colon(&self, state: &mut ReaderState) -> ReaderResult {
-        read_char!(state, ':');
-
-        match state.peek() {
-            Some('=') => {
-                state.read();
-
-                return ReaderResult::Token(Token {
-                    kind: TokenKind::ColonEquals,
-                    start: state.get_start(),
-                    end: state.get_position(),
-                    value: TokenValue::None,
-                });
-            }
-            _ => {
-                return ReaderResult::Token(Token {
-                    kind: TokenKind::Colon,
-                    start: state.get_start(),
-                    end: state.get_position(),
-                    value: TokenValue::None,
-                });
-            }
-        }
-    }
 }
 
 impl Reader for OperatorReader {
-    fn name(&self) -> String {
-        "OperatorReader".to_string()
+    fn name(&self) -> &'static str {
+        "OperatorReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
         match state.peek().unwrap() {
             '=' => self.read_equals(state),
-            '-' => self.read_minus(state),
-            '*' => self.read_star(state),
-            '/' => self.read_slash(state),
-            '+' => self.read_plus(state),
-            '%' => self.read_percent(state),
-            '^' => self.read_caret(state),
+            '-' => self.read_simple_or_equals(TokenKind::Minus, TokenKind::MinusEquals, state),
+            '*' => self.read_simple_or_equals(TokenKind::Star, TokenKind::StarEquals, state),
+            '/' => self.read_simple_or_equals(TokenKind::Slash, TokenKind::SlashEquals, state),
+            '+' => self.read_simple_or_equals(TokenKind::Plus, TokenKind::PlusEquals, state),
+            '%' => self.read_simple_or_equals(TokenKind::Percent, TokenKind::PercentEquals, state),
+            '^' => self.read_simple_or_equals(TokenKind::Caret, TokenKind::CaretEquals, state),
             '&' => self.read_ampersand(state),
             '|' => self.read_pipe(state),
-            '!' => self.read_bang(state),
+            '!' => self.read_simple_or_equals(TokenKind::Bang, TokenKind::BangEquals, state),
             '<' => self.read_less_than(state),
             '>' => self.read_greater_than(state),
             '.' => self.read_dot(state),
-            ':' => self.read_colon(state),
+            ':' => self.read_simple_or_equals(TokenKind::Colon, TokenKind::ColonEquals, state),
             ',' => self.get_readers_result(TokenKind::Comma, state),
+            ';' => self.get_readers_result(TokenKind::Semicolon, state),
             '(' => self.get_readers_result(TokenKind::BraceRoundOpen, state),
             ')' => self.get_readers_result(TokenKind::BraceRoundClose, state),
             '{' => self.get_readers_result(TokenKind::BraceCurlyOpen, state),
@@ -702,8 +1039,8 @@ impl Reader for OperatorReader {
 struct CommandReader;
 
 impl Reader for CommandReader {
-    fn name(&self) -> String {
-        "CommandReader".to_string()
+    fn name(&self) -> &'static str {
+        "CommandReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
@@ -713,23 +1050,54 @@ impl Reader for CommandReader {
 
         read_char!(state, '$');
 
-        state.consume_whitespace();
-
+        let raw_start = state.get_position();
         let mut command = String::new();
+        let mut interpolations = vec![];
+
+        while let Some(&char) = state.peek() {
+            // Interpolated expression, e.g. "$ echo ${a + b}". Only closed on the same line, otherwise
+            // the `$` is left as part of the command.
+            if state.peek_str("${") {
+                if let Some(interpolation) = read_command_interpolation(state) {
+                    command.push_str("${");
+                    command.push_str(&interpolation.source);
+                    command.push('}');
+                    interpolations.push(interpolation);
+
+                    continue;
+                }
+            }
 
-        while let Some(char) = state.clone().peek() {
             match char {
-                // Escape new lines
-                '\\' if peek_char!(state, '\n') => {
+                // An escaped backslash is passed to the shell as is, and doesn't escape what follows
+                '\\' if matches!(state.peek_nth(1), Some('\\')) => {
+                    command.push(read_char!(state, '\\'));
+                    command.push(read_char!(state, '\\'));
+                }
+                // Escaped new lines continue the command and are folded into a single space
+                '\\' if matches!(state.peek_nth(1), Some(&char) if is_line_break(char)) => {
                     read_char!(state, '\\');
-                    command.push(read_char!(state, '\n'));
-                    continue;
+                    read_line_break(state);
+                    command.push(' ');
+                }
+                // A continuation with nothing to continue onto, which is most likely a truncated
+                // source rather than a literal backslash
+                '\\' if state.peek_nth(1).is_none() => {
+                    let position = state.get_position();
+
+                    read_char!(state, '\\');
+
+                    return ReaderResult::Err(
+                        ReaderError::new("Dangling line continuation", position)
+                            .with_span(Span::new(position, position + 1))
+                            .with_label("the source ends after this '\\'"),
+                    );
                 }
                 // Unescaped newline ends the command
-                '\n' => break,
+                char if is_line_break(char) => break,
                 // All other characters are part of the command
                 char => {
-                    command.push(read_char!(state, *char));
+                    command.push(read_char!(state, char));
                 }
             }
         }
@@ -738,22 +1106,47 @@ impl Reader for CommandReader {
             kind: TokenKind::Command,
             start: state.get_start(),
             end: state.get_position(),
-            value: TokenValue::String(command),
+            value: TokenValue::Command(CommandValue {
+                command: command.trim().to_string(),
+                raw_start,
+                raw_end: state.get_position(),
+                interpolations,
+            }),
         });
     }
 }
 
+/// Reads a `${...}` interpolation within a command, or nothing if it isn't closed before the end of
+/// the line.
+fn read_command_interpolation(state: &mut ReaderState) -> Option<Interpolation> {
+    let mut lookahead = state.clone();
+
+    read_char!(lookahead, '$');
+    read_char!(lookahead, '{');
+
+    let start = lookahead.get_position();
+    let source = read_interpolation_body(&mut lookahead)?;
+    let end = lookahead.get_position();
+
+    if source.chars().any(is_line_break) {
+        return None;
+    }
+
+    read_char!(lookahead, '}');
+    *state = lookahead;
+
+    Some(Interpolation { start, end, source })
+}
+
 struct NewLineReader;
 
 impl Reader for NewLineReader {
-    fn name(&self) -> String {
-        "NewLineReader".to_string()
+    fn name(&self) -> &'static str {
+        "NewLineReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
-        if state.peek() == Some(&'\n') {
-            state.read();
-
+        if read_line_break(state) {
             return ReaderResult::Token(Token {
                 kind: TokenKind::NewLine,
                 start: state.get_start(),
@@ -766,16 +1159,49 @@ impl Reader for NewLineReader {
     }
 }
 
+/// Reads a single line ending, treating `\r\n` as one. Returns false if there isn't one.
+fn read_line_break(state: &mut ReaderState) -> bool {
+    match state.peek() {
+        Some('\r') => {
+            state.read();
+
+            if state.peek() == Some(&'\n') {
+                state.read();
+            }
+
+            true
+        }
+        Some('\n') => {
+            state.read();
+
+            true
+        }
+        _ => false,
+    }
+}
+
 struct WhitespaceReader;
 
 impl Reader for WhitespaceReader {
-    fn name(&self) -> String {
-        "WhitespaceReader".to_string()
+    fn name(&self) -> &'static str {
+        "WhitespaceReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
-        while matches!(state.peek(), Some(char) if char.is_whitespace()) {
-            state.read();
+        loop {
+            match state.peek() {
+                // Newlines are significant, so they are left for the NewLineReader. Other Unicode
+                // whitespace, including U+2028 LINE SEPARATOR, is ordinary whitespace
+                Some(&char) if char.is_whitespace() && !is_line_break(char) => {
+                    state.read();
+                }
+                // Unless escaped, in which case the line continues onto the next
+                Some('\\') if matches!(state.peek_nth(1), Some(&char) if is_line_break(char)) => {
+                    read_char!(state, '\\');
+                    read_line_break(state);
+                }
+                _ => break,
+            }
         }
 
         if state.did_advance() {
@@ -794,51 +1220,105 @@ impl Reader for WhitespaceReader {
 struct UnexpectedCharacterReader;
 
 impl Reader for UnexpectedCharacterReader {
-    fn name(&self) -> String {
-        "UnexpectedCharacterReader".to_string()
+    fn name(&self) -> &'static str {
+        "UnexpectedCharacterReader"
     }
 
     fn read(&self, state: &mut ReaderState) -> ReaderResult {
         let char = state.read().unwrap();
 
-        return ReaderResult::Err(ReaderError {
-            message: format!("Unexpected character '{}'", char),
-            position: state.get_start(),
-        });
+        return ReaderResult::Err(
+            ReaderError::new(format!("Unexpected character '{}'", char), state.get_start())
+                .with_span(Span::new(state.get_start(), state.get_position())),
+        );
     }
 }
 
 pub fn default_lexer() -> Lexer {
     Lexer::new()
+        .add_reader(ShebangReader)
         .add_reader(CommentReader)
-        .add_reader(KeywordReader)
+        .add_reader(KeywordReader::new())
         .add_reader(BooleanReader)
         .add_reader(NumberReader)
         .add_reader(StringReader)
+        .add_reader(LabelReader)
+        .add_reader(CharReader)
         .add_reader(IdentifierReader)
         .add_reader(OperatorReader)
         .add_reader(CommandReader)
         .add_reader(NewLineReader)
         .add_reader(WhitespaceReader)
-        .add_reader(UnexpectedCharacterReader)
+        .with_fallback(UnexpectedCharacterReader)
 }
 
-// TODO: Remove this in favour of proper unit tests
-pub fn test() {
-    let mut lexer = default_lexer();
+/// The lexer with the default readers, see `default_lexer`. `Lexer::new` is the lexer without any
+/// readers, for building a custom stack.
+impl Default for Lexer {
+    fn default() -> Self {
+        default_lexer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::span::Span;
+
+    use super::{IdentifierReader, KeywordReader, WhitespaceReader, DEFAULT_KEYWORDS};
+    use crate::{
+        default_lexer,
+        lexer::Lexer,
+        token::{CommandValue, Interpolation, TemplateSegment, Token, TokenKind, TokenValue},
+    };
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        let result = default_lexer().lex(source);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        result.tokens.into_iter().map(|token| token.kind).filter(|kind| *kind != TokenKind::Whitespace).collect()
+    }
+
+    fn token(source: &str) -> Token {
+        let result = default_lexer().lex(source);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        let [token] = result.tokens.as_slice() else { panic!("expected one token, got {:?}", result.tokens) };
+        token.clone()
+    }
+
+    fn command(source: &str) -> (Token, CommandValue) {
+        let result = default_lexer().lex(source);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        match &result.tokens[0].value {
+            TokenValue::Command(value) => (result.tokens[0].clone(), value.clone()),
+            value => panic!("expected a command value, got {value:?}"),
+        }
+    }
+
+    fn interpolation(start: usize, end: usize, source: &str) -> TemplateSegment {
+        TemplateSegment::Interpolation(Interpolation { start, end, source: source.to_string() })
+    }
+
+    fn literal(text: &str) -> TemplateSegment {
+        TemplateSegment::Literal(text.to_string())
+    }
 
-    let source = "\
+    #[test]
+    fn lexes_a_sample_of_every_token_without_errors() {
+        let source = "\
         Ident ident ident_snake identCamel ident123
-        123 123.456 123. 123.456
+        123 123.456 123. 123.456 0 007 0x1F 0o17 0b101 0..5
         \"\" \"Hello World\" \"Hello \\\"World\\\"!\" \"multi
         line
         string\"
         true false
-        + - * / % ^ & | && || ! .. < << > >>
+        + - * / % ^ & | && || ! .. < << > >> >>>
         ( ) { } [ ]
-        = == += -= *= /= %= ^= <= <<= >= >>= &= &&= |= ||= !=
-        . ,
-        if else while for loop break continue return
+        = == => += -= *= /= %= ^= <= <<= >= >>= >>>= &= &&= |= ||= !=
+        . , ;
+        'a' '\\n' '\\u{1F600}'
+        if else while for loop break continue return fn match
         $ echo \"Hello World!\"
         $ echo Multi \
                line \
@@ -855,11 +1335,376 @@ pub fn test() {
         }
         ";
 
-    let result = lexer.lex(&source);
+        let result = default_lexer().lex(source);
+
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+    }
+
+    #[test]
+    fn lexes_a_leading_shebang_line() {
+        assert_eq!(kinds("#!/usr/bin/env cs\nx = 1"), [TokenKind::Shebang, TokenKind::NewLine, TokenKind::Identifier, TokenKind::Equals, TokenKind::Number]);
+        assert_eq!(kinds("#!a\r\nb"), [TokenKind::Shebang, TokenKind::NewLine, TokenKind::Identifier]);
+
+        let shebang = token("#!/usr/bin/env cs");
+        assert_eq!((shebang.start, shebang.end), (0, 17));
+        assert_eq!(shebang.value, TokenValue::String("/usr/bin/env cs".to_string()));
+        assert_eq!(token("#!").value, TokenValue::String(String::new()));
+    }
+
+    #[test]
+    fn only_reads_a_shebang_at_the_start_of_the_source() {
+        for source in [" #!a", "x\n#!a", "#", "#x", "a # b"] {
+            let result = default_lexer().lex(source);
+
+            assert!(result.tokens.iter().all(|token| token.kind != TokenKind::Shebang), "{source:?}");
+            assert_eq!(result.errors.len(), 1, "{source:?}");
+            assert_eq!(result.errors[0].message, "Unexpected character '#'");
+        }
+    }
+
+    #[test]
+    fn lexes_unicode_spaces_and_line_separators_as_whitespace() {
+        for separator in ["\u{a0}", "\u{2028}", "\u{2029}", "\u{85}", "\u{3000}"] {
+            let result = default_lexer().lex(&format!("a{separator}b"));
+            let kinds: Vec<TokenKind> = result.tokens.iter().map(|token| token.kind.clone()).collect();
+
+            assert!(result.errors.is_empty(), "{separator:?}: {:?}", result.errors);
+            assert_eq!(kinds, [TokenKind::Identifier, TokenKind::Whitespace, TokenKind::Identifier], "{separator:?}");
+            assert_eq!((result.tokens[1].start, result.tokens[1].end), (1, 2));
+        }
+
+        assert_eq!(kinds("a \u{a0}\u{2028}\nb"), [TokenKind::Identifier, TokenKind::NewLine, TokenKind::Identifier]);
+    }
+
+    #[test]
+    fn lexes_strings_without_interpolation_as_strings() {
+        assert_eq!(token("\"Hello\"").value, TokenValue::String("Hello".to_string()));
+        assert_eq!(token("\"a $ b $1\"").value, TokenValue::String("a $ b $1".to_string()));
+    }
+
+    #[test]
+    fn reports_unterminated_strings_from_the_opening_quote() {
+        for (source, start) in [("\"abc", 0), ("\"${a}", 0), ("\"x$a", 0), ("x = \"${a}", 4), ("\"a\\\"", 0), ("\"a\nb", 0)] {
+            let result = default_lexer().lex(source);
+            let [error] = result.errors.as_slice() else { panic!("expected one error for {source:?}, got {:?}", result.errors) };
+
+            assert_eq!(error.message, "Unterminated string", "{source:?}");
+            assert_eq!(error.span, Some(Span::new(start, source.chars().count())), "{source:?}");
+            assert_eq!(error.label.as_deref(), Some("no closing quote"));
+            assert!(result.tokens.iter().all(|token| !matches!(token.kind, TokenKind::String | TokenKind::TemplateString)));
+        }
+
+        assert_eq!(token("\"a\nb\"").value, TokenValue::String("a\nb".to_string()));
+    }
+
+    #[test]
+    fn lexes_named_interpolation() {
+        let token = token("\"Hello $name!\"");
+
+        assert_eq!(token.kind, TokenKind::TemplateString);
+        assert_eq!((token.start, token.end), (0, 14));
+        assert_eq!(token.value, TokenValue::Template(vec![literal("Hello "), interpolation(8, 12, "name"), literal("!")]));
+    }
+
+    #[test]
+    fn lexes_expression_interpolation() {
+        let token = token("\"${a + b} = $c\"");
+
+        assert_eq!(token.kind, TokenKind::TemplateString);
+        assert_eq!(token.value, TokenValue::Template(vec![
+            interpolation(3, 8, "a + b"),
+            literal(" = "),
+            interpolation(13, 14, "c"),
+        ]));
+    }
+
+    #[test]
+    fn lexes_deeply_nested_interpolations_without_overflowing() {
+        let depth = 100_000;
+        let source = format!("{}1{}", "\"${".repeat(depth), "}\"".repeat(depth));
+        let token = token(&source);
+
+        assert_eq!(token.kind, TokenKind::TemplateString);
+        assert_eq!(token.end, source.chars().count());
+    }
+
+    #[test]
+    fn escaped_dollars_are_literal() {
+        assert_eq!(token("\"\\$name\"").value, TokenValue::String("$name".to_string()));
+        assert_eq!(token("\"\\${a}\"").value, TokenValue::String("${a}".to_string()));
+    }
+
+    #[test]
+    fn lexes_operators_with_and_without_equals() {
+        assert_eq!(kinds("= - * / + % ^"), [
+            TokenKind::Equals,
+            TokenKind::Minus,
+            TokenKind::Star,
+            TokenKind::Slash,
+            TokenKind::Plus,
+            TokenKind::Percent,
+            TokenKind::Caret,
+        ]);
+        assert_eq!(kinds("== -= *= /= += %= ^="), [
+            TokenKind::EqualsEquals,
+            TokenKind::MinusEquals,
+            TokenKind::StarEquals,
+            TokenKind::SlashEquals,
+            TokenKind::PlusEquals,
+            TokenKind::PercentEquals,
+            TokenKind::CaretEquals,
+        ]);
+        assert_eq!(kinds("+==-"), [TokenKind::PlusEquals, TokenKind::Equals, TokenKind::Minus]);
+    }
+
+    #[test]
+    fn lexes_fat_arrows() {
+        assert_eq!(kinds("= == =>"), [TokenKind::Equals, TokenKind::EqualsEquals, TokenKind::FatArrow]);
+        assert_eq!(kinds("==>"), [TokenKind::EqualsEquals, TokenKind::GreaterThan]);
+        assert_eq!(kinds("=>="), [TokenKind::FatArrow, TokenKind::Equals]);
+        assert_eq!(kinds("=>>"), [TokenKind::FatArrow, TokenKind::GreaterThan]);
+    }
+
+    #[test]
+    fn operator_tokens_span_their_text() {
+        let result = default_lexer().lex("a^=1");
+        let spans: Vec<(usize, usize)> = result.tokens.iter().map(|token| (token.start, token.end)).collect();
+
+        assert_eq!(spans, [(0, 1), (1, 3), (3, 4)]);
+        assert_eq!(result.tokens[1].value, TokenValue::None);
+    }
+
+    #[test]
+    fn lexes_single_line_commands() {
+        let (token, value) = command("$ echo hi\nx");
+
+        assert_eq!((token.start, token.end), (0, 9));
+        assert_eq!(value.command, "echo hi");
+        assert_eq!((value.raw_start, value.raw_end), (1, 9));
+    }
+
+    #[test]
+    fn trims_commands_but_not_their_raw_span() {
+        let (token, value) = command("$   ls -la  \n");
+
+        assert_eq!(value.command, "ls -la");
+        assert_eq!((value.raw_start, value.raw_end), (1, 12));
+        assert_eq!(token.end, 12);
+        assert_eq!(command("$echo").1.command, "echo");
+    }
+
+    #[test]
+    fn folds_escaped_newlines_in_commands_into_spaces() {
+        let (token, value) = command("$ echo a\\\n  b\\\r\nc\nd");
+
+        assert_eq!(value.command, "echo a   b c");
+        assert_eq!((value.raw_start, value.raw_end), (1, 17));
+        assert_eq!(token.end, 17);
+    }
+
+    #[test]
+    fn lexes_right_shifts() {
+        assert_eq!(kinds("> >> >>> >>= >>>= >="), [
+            TokenKind::GreaterThan,
+            TokenKind::GreaterThanGreaterThan,
+            TokenKind::GreaterThanGreaterThanGreaterThan,
+            TokenKind::GreaterThanGreaterThanEquals,
+            TokenKind::GreaterThanGreaterThanGreaterThanEquals,
+            TokenKind::GreaterThanEquals,
+        ]);
+        assert_eq!(kinds(">>>>"), [TokenKind::GreaterThanGreaterThanGreaterThan, TokenKind::GreaterThan]);
+    }
+
+    #[test]
+    fn lexes_the_default_keywords_as_whole_words() {
+        let source = DEFAULT_KEYWORDS.iter().map(|(keyword, _)| *keyword).collect::<Vec<_>>().join(" ");
+        let expected: Vec<TokenKind> = DEFAULT_KEYWORDS.iter().map(|(_, kind)| kind.clone()).collect();
 
-    // println!("{result:#?}");
+        assert_eq!(kinds(&source), expected);
+        assert_eq!(kinds("iffy fn_name match2 in_"), vec![TokenKind::Identifier; 4]);
+    }
+
+    #[test]
+    fn default_lexer_has_the_default_readers() {
+        let result = Lexer::default().lex("1 + 1");
+
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.tokens, default_lexer().lex("1 + 1").tokens);
+        assert_eq!(Lexer::new().lex("1 + 1").errors.len(), 5);
+    }
+
+    #[test]
+    fn lexes_custom_keywords() {
+        let mut lexer = Lexer::new()
+            .add_reader(KeywordReader::new().reserve("import").add_keyword("until", TokenKind::While))
+            .add_reader(IdentifierReader)
+            .add_reader(WhitespaceReader);
+        let result = lexer.lex("import until if imports");
+
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let kinds: Vec<TokenKind> = result.tokens.iter().map(|token| token.kind.clone()).filter(|kind| *kind != TokenKind::Whitespace).collect();
+        assert_eq!(kinds, [TokenKind::Reserved, TokenKind::While, TokenKind::If, TokenKind::Identifier]);
+        assert_eq!(result.tokens[0].value, TokenValue::String("import".to_string()));
+
+        let result = Lexer::new()
+            .add_reader(KeywordReader::with_keywords([("when", TokenKind::If)]))
+            .add_reader(IdentifierReader)
+            .add_reader(WhitespaceReader)
+            .lex("when if");
+        let kinds: Vec<TokenKind> = result.tokens.iter().map(|token| token.kind.clone()).collect();
+        assert_eq!(kinds, [TokenKind::If, TokenKind::Whitespace, TokenKind::Identifier]);
+    }
+
+    #[test]
+    fn distinguishes_labels_from_char_literals() {
+        assert_eq!(token("'outer").summary(), "Label(0..6, \"outer\")");
+        assert_eq!(token("'a").summary(), "Label(0..2, \"a\")");
+        assert_eq!(token("'éa").summary(), "Label(0..3, \"éa\")");
+        assert_eq!(kinds("'outer: loop"), [TokenKind::Label, TokenKind::Colon, TokenKind::Loop]);
+
+        assert_eq!(token("'a'").summary(), "Char(0..3, 'a')");
+        assert_eq!(token("'1'").summary(), "Char(0..3, '1')");
+        assert_eq!(token("'é'").summary(), "Char(0..3, 'é')");
+        assert_eq!(token("' '").summary(), "Char(0..3, ' ')");
+    }
 
-    result.errors.iter().for_each(move |error| {
-        println!("{}", format_error_message_inline(source, &error.message, error.position));
-    });
+    #[test]
+    fn decodes_escapes_in_chars_and_strings_alike() {
+        let cases = [("\\n", '\n'), ("\\x41", 'A'), ("\\u{41}", 'A'), ("\\u{1F600}", '😀'), ("\\t", '\t'), ("\\0", '\0'), ("\\\\", '\\')];
+
+        for (escape, expected) in cases {
+            assert_eq!(token(&format!("'{escape}'")).value, TokenValue::Char(expected), "{escape}");
+            assert_eq!(token(&format!("\"{escape}\"")).value, TokenValue::String(expected.to_string()), "{escape}");
+        }
+
+        assert_eq!(token("'\\''").value, TokenValue::Char('\''));
+        assert_eq!(token("\"\\\"\"").value, TokenValue::String("\"".to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_escapes_in_chars_and_strings_alike() {
+        let cases = [
+            ("\\q", "Unknown escape sequence '\\q'"),
+            ("\\x80", "Escapes with '\\x' must be at most '\\x7F'"),
+            ("\\x4", "Expected two hex digits after '\\x'"),
+            ("\\u{}", "Expected hex digits after '\\u{'"),
+            ("\\u{D800}", "'\\u{D800}' is not a valid character"),
+        ];
+
+        for (escape, message) in cases {
+            for source in [format!("'{escape}'"), format!("\"{escape}\"")] {
+                let errors = default_lexer().lex(&source).errors;
+                assert_eq!(errors.first().map(|error| error.message.as_str()), Some(message), "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn lexes_zero_and_leading_zero_integers() {
+        let integer = |value, raw: &str| TokenValue::Integer { value, raw: raw.to_string() };
+
+        assert_eq!(token("0").value, integer(0, "0"));
+        assert_eq!(token("00").value, integer(0, "00"));
+        assert_eq!(token("007").value, integer(7, "007"));
+        assert_eq!(token("0x0").value, integer(0, "0x0"));
+        assert_eq!(token("0b0").value, integer(0, "0b0"));
+        assert_eq!(token("0o17").value, integer(15, "0o17"));
+        assert_eq!(token("0xFF").value, integer(255, "0xFF"));
+        assert_eq!(token("0.5").value, TokenValue::Number { value: 0.5, raw: "0.5".to_string() });
+        assert_eq!(kinds("0..5"), [TokenKind::Number, TokenKind::DotDot, TokenKind::Number]);
+    }
+
+    #[test]
+    fn rejects_base_prefixes_without_valid_digits() {
+        for (source, message) in [
+            ("0x", "Expected digits after '0x'"),
+            ("0b2", "Invalid digit '2' in base 2 literal '0b2'"),
+            ("0xG", "Invalid digit 'G' in base 16 literal '0xG'"),
+        ] {
+            let errors = default_lexer().lex(source).errors;
+            assert_eq!(errors.iter().map(|error| error.message.as_str()).collect::<Vec<_>>(), [message], "{source}");
+        }
+    }
+
+    #[test]
+    fn lexes_long_identifiers_and_numbers() {
+        let identifier = format!("a{}", "é_9".repeat(100_000));
+        assert_eq!(token(&identifier).value, TokenValue::String(identifier.clone()));
+
+        let digits = "1234567890".repeat(50_000);
+        let float = format!("{digits}.{digits}");
+        match token(&float).value {
+            TokenValue::Number { value, raw } => {
+                assert_eq!(raw, float);
+                assert_eq!(value, float.parse::<f64>().unwrap());
+            }
+            value => panic!("expected a float, got {value:?}"),
+        }
+    }
+
+    #[test]
+    fn lexes_each_line_ending_as_one_new_line() {
+        for (ending, len) in [("\n", 1), ("\r", 1), ("\r\n", 2)] {
+            let newline = token(ending);
+            assert_eq!((newline.kind, newline.start, newline.end), (TokenKind::NewLine, 0, len), "{ending:?}");
+
+            assert_eq!(kinds(&format!("a{ending}b")), [TokenKind::Identifier, TokenKind::NewLine, TokenKind::Identifier], "{ending:?}");
+        }
+
+        assert_eq!(kinds("\r\r"), [TokenKind::NewLine, TokenKind::NewLine]);
+        assert_eq!(kinds("\n\r"), [TokenKind::NewLine, TokenKind::NewLine]);
+    }
+
+    #[test]
+    fn reports_a_dangling_line_continuation_at_the_end_of_a_command() {
+        for (source, position) in [("$ echo a\\", 8), ("x\n$ echo a\\", 10)] {
+            let result = default_lexer().lex(source);
+            let [error] = result.errors.as_slice() else { panic!("expected one error, got {:?}", result.errors) };
+
+            assert_eq!(error.message, "Dangling line continuation");
+            assert_eq!(error.span, Some(Span::new(position, position + 1)));
+            assert!(result.tokens.iter().all(|token| token.kind != TokenKind::Command));
+        }
+    }
+
+    #[test]
+    fn continues_a_command_onto_the_next_line() {
+        assert_eq!(command("$ echo a\\\n").1.command, "echo a");
+        assert_eq!(command("$ echo a\\\nb").1.command, "echo a b");
+        assert_eq!(command("$ echo a\\ b").1.command, "echo a\\ b");
+
+        // An escaped backslash doesn't continue the command
+        assert_eq!(command("$ echo a \\\\\n").1.command, "echo a \\\\");
+    }
+
+    #[test]
+    fn lexes_a_trailing_backslash_as_a_line_continuation() {
+        let operands = [TokenKind::Identifier, TokenKind::Plus, TokenKind::Identifier];
+
+        assert_eq!(kinds("a \\\n + b"), operands);
+        assert_eq!(kinds("a \\\r\n + b"), operands);
+        assert_eq!(kinds("a +\\\nb"), operands);
+        assert_eq!(kinds("a \\\n\n + b"), [TokenKind::Identifier, TokenKind::NewLine, TokenKind::Plus, TokenKind::Identifier]);
+
+        let result = default_lexer().lex("a \\\n b");
+        assert_eq!((result.tokens[1].kind.clone(), result.tokens[1].start, result.tokens[1].end), (TokenKind::Whitespace, 1, 5));
+    }
+
+    #[test]
+    fn records_command_interpolation_spans() {
+        let result = default_lexer().lex("$ echo ${a} ${b + 1}");
+        let [token] = result.tokens.as_slice() else { panic!("expected one token, got {:?}", result.tokens) };
+
+        assert_eq!(token.kind, TokenKind::Command);
+        match &token.value {
+            TokenValue::Command(value) => assert_eq!(
+                value.interpolations,
+                vec![
+                    Interpolation { start: 9, end: 10, source: "a".to_string() },
+                    Interpolation { start: 14, end: 19, source: "b + 1".to_string() },
+                ]
+            ),
+            value => panic!("expected a command value, got {value:?}"),
+        }
+    }
 }
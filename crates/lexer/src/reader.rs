@@ -1,7 +1,8 @@
 use crate::{ reader_result::ReaderResult, reader_state::ReaderState };
 
 pub trait Reader {
-  fn name(&self) -> String;
+  /// Identifies the reader in errors and traces, and when positioning readers relative to it.
+  fn name(&self) -> &'static str;
 
   fn read(
     &self,
@@ -1,6 +1,34 @@
+use common::span::Span;
+
 #[derive(thiserror::Error, Debug)]
 #[error("Reader error: {message} at {position}")]
 pub struct ReaderError {
     pub message: String,
+    /// Char offset at which the error occurred.
     pub position: usize,
+    /// The region of source the error applies to, if it's known.
+    pub span: Option<Span>,
+    /// A short note shown against the span in reports.
+    pub label: Option<String>,
+}
+
+impl ReaderError {
+    pub fn new(message: impl Into<String>, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            position,
+            span: None,
+            label: None,
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
 }
@@ -0,0 +1,7 @@
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ReaderPositionError {
+    #[error("No reader named '{0}'")]
+    UnknownReader(String),
+    #[error("Reader index {index} is out of bounds for {len} readers")]
+    IndexOutOfBounds { index: usize, len: usize },
+}
@@ -4,4 +4,6 @@ pub enum ReaderResult {
     Err(reader_error::ReaderError),
     None,
     Token(token::Token),
+    /// Several tokens read at once, in order, e.g. the parts of a string with embedded expressions.
+    Tokens(Vec<token::Token>),
 }
@@ -1,23 +1,45 @@
+use std::rc::Rc;
+
+use common::source_map::is_line_break;
+use unicode_id_start::is_id_continue;
+
 use crate::lexer_state::LexerState;
 
 #[derive(Debug, Clone)]
 pub struct ReaderState {
-  chars: Vec<char>,
+  chars: Rc<[char]>,
   position_start: usize,
   position_current: usize,
+  byte_position_start: usize,
+  byte_position_current: usize,
 }
 
 impl ReaderState {
   pub fn read(&mut self) -> Option<&char> {
     let ch = self.chars.get(self.position_current)?;
     self.position_current += 1;
+    self.byte_position_current += ch.len_utf8();
     Some(ch)
   }
 
-  pub fn peek(&mut self) -> Option<&char> {
+  pub fn peek(&self) -> Option<&char> {
     self.chars.get(self.position_current)
   }
 
+  /// Peeks the character `n` positions ahead of the current position without consuming anything.
+  /// `peek_nth(0)` is equivalent to `peek()`.
+  pub fn peek_nth(&self, n: usize) -> Option<&char> {
+    self.chars.get(self.position_current + n)
+  }
+
+  /// Returns true if the upcoming characters match the given string, without consuming them.
+  pub fn peek_str(&self, str: &str) -> bool {
+    str
+      .chars()
+      .enumerate()
+      .all(|(n, ch)| self.peek_nth(n) == Some(&ch))
+  }
+
   pub fn get_start(&self) -> usize {
     self.position_start
   }
@@ -26,10 +48,41 @@ impl ReaderState {
     self.position_current
   }
 
+  /// Byte offset of the start of the token being read, for tools which index the source by bytes.
+  pub fn get_byte_start(&self) -> usize {
+    self.byte_position_start
+  }
+
+  /// Byte offset of the current position, see `get_byte_start`.
+  pub fn get_byte_position(&self) -> usize {
+    self.byte_position_current
+  }
+
+  /// The number of chars after the current position.
+  pub fn remaining(&self) -> usize {
+    self.chars.len() - self.position_current
+  }
+
+  /// The length of the whole source in chars.
+  pub fn source_len(&self) -> usize {
+    self.chars.len()
+  }
+
+  /// Whether the token being read starts a line, i.e. is at the start of the source or directly
+  /// after a line break.
+  pub fn at_line_start(&self) -> bool {
+    match self.position_start.checked_sub(1) {
+      Some(previous) => is_line_break(self.chars[previous]),
+      None => true,
+    }
+  }
+
   pub fn did_advance(&self) -> bool {
     self.position_start != self.position_current
   }
 
+  /// Consumes all whitespace, including line breaks and Unicode whitespace such as U+00A0 NO-BREAK
+  /// SPACE and U+2028 LINE SEPARATOR.
   pub fn consume_whitespace(&mut self) {
     while let Some(ch) = self.peek() {
       if !ch.is_whitespace() {
@@ -40,23 +93,161 @@ impl ReaderState {
   }
 
   pub fn read_str<'a>(&mut self, str: &'a str) -> Option<&'a str> {
-    let start = self.position_current;
+    let start = self.save();
     for ch in str.chars() {
       if self.read() != Some(&ch) {
-        self.position_current = start;
+        self.restore(start);
         return None;
       }
     }
     Some(str)
   }
+
+  /// Like `read_str`, but only matches whole words, so `"if"` doesn't match the start of `iffy`.
+  pub fn read_word<'a>(&mut self, word: &'a str) -> Option<&'a str> {
+    let start = self.save();
+    self.read_str(word)?;
+
+    if matches!(self.peek(), Some(ch) if is_id_continue(*ch)) {
+      self.restore(start);
+      return None;
+    }
+
+    Some(word)
+  }
+
+  /// The current char and byte positions, to return to with `restore`.
+  fn save(&self) -> (usize, usize) {
+    (self.position_current, self.byte_position_current)
+  }
+
+  fn restore(&mut self, (position, byte_position): (usize, usize)) {
+    self.position_current = position;
+    self.byte_position_current = byte_position;
+  }
 }
 
 impl From<&LexerState> for ReaderState {
   fn from(lexer_state: &LexerState) -> Self {
     Self {
-      chars: lexer_state.chars.clone(),
+      chars: Rc::clone(&lexer_state.chars),
       position_start: lexer_state.position.clone(),
       position_current: lexer_state.position.clone(),
+      byte_position_start: lexer_state.byte_position,
+      byte_position_current: lexer_state.byte_position,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::lexer_state::LexerState;
+
+  use super::ReaderState;
+
+  fn state(source: &str, position: usize) -> ReaderState {
+    let chars: Vec<char> = source.chars().collect();
+
+    ReaderState::from(&LexerState {
+      length: chars.len(),
+      chars: chars.into(),
+      position,
+      byte_position: source.chars().take(position).map(char::len_utf8).sum(),
+    })
+  }
+
+  #[test]
+  fn tracks_byte_offsets_alongside_char_offsets() {
+    let mut state = state("é€a", 1);
+
+    assert_eq!((state.get_start(), state.get_byte_start()), (1, 2));
+
+    state.read();
+    assert_eq!((state.get_position(), state.get_byte_position()), (2, 5));
+
+    state.read();
+    state.read();
+    assert_eq!((state.get_position(), state.get_byte_position()), (3, 6));
+    assert_eq!((state.get_start(), state.get_byte_start()), (1, 2));
+  }
+
+  #[test]
+  fn peek_nth_looks_ahead_without_consuming() {
+    let state = state("abc", 1);
+
+    assert_eq!(state.peek_nth(0), Some(&'b'));
+    assert_eq!(state.peek_nth(0), state.peek());
+    assert_eq!(state.peek_nth(1), Some(&'c'));
+    assert_eq!(state.get_position(), 1);
+  }
+
+  #[test]
+  fn peek_nth_at_and_beyond_eof() {
+    let state = state("abc", 1);
+
+    assert_eq!(state.peek_nth(2), None);
+    assert_eq!(state.peek_nth(usize::MAX - 1), None);
+    assert_eq!(self::state("abc", 3).peek_nth(0), None);
+    assert_eq!(self::state("", 0).peek_nth(0), None);
+  }
+
+  #[test]
+  fn peek_str_matches_upcoming_chars() {
+    let state = state("a${b}", 1);
+
+    assert!(state.peek_str("${"));
+    assert!(state.peek_str("${b}"));
+    assert!(state.peek_str(""));
+    assert!(!state.peek_str("{"));
+    assert_eq!(state.get_position(), 1);
+  }
+
+  #[test]
+  fn peek_str_at_and_beyond_eof() {
+    assert!(!state("ab", 1).peek_str("bc"));
+    assert!(!state("ab", 2).peek_str("b"));
+    assert!(state("ab", 2).peek_str(""));
+  }
+
+  #[test]
+  fn consume_whitespace_consumes_unicode_whitespace_and_line_breaks() {
+    let mut state = state("a \u{a0}\u{2028}\r\n\tb", 1);
+
+    state.consume_whitespace();
+    assert_eq!(state.peek(), Some(&'b'));
+    assert_eq!(state.get_position(), 7);
+  }
+
+  #[test]
+  fn remaining_counts_chars_after_the_position() {
+    let mut state = state("aé€b", 1);
+
+    assert_eq!((state.remaining(), state.source_len()), (3, 4));
+
+    state.read();
+    assert_eq!((state.remaining(), state.source_len()), (2, 4));
+
+    state.read();
+    state.read();
+    assert_eq!(state.remaining(), 0);
+    assert_eq!(state.read(), None);
+    assert_eq!(state.remaining(), 0);
+  }
+
+  #[test]
+  fn at_line_start_after_a_line_break_or_at_the_start() {
+    assert!(state("ab", 0).at_line_start());
+    assert!(!state("ab", 1).at_line_start());
+    assert!(state("a\nb", 2).at_line_start());
+    assert!(state("a\r\nb", 3).at_line_start());
+    assert!(state("", 0).at_line_start());
+  }
+
+  #[test]
+  fn at_line_start_is_fixed_while_reading() {
+    let mut state = state("\nab", 1);
+
+    state.read();
+    assert!(state.at_line_start());
+  }
+}
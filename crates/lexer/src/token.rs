@@ -1,15 +1,21 @@
 use std::fmt::{Display, Debug};
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TokenKind {
     NewLine,
     Identifier,
     String,
+    TemplateString,
+    Char,
+    /// A loop label, e.g. `'outer`. The value is the name without the `'`.
+    Label,
     Number,
     Boolean,
     Command,
     Equals,
     EqualsEquals,
+    FatArrow,
     BangEquals,
     LessThan,
     LessThanEquals,
@@ -19,6 +25,9 @@ pub enum TokenKind {
     GreaterThanEquals,
     GreaterThanGreaterThan,
     GreaterThanGreaterThanEquals,
+    /// Unsigned right shift, `>>>`.
+    GreaterThanGreaterThanGreaterThan,
+    GreaterThanGreaterThanGreaterThanEquals,
     SlashEquals,
     StarEquals,
     PlusEquals,
@@ -45,7 +54,13 @@ pub enum TokenKind {
     Caret,
     Percent,
     Comma,
+    Semicolon,
     Comment,
+    /// A `///` comment documenting what follows it.
+    DocComment,
+    /// The `#!` line at the very start of a script, e.g. `#!/usr/bin/env commandscript`. The value
+    /// is the text after the `#!`.
+    Shebang,
     BraceCurlyOpen,
     BraceCurlyClose,
     BraceSquareOpen,
@@ -55,15 +70,183 @@ pub enum TokenKind {
     If,
     Else,
     For,
+    In,
     While,
     Loop,
     Break,
     Continue,
     Return,
     Function,
+    Match,
+    /// A keyword reserved by the embedder which has no meaning in the language yet. The value is
+    /// the keyword.
+    Reserved,
     Whitespace,
 }
 
+impl TokenKind {
+    /// Whitespace, newlines, comments and the shebang, which carry no meaning for the parser.
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Whitespace
+                | TokenKind::NewLine
+                | TokenKind::Comment
+                | TokenKind::DocComment
+                | TokenKind::Shebang
+        )
+    }
+
+    pub fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Equals
+                | TokenKind::EqualsEquals
+                | TokenKind::BangEquals
+                | TokenKind::LessThan
+                | TokenKind::LessThanEquals
+                | TokenKind::LessThanLessThan
+                | TokenKind::LessThanLessThanEquals
+                | TokenKind::GreaterThan
+                | TokenKind::GreaterThanEquals
+                | TokenKind::GreaterThanGreaterThan
+                | TokenKind::GreaterThanGreaterThanEquals
+                | TokenKind::GreaterThanGreaterThanGreaterThan
+                | TokenKind::GreaterThanGreaterThanGreaterThanEquals
+                | TokenKind::SlashEquals
+                | TokenKind::StarEquals
+                | TokenKind::PlusEquals
+                | TokenKind::MinusEquals
+                | TokenKind::PercentEquals
+                | TokenKind::CaretEquals
+                | TokenKind::AmpersandEquals
+                | TokenKind::AmpersandAmpersandEquals
+                | TokenKind::Ampersand
+                | TokenKind::AmpersandAmpersand
+                | TokenKind::PipeEquals
+                | TokenKind::PipePipeEquals
+                | TokenKind::Pipe
+                | TokenKind::PipePipe
+                | TokenKind::ColonEquals
+                | TokenKind::Dot
+                | TokenKind::DotDot
+                | TokenKind::Bang
+                | TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Slash
+                | TokenKind::Star
+                | TokenKind::Caret
+                | TokenKind::Percent
+        )
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::If
+                | TokenKind::Else
+                | TokenKind::For
+                | TokenKind::In
+                | TokenKind::While
+                | TokenKind::Loop
+                | TokenKind::Break
+                | TokenKind::Continue
+                | TokenKind::Return
+                | TokenKind::Function
+                | TokenKind::Match
+                | TokenKind::Reserved
+        )
+    }
+
+    /// The source text of kinds which are always written the same way, e.g. `+` or `if`. None for
+    /// kinds whose text varies, such as identifiers, literals and comments.
+    pub fn as_str(&self) -> Option<&'static str> {
+        let str = match self {
+            TokenKind::Equals => "=",
+            TokenKind::EqualsEquals => "==",
+            TokenKind::FatArrow => "=>",
+            TokenKind::BangEquals => "!=",
+            TokenKind::LessThan => "<",
+            TokenKind::LessThanEquals => "<=",
+            TokenKind::LessThanLessThan => "<<",
+            TokenKind::LessThanLessThanEquals => "<<=",
+            TokenKind::GreaterThan => ">",
+            TokenKind::GreaterThanEquals => ">=",
+            TokenKind::GreaterThanGreaterThan => ">>",
+            TokenKind::GreaterThanGreaterThanEquals => ">>=",
+            TokenKind::GreaterThanGreaterThanGreaterThan => ">>>",
+            TokenKind::GreaterThanGreaterThanGreaterThanEquals => ">>>=",
+            TokenKind::SlashEquals => "/=",
+            TokenKind::StarEquals => "*=",
+            TokenKind::PlusEquals => "+=",
+            TokenKind::MinusEquals => "-=",
+            TokenKind::PercentEquals => "%=",
+            TokenKind::CaretEquals => "^=",
+            TokenKind::AmpersandEquals => "&=",
+            TokenKind::AmpersandAmpersandEquals => "&&=",
+            TokenKind::Ampersand => "&",
+            TokenKind::AmpersandAmpersand => "&&",
+            TokenKind::PipeEquals => "|=",
+            TokenKind::PipePipeEquals => "||=",
+            TokenKind::Pipe => "|",
+            TokenKind::PipePipe => "||",
+            TokenKind::Colon => ":",
+            TokenKind::ColonEquals => ":=",
+            TokenKind::Dot => ".",
+            TokenKind::DotDot => "..",
+            TokenKind::Bang => "!",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Slash => "/",
+            TokenKind::Star => "*",
+            TokenKind::Caret => "^",
+            TokenKind::Percent => "%",
+            TokenKind::Comma => ",",
+            TokenKind::Semicolon => ";",
+            TokenKind::BraceCurlyOpen => "{",
+            TokenKind::BraceCurlyClose => "}",
+            TokenKind::BraceSquareOpen => "[",
+            TokenKind::BraceSquareClose => "]",
+            TokenKind::BraceRoundOpen => "(",
+            TokenKind::BraceRoundClose => ")",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::For => "for",
+            TokenKind::In => "in",
+            TokenKind::While => "while",
+            TokenKind::Loop => "loop",
+            TokenKind::Break => "break",
+            TokenKind::Continue => "continue",
+            TokenKind::Return => "return",
+            TokenKind::Function => "fn",
+            TokenKind::Match => "match",
+            TokenKind::NewLine
+            | TokenKind::Identifier
+            | TokenKind::String
+            | TokenKind::TemplateString
+            | TokenKind::Number
+            | TokenKind::Boolean
+            | TokenKind::Char
+            | TokenKind::Label
+            | TokenKind::Command
+            | TokenKind::Comment
+            | TokenKind::DocComment
+            | TokenKind::Shebang
+            | TokenKind::Reserved
+            | TokenKind::Whitespace => return None,
+        };
+
+        Some(str)
+    }
+
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::String | TokenKind::Number | TokenKind::Boolean | TokenKind::Char
+        )
+    }
+}
+
 impl Display for TokenKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Debug::fmt(self, f)
@@ -71,17 +254,250 @@ impl Display for TokenKind {
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TokenValue {
     None,
     String(String),
-    Number(f64),
+    /// An integer, along with its source text.
+    Integer { value: i64, raw: String },
+    /// A floating point number, i.e. one written with a decimal point or an exponent, along with
+    /// its source text. The source text is exact where the value may have lost precision.
+    Number { value: f64, raw: String },
     Boolean(bool),
+    Char(char),
+    Template(Vec<TemplateSegment>),
+    Command(CommandValue),
 }
 
+/// The value of a `$ command` token.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CommandValue {
+    /// The command with surrounding whitespace trimmed and each escaped newline (`\` followed by a
+    /// newline) folded into a single space.
+    pub command: String,
+    /// Position of the first character after the `$`.
+    pub raw_start: usize,
+    /// Position after the last character of the command, i.e. before the terminating newline.
+    pub raw_end: usize,
+    /// The `${...}` expressions embedded in the command, which are also left in `command`.
+    pub interpolations: Vec<Interpolation>,
+}
+
+/// An expression embedded in a string, e.g. `$name` or `${a + b}`.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Interpolation {
+    /// Position of the first character of the embedded expression.
+    pub start: usize,
+    /// Position after the last character of the embedded expression.
+    pub end: usize,
+    /// Source text of the embedded expression.
+    pub source: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TemplateSegment {
+    Literal(String),
+    Interpolation(Interpolation),
+}
+
+/// A token of the source. Like every position in the lexer and parser, `start` and `end` are char
+/// offsets into the source rather than byte offsets, so they can't be used to slice the source
+/// directly. See `LexerResult::tokens_with_text`.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Token {
     pub kind: TokenKind,
+    /// Char offset of the first character of the token.
     pub start: usize,
+    /// Char offset after the last character of the token.
     pub end: usize,
     pub value: TokenValue,
 }
+
+impl Token {
+    pub fn is_trivia(&self) -> bool {
+        self.kind.is_trivia()
+    }
+
+    /// A compact description of the token for snapshots, e.g. `Number(0..3, 123)` or `Plus(4..5)`.
+    /// Numbers are given as written and text is quoted. Templates are given as their literal text
+    /// with each interpolation written as `${...}`.
+    pub fn summary(&self) -> String {
+        let value = match &self.value {
+            TokenValue::None => return format!("{}({}..{})", self.kind, self.start, self.end),
+            TokenValue::String(value) => format!("{:?}", value),
+            TokenValue::Integer { raw, .. } | TokenValue::Number { raw, .. } => raw.clone(),
+            TokenValue::Boolean(value) => value.to_string(),
+            TokenValue::Char(value) => format!("{:?}", value),
+            TokenValue::Template(segments) => {
+                let text: String = segments
+                    .iter()
+                    .map(|segment| match segment {
+                        TemplateSegment::Literal(text) => text.clone(),
+                        TemplateSegment::Interpolation(interpolation) => format!("${{{}}}", interpolation.source),
+                    })
+                    .collect();
+
+                format!("{:?}", text)
+            }
+            TokenValue::Command(value) => format!("{:?}", value.command),
+        };
+
+        format!("{}({}..{}, {})", self.kind, self.start, self.end, value)
+    }
+}
+
+/// Iterates over the tokens which are meaningful to a parser, skipping whitespace, newlines and
+/// comments.
+pub fn significant_tokens(tokens: &[Token]) -> impl Iterator<Item = &Token> {
+    tokens.iter().filter(|token| !token.is_trivia())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{significant_tokens, Token, TokenKind, TokenValue};
+    use crate::default_lexer;
+
+    fn token(kind: TokenKind, start: usize) -> Token {
+        Token { kind, start, end: start + 1, value: TokenValue::None }
+    }
+
+    /// The classes of the kind, as the names of the classification methods it satisfies.
+    fn classes(kind: TokenKind) -> Vec<&'static str> {
+        [
+            ("trivia", kind.is_trivia()),
+            ("operator", kind.is_operator()),
+            ("keyword", kind.is_keyword()),
+            ("literal", kind.is_literal()),
+        ]
+        .into_iter()
+        .filter(|(_, is)| *is)
+        .map(|(class, _)| class)
+        .collect()
+    }
+
+    #[test]
+    fn classifies_token_kinds() {
+        for kind in [TokenKind::Whitespace, TokenKind::NewLine, TokenKind::Comment, TokenKind::DocComment, TokenKind::Shebang] {
+            assert_eq!(classes(kind.clone()), ["trivia"], "{kind}");
+        }
+
+        for kind in [TokenKind::Plus, TokenKind::Equals, TokenKind::AmpersandAmpersandEquals, TokenKind::Dot, TokenKind::Bang] {
+            assert_eq!(classes(kind.clone()), ["operator"], "{kind}");
+        }
+
+        for kind in [TokenKind::If, TokenKind::Function, TokenKind::Match, TokenKind::Reserved] {
+            assert_eq!(classes(kind.clone()), ["keyword"], "{kind}");
+        }
+
+        for kind in [TokenKind::String, TokenKind::Number, TokenKind::Boolean, TokenKind::Char] {
+            assert_eq!(classes(kind.clone()), ["literal"], "{kind}");
+        }
+
+        for kind in [TokenKind::Identifier, TokenKind::Command, TokenKind::BraceRoundOpen, TokenKind::Comma, TokenKind::Label] {
+            assert!(classes(kind.clone()).is_empty(), "{kind}");
+        }
+    }
+
+    #[test]
+    fn fixed_text_kinds_round_trip_through_the_lexer() {
+        let kinds = [
+            (TokenKind::Plus, "+"),
+            (TokenKind::EqualsEquals, "=="),
+            (TokenKind::FatArrow, "=>"),
+            (TokenKind::GreaterThanGreaterThanEquals, ">>="),
+            (TokenKind::GreaterThanGreaterThanGreaterThanEquals, ">>>="),
+            (TokenKind::AmpersandAmpersandEquals, "&&="),
+            (TokenKind::ColonEquals, ":="),
+            (TokenKind::DotDot, ".."),
+            (TokenKind::BraceCurlyOpen, "{"),
+            (TokenKind::BraceRoundClose, ")"),
+            (TokenKind::Semicolon, ";"),
+            (TokenKind::If, "if"),
+            (TokenKind::In, "in"),
+            (TokenKind::Continue, "continue"),
+            (TokenKind::Function, "fn"),
+            (TokenKind::Match, "match"),
+        ];
+
+        for (kind, text) in kinds {
+            assert_eq!(kind.as_str(), Some(text));
+
+            let tokens = default_lexer().lex(text).tokens;
+            assert_eq!(tokens.iter().map(|token| &token.kind).collect::<Vec<_>>(), [&kind], "{text}");
+        }
+    }
+
+    #[test]
+    fn variable_text_kinds_have_no_fixed_text() {
+        for kind in [
+            TokenKind::Identifier,
+            TokenKind::Number,
+            TokenKind::String,
+            TokenKind::TemplateString,
+            TokenKind::Boolean,
+            TokenKind::Command,
+            TokenKind::Comment,
+            TokenKind::Whitespace,
+            TokenKind::NewLine,
+            TokenKind::Reserved,
+        ] {
+            assert_eq!(kind.as_str(), None, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn summarizes_a_token_stream() {
+        let tokens = default_lexer().lex("x = 1.50 + \"a\" // c\n'b' true \"${x}!\"").tokens;
+        let summaries: Vec<String> = significant_tokens(&tokens).map(Token::summary).collect();
+
+        assert_eq!(summaries, [
+            "Identifier(0..1, \"x\")",
+            "Equals(2..3)",
+            "Number(4..8, 1.50)",
+            "Plus(9..10)",
+            "String(11..14, \"a\")",
+            "Char(20..23, 'b')",
+            "Boolean(24..28, true)",
+            "TemplateString(29..36, \"${x}!\")",
+        ]);
+        assert_eq!(default_lexer().lex("$ echo hi").tokens[0].summary(), "Command(0..9, \"echo hi\")");
+    }
+
+    #[test]
+    fn token_is_trivia_if_its_kind_is() {
+        assert!(token(TokenKind::Comment, 0).is_trivia());
+        assert!(!token(TokenKind::Identifier, 0).is_trivia());
+    }
+
+    #[test]
+    fn significant_tokens_skips_trivia() {
+        let tokens = vec![
+            token(TokenKind::Shebang, 0),
+            token(TokenKind::NewLine, 1),
+            token(TokenKind::DocComment, 2),
+            token(TokenKind::Identifier, 3),
+            token(TokenKind::Whitespace, 4),
+            token(TokenKind::Plus, 5),
+            token(TokenKind::Comment, 6),
+            token(TokenKind::NewLine, 7),
+            token(TokenKind::Number, 8),
+            token(TokenKind::Whitespace, 9),
+        ];
+
+        let starts: Vec<usize> = significant_tokens(&tokens).map(|token| token.start).collect();
+
+        assert_eq!(starts, [3, 5, 8]);
+    }
+
+    #[test]
+    fn significant_tokens_of_only_trivia_is_empty() {
+        let tokens = vec![token(TokenKind::Whitespace, 0), token(TokenKind::Comment, 1)];
+
+        assert_eq!(significant_tokens(&tokens).count(), 0);
+        assert_eq!(significant_tokens(&[]).count(), 0);
+    }
+}
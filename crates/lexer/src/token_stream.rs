@@ -0,0 +1,158 @@
+use crate::{
+    token::{Token, TokenKind},
+    token_stream_error::TokenStreamError,
+};
+
+/// A cursor over a slice of tokens with the lookahead helpers needed to write a parser.
+#[derive(Debug, Clone)]
+pub struct TokenStream<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            position: 0,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    pub fn peek_kind(&self) -> Option<&'a TokenKind> {
+        self.peek().map(|token| &token.kind)
+    }
+
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    /// Consumes the next token if it is of the given kind, otherwise returns an error without
+    /// advancing.
+    pub fn expect(&mut self, kind: TokenKind) -> Result<&'a Token, TokenStreamError> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => {
+                return Err(TokenStreamError {
+                    message: format!("Expected token of kind {}, found end of file", kind),
+                    position: match self.tokens.last() {
+                        Some(token) => token.end,
+                        None => 0,
+                    },
+                })
+            }
+        };
+
+        if token.kind != kind {
+            return Err(TokenStreamError {
+                message: format!(
+                    "Expected token of kind {}, found token of kind {}",
+                    kind, token.kind
+                ),
+                position: token.start,
+            });
+        }
+
+        self.position += 1;
+
+        Ok(token)
+    }
+
+    /// Advances past any whitespace, newline and comment tokens.
+    pub fn skip_trivia(&mut self) {
+        while matches!(self.peek(), Some(token) if token.is_trivia()) {
+            self.position += 1;
+        }
+    }
+}
+
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = &'a Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.peek()?;
+        self.position += 1;
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{default_lexer, token::TokenKind};
+
+    use super::TokenStream;
+
+    #[test]
+    fn expect_consumes_a_token_of_the_given_kind() {
+        let tokens = default_lexer().lex("a = 1").tokens;
+        let mut stream = TokenStream::new(&tokens);
+
+        let token = stream.expect(TokenKind::Identifier).unwrap();
+
+        assert_eq!((token.start, token.end), (0, 1));
+        assert_eq!(stream.get_position(), 1);
+        assert_eq!(stream.peek_kind(), Some(&TokenKind::Whitespace));
+    }
+
+    #[test]
+    fn expect_fails_without_advancing() {
+        let tokens = default_lexer().lex("a = 1").tokens;
+        let mut stream = TokenStream::new(&tokens);
+        stream.next();
+        stream.skip_trivia();
+
+        let error = stream.expect(TokenKind::Number).unwrap_err();
+
+        assert_eq!(error.message, "Expected token of kind Number, found token of kind Equals");
+        assert_eq!(error.position, 2);
+        assert_eq!(stream.get_position(), 2);
+    }
+
+    #[test]
+    fn expect_fails_at_the_end_of_the_tokens() {
+        let tokens = default_lexer().lex("a").tokens;
+        let mut stream = TokenStream::new(&tokens);
+        stream.next();
+
+        let error = stream.expect(TokenKind::Equals).unwrap_err();
+
+        assert_eq!(error.message, "Expected token of kind Equals, found end of file");
+        assert_eq!(error.position, 1);
+        assert_eq!(TokenStream::new(&[]).expect(TokenKind::Equals).unwrap_err().position, 0);
+    }
+
+    #[test]
+    fn skip_trivia_skips_whitespace_newlines_and_comments() {
+        let tokens = default_lexer().lex("a // comment\n  \n b").tokens;
+        let mut stream = TokenStream::new(&tokens);
+        stream.next();
+        stream.skip_trivia();
+
+        assert_eq!(stream.peek_kind(), Some(&TokenKind::Identifier));
+        assert_eq!(stream.peek().unwrap().start, 17);
+
+        stream.next();
+        stream.skip_trivia();
+
+        assert!(stream.at_end());
+        assert_eq!(stream.next(), None);
+    }
+
+    #[test]
+    fn iterates_over_the_remaining_tokens() {
+        let tokens = default_lexer().lex("a+b").tokens;
+        let mut stream = TokenStream::new(&tokens);
+        stream.next();
+
+        let kinds: Vec<&TokenKind> = stream.map(|token| &token.kind).collect();
+
+        assert_eq!(kinds, [&TokenKind::Plus, &TokenKind::Identifier]);
+    }
+}
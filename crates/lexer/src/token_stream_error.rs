@@ -0,0 +1,6 @@
+#[derive(thiserror::Error, Debug)]
+#[error("Token stream error: {message} at {position}")]
+pub struct TokenStreamError {
+    pub message: String,
+    pub position: usize,
+}
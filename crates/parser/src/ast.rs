@@ -1,33 +1,69 @@
-use lexer::token::{Token, TokenKind, TokenValue};
+use std::fmt::Display;
 
-use crate::{from_token::FromToken, parser_error::ParserError, span::Span};
+use lexer::token::{Token, TokenKind, TokenValue};
 
-macro_rules! unwrap_token_value {
-    ($variant:ident, $value:expr) => {
-        match $value {
-            TokenValue::$variant(inner) => inner,
-            _ => unreachable!("Unexpected enum variant: {:?}", $value),
-        }
-    };
-}
+use crate::{diagnostic::Diagnostic, from_token::FromToken, parser_error::ParserError, span::Span};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Program {
     pub ast: Vec<Expression>,
+    /// Doc comments, in the order they appear in the source.
+    pub docs: Vec<DocComment>,
+    /// Likely mistakes which didn't stop the program from parsing, in the order they were found.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Program {
+    /// Calls `f` with every expression of the program, each before its children, in source order.
+    /// Identifiers which aren't expressions themselves, such as parameters and member names, are
+    /// part of the expression they belong to.
+    pub fn walk(&self, mut f: impl FnMut(&Expression)) {
+        self.ast.iter().for_each(|expression| expression.walk(&mut f));
+    }
+
+    /// Calls `f` with every expression of the program for rewriting in place, each after its
+    /// children, so `f` sees children which have already been rewritten. See `walk`.
+    pub fn walk_mut(&mut self, mut f: impl FnMut(&mut Expression)) {
+        self.ast.iter_mut().for_each(|expression| expression.walk_mut(&mut f));
+    }
 }
 
-#[derive(Debug)]
+/// The text of the `///` comments directly preceding a statement, joined by newlines.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DocComment {
+    /// The span of the documented statement.
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Expression {
     Infix(Box<InfixExpression>),
     Prefix(Box<PrefixExpression>),
+    Postfix(Box<PostfixExpression>),
     Grouping(Box<GroupingExpression>),
+    Tuple(Box<TupleExpression>),
+    Array(Box<ArrayExpression>),
     Block(Box<BlockExpression>),
     Literal(Box<LiteralExpression>),
+    TemplateString(Box<TemplateStringExpression>),
+    Command(Box<CommandExpression>),
     Identifier(Box<IdentifierExpression>),
     Call(Box<CallExpression>),
+    Member(Box<MemberExpression>),
+    Index(Box<IndexExpression>),
     If(Box<IfExpression>),
+    Loop(Box<LoopExpression>),
+    While(Box<WhileExpression>),
+    For(Box<ForExpression>),
     Break(Box<BreakExpression>),
+    Continue(Box<ContinueExpression>),
+    Return(Box<ReturnExpression>),
     FunctionDeclaration(Box<FunctionDeclarationExpression>),
+    Match(Box<MatchExpression>),
 }
 
 impl Expression {
@@ -35,14 +71,27 @@ impl Expression {
         match self {
             Expression::Infix(expression) => *expression.span,
             Expression::Prefix(expression) => *expression.span,
+            Expression::Postfix(expression) => *expression.span,
             Expression::Grouping(expression) => *expression.span,
+            Expression::Tuple(expression) => *expression.span,
+            Expression::Array(expression) => *expression.span,
             Expression::Block(expression) => *expression.span,
             Expression::Literal(expression) => *expression.span,
+            Expression::TemplateString(expression) => *expression.span,
+            Expression::Command(expression) => *expression.span,
             Expression::Identifier(expression) => *expression.span,
             Expression::Call(expression) => *expression.span,
+            Expression::Member(expression) => *expression.span,
+            Expression::Index(expression) => *expression.span,
             Expression::If(expression) => *expression.span,
+            Expression::Loop(expression) => *expression.span,
+            Expression::While(expression) => *expression.span,
+            Expression::For(expression) => *expression.span,
             Expression::Break(expression) => *expression.span,
+            Expression::Continue(expression) => *expression.span,
+            Expression::Return(expression) => *expression.span,
             Expression::FunctionDeclaration(expression) => *expression.span,
+            Expression::Match(expression) => *expression.span,
         }
     }
 
@@ -50,24 +99,303 @@ impl Expression {
         match self {
             Expression::Infix(_) => "infix".to_string(),
             Expression::Prefix(_) => "prefix".to_string(),
+            Expression::Postfix(_) => "postfix".to_string(),
             Expression::Grouping(_) => "grouping".to_string(),
+            Expression::Tuple(_) => "tuple".to_string(),
+            Expression::Array(_) => "array".to_string(),
             Expression::Block(_) => "block".to_string(),
             Expression::Literal(_) => "literal".to_string(),
+            Expression::TemplateString(_) => "template string".to_string(),
+            Expression::Command(_) => "command".to_string(),
             Expression::Identifier(_) => "identifier".to_string(),
             Expression::Call(_) => "call".to_string(),
+            Expression::Member(_) => "member".to_string(),
+            Expression::Index(_) => "index".to_string(),
             Expression::If(_) => "if".to_string(),
+            Expression::Loop(_) => "loop".to_string(),
+            Expression::While(_) => "while".to_string(),
+            Expression::For(_) => "for".to_string(),
             Expression::Break(_) => "break".to_string(),
+            Expression::Continue(_) => "continue".to_string(),
+            Expression::Return(_) => "return".to_string(),
             Expression::FunctionDeclaration(_) => "function declaration".to_string(),
+            Expression::Match(_) => "match".to_string(),
+        }
+    }
+
+    /// The direct subexpressions of the expression, in source order.
+    pub fn children(&self) -> Vec<&Expression> {
+        let mut children: Vec<&Expression> = Vec::new();
+
+        match self {
+            Expression::Infix(expression) => {
+                children.push(&expression.left);
+                children.push(&expression.right);
+            }
+            Expression::Prefix(expression) => children.push(&expression.right),
+            Expression::Postfix(expression) => children.push(&expression.left),
+            Expression::Grouping(expression) => children.push(&expression.expression),
+            Expression::Tuple(expression) => children.extend(expression.elements.iter()),
+            Expression::Array(expression) => children.extend(expression.elements.iter()),
+            Expression::Block(expression) => {
+                children.extend(expression.statements.iter());
+                children.extend(expression.tail.as_deref());
+            }
+            Expression::TemplateString(expression) => {
+                for part in expression.parts.iter() {
+                    if let TemplatePart::Expression(expression) = part {
+                        children.push(expression);
+                    }
+                }
+            }
+            Expression::Command(expression) => {
+                for part in expression.parts.iter() {
+                    if let TemplatePart::Expression(expression) = part {
+                        children.push(expression);
+                    }
+                }
+            }
+            Expression::Call(expression) => {
+                children.push(&expression.callee);
+                children.extend(expression.arguments.iter());
+            }
+            Expression::Member(expression) => children.push(&expression.object),
+            Expression::Index(expression) => {
+                children.push(&expression.object);
+                children.push(&expression.index);
+            }
+            Expression::If(expression) => {
+                for condition in expression.conditions.iter() {
+                    children.push(&condition.condition);
+                    children.push(&condition.consequence);
+                }
+
+                if let Some(default) = &expression.default {
+                    children.push(&default.consequence);
+                }
+            }
+            Expression::Loop(expression) => children.push(&expression.body),
+            Expression::While(expression) => {
+                children.push(&expression.condition);
+                children.push(&expression.body);
+            }
+            Expression::For(expression) => {
+                children.push(&expression.iterable);
+                children.push(&expression.body);
+            }
+            Expression::Break(expression) => children.extend(expression.expression.as_deref()),
+            Expression::Return(expression) => children.extend(expression.value.as_deref()),
+            Expression::FunctionDeclaration(expression) => children.push(&expression.body),
+            Expression::Match(expression) => {
+                children.push(&expression.scrutinee);
+                children.extend(expression.arms.iter().map(|arm| &*arm.expression));
+            }
+            Expression::Literal(_) | Expression::Identifier(_) | Expression::Continue(_) => {}
+        }
+
+        children
+    }
+
+    /// The direct subexpressions of the expression, in source order, for rewriting in place.
+    pub fn children_mut(&mut self) -> Vec<&mut Expression> {
+        let mut children: Vec<&mut Expression> = Vec::new();
+
+        match self {
+            Expression::Infix(expression) => {
+                children.push(&mut expression.left);
+                children.push(&mut expression.right);
+            }
+            Expression::Prefix(expression) => children.push(&mut expression.right),
+            Expression::Postfix(expression) => children.push(&mut expression.left),
+            Expression::Grouping(expression) => children.push(&mut expression.expression),
+            Expression::Tuple(expression) => children.extend(expression.elements.iter_mut()),
+            Expression::Array(expression) => children.extend(expression.elements.iter_mut()),
+            Expression::Block(expression) => {
+                children.extend(expression.statements.iter_mut());
+                children.extend(expression.tail.as_deref_mut());
+            }
+            Expression::TemplateString(expression) => {
+                for part in expression.parts.iter_mut() {
+                    if let TemplatePart::Expression(expression) = part {
+                        children.push(expression);
+                    }
+                }
+            }
+            Expression::Command(expression) => {
+                for part in expression.parts.iter_mut() {
+                    if let TemplatePart::Expression(expression) = part {
+                        children.push(expression);
+                    }
+                }
+            }
+            Expression::Call(expression) => {
+                children.push(&mut expression.callee);
+                children.extend(expression.arguments.iter_mut());
+            }
+            Expression::Member(expression) => children.push(&mut expression.object),
+            Expression::Index(expression) => {
+                children.push(&mut expression.object);
+                children.push(&mut expression.index);
+            }
+            Expression::If(expression) => {
+                for condition in expression.conditions.iter_mut() {
+                    children.push(&mut condition.condition);
+                    children.push(&mut condition.consequence);
+                }
+
+                if let Some(default) = &mut expression.default {
+                    children.push(&mut default.consequence);
+                }
+            }
+            Expression::Loop(expression) => children.push(&mut expression.body),
+            Expression::While(expression) => {
+                children.push(&mut expression.condition);
+                children.push(&mut expression.body);
+            }
+            Expression::For(expression) => {
+                children.push(&mut expression.iterable);
+                children.push(&mut expression.body);
+            }
+            Expression::Break(expression) => children.extend(expression.expression.as_deref_mut()),
+            Expression::Return(expression) => children.extend(expression.value.as_deref_mut()),
+            Expression::FunctionDeclaration(expression) => children.push(&mut expression.body),
+            Expression::Match(expression) => {
+                children.push(&mut expression.scrutinee);
+                children.extend(expression.arms.iter_mut().map(|arm| &mut *arm.expression));
+            }
+            Expression::Literal(_) | Expression::Identifier(_) | Expression::Continue(_) => {}
+        }
+
+        children
+    }
+
+    /// Calls `f` with the expression and then each of its descendants, see `Program::walk`.
+    pub fn walk<F: FnMut(&Expression)>(&self, f: &mut F) {
+        f(self);
+        self.children().into_iter().for_each(|child| child.walk(f));
+    }
+
+    /// Calls `f` with each descendant of the expression and then the expression itself, see
+    /// `Program::walk_mut`.
+    pub fn walk_mut<F: FnMut(&mut Expression)>(&mut self, f: &mut F) {
+        self.children_mut().into_iter().for_each(|child| child.walk_mut(f));
+        f(self);
+    }
+
+    /// Whether the expressions are the same apart from their spans, e.g. `a + 1` parsed at different
+    /// offsets of a source.
+    pub fn structurally_eq(&self, other: &Expression) -> bool {
+        let same_node = match (self, other) {
+            (Expression::Infix(left), Expression::Infix(right)) => left.operator == right.operator,
+            (Expression::Prefix(left), Expression::Prefix(right)) => left.operator == right.operator,
+            (Expression::Postfix(left), Expression::Postfix(right)) => left.operator == right.operator,
+            (Expression::Grouping(_), Expression::Grouping(_)) => true,
+            (Expression::Tuple(_), Expression::Tuple(_)) => true,
+            (Expression::Array(_), Expression::Array(_)) => true,
+            (Expression::Block(left), Expression::Block(right)) => left.tail.is_some() == right.tail.is_some(),
+            (Expression::Literal(left), Expression::Literal(right)) => left.value == right.value,
+            (Expression::TemplateString(left), Expression::TemplateString(right)) => {
+                same_strings(&left.parts, &right.parts)
+            }
+            (Expression::Command(left), Expression::Command(right)) => same_strings(&left.parts, &right.parts),
+            (Expression::Identifier(left), Expression::Identifier(right)) => left.name == right.name,
+            (Expression::Call(_), Expression::Call(_)) => true,
+            (Expression::Member(left), Expression::Member(right)) => left.property.name == right.property.name,
+            (Expression::Index(_), Expression::Index(_)) => true,
+            (Expression::If(left), Expression::If(right)) => {
+                left.conditions.len() == right.conditions.len() && left.default.is_some() == right.default.is_some()
+            }
+            (Expression::Loop(left), Expression::Loop(right)) => same_label(&left.label, &right.label),
+            (Expression::While(left), Expression::While(right)) => same_label(&left.label, &right.label),
+            (Expression::For(left), Expression::For(right)) => {
+                same_label(&left.label, &right.label) && left.binding.name == right.binding.name
+            }
+            (Expression::Break(left), Expression::Break(right)) => same_label(&left.label, &right.label),
+            (Expression::Continue(left), Expression::Continue(right)) => same_label(&left.label, &right.label),
+            (Expression::Return(_), Expression::Return(_)) => true,
+            (Expression::FunctionDeclaration(left), Expression::FunctionDeclaration(right)) => {
+                // Types are compared as written, as they have no subexpressions
+                let signature = |parameters: &[Parameter]| -> Vec<(String, Option<String>)> {
+                    parameters
+                        .iter()
+                        .map(|parameter| {
+                            (parameter.name.name.clone(), parameter.annotation.as_ref().map(|annotation| annotation.to_string()))
+                        })
+                        .collect()
+                };
+
+                let name = |function: &FunctionDeclarationExpression| function.name.as_ref().map(|name| name.name.clone());
+
+                name(left) == name(right) && signature(&left.parameters) == signature(&right.parameters)
+            }
+            (Expression::Match(left), Expression::Match(right)) => {
+                left.arms.len() == right.arms.len()
+                    && left.arms.iter().zip(right.arms.iter()).all(|(left, right)| {
+                        match (&*left.pattern, &*right.pattern) {
+                            (MatchPattern::Literal(left), MatchPattern::Literal(right)) => left.value == right.value,
+                            (MatchPattern::Identifier(left), MatchPattern::Identifier(right)) => left.name == right.name,
+                            _ => false,
+                        }
+                    })
+            }
+            _ => false,
+        };
+
+        // Which child is which is determined by the node, e.g. how many conditions an if has
+        let (children, other_children) = (self.children(), other.children());
+
+        same_node
+            && children.len() == other_children.len()
+            && children.iter().zip(other_children).all(|(child, other)| child.structurally_eq(other))
+    }
+
+    /// Whether the expression can be assigned to, i.e. is an identifier, a member, an index or an
+    /// array pattern of those, e.g. `[a, b.c, d[0]] = xs`.
+    pub fn is_assignable(&self) -> bool {
+        match self {
+            Expression::Identifier(_) | Expression::Member(_) | Expression::Index(_) => true,
+            Expression::Array(array) => array.elements.iter().all(Expression::is_assignable),
+            _ => false,
+        }
+    }
+
+    /// The value of a numeric literal under any number of prefix `-` and `+` operators or
+    /// parentheses, e.g. `-3` is `-3.0`. None for anything else, such as `-x`.
+    pub fn as_const_number(&self) -> Option<f64> {
+        match self {
+            Expression::Literal(literal) => literal.as_number(),
+            Expression::Grouping(grouping) => grouping.expression.as_const_number(),
+            Expression::Prefix(prefix) => match prefix.operator {
+                PrefixOperatorKind::Minus => prefix.right.as_const_number().map(|value| -value),
+                PrefixOperatorKind::Plus => prefix.right.as_const_number(),
+                PrefixOperatorKind::Bang => None,
+            },
+            _ => None,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct LiteralExpression {
     pub span: Box<Span>,
     pub value: Box<LiteralExpressionValue>,
 }
 
+impl LiteralExpression {
+    pub fn as_number(&self) -> Option<f64> {
+        self.value.as_number()
+    }
+
+    pub fn as_string(&self) -> Option<&str> {
+        self.value.as_string()
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        self.value.as_bool()
+    }
+}
+
 impl FromToken for LiteralExpression {
     fn from_token(token: &Token) -> Result<Self, ParserError> {
         Ok(Self {
@@ -77,11 +405,42 @@ impl FromToken for LiteralExpression {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum LiteralExpressionValue {
     String(String),
-    Number(f64),
+    /// An integer, along with its source text.
+    Integer { value: i64, raw: String },
+    /// A floating point number, along with its source text which is exact where the value may
+    /// have lost precision.
+    Float { value: f64, raw: String },
     Boolean(bool),
+    Char(char),
+}
+
+impl LiteralExpressionValue {
+    /// The value of an integer or float literal. Integers beyond 2^53 lose precision.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Integer { value, .. } => Some(*value as f64),
+            Self::Float { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
 }
 
 impl FromToken for LiteralExpressionValue {
@@ -94,12 +453,10 @@ impl FromToken for LiteralExpressionValue {
                     unreachable!("Token of kind String must have a value of type String");
                 }
             }
-            TokenKind::Number => {
-                if let TokenValue::Number(value) = token.value {
-                    Ok(Self::Number(value))
-                } else {
-                    unreachable!("Token of kind Number must have a value of type Number");
-                }
+            TokenKind::Number => match &token.value {
+                TokenValue::Integer { value, raw } => Ok(Self::Integer { value: *value, raw: raw.clone() }),
+                TokenValue::Number { value, raw } => Ok(Self::Float { value: *value, raw: raw.clone() }),
+                _ => unreachable!("Token of kind Number must have a value of type Integer or Number"),
             },
             TokenKind::Boolean => {
                 if let TokenValue::Boolean(value) = token.value {
@@ -108,15 +465,56 @@ impl FromToken for LiteralExpressionValue {
                     unreachable!("Token of kind Boolean must have a value of type Boolean");
                 }
             },
-            kind => Err(ParserError {
-                message: format!("Token of kind {kind} is not a valid literal expression"),
-                position: token.start,
-            }),
+            TokenKind::Char => match token.value {
+                TokenValue::Char(value) => Ok(Self::Char(value)),
+                _ => unreachable!("Token of kind Char must have a value of type Char"),
+            },
+            kind => Err(ParserError::new(format!("Token of kind {kind} is not a valid literal expression"), token.start)),
         }
     }
 }
 
-#[derive(Debug)]
+/// A string with embedded expressions, e.g. `"Hello ${name}!"`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TemplateStringExpression {
+    pub span: Box<Span>,
+    pub parts: Box<Vec<TemplatePart>>,
+}
+
+/// A shell command, e.g. `$ echo ${name}`. The parts are the command as cleaned up by the lexer,
+/// see `CommandValue::command`, split around its interpolated expressions.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CommandExpression {
+    pub span: Box<Span>,
+    pub parts: Box<Vec<TemplatePart>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TemplatePart {
+    String(String),
+    Expression(Expression),
+}
+
+/// Whether the parts have the same strings in the same places, ignoring the expressions.
+fn same_strings(left: &[TemplatePart], right: &[TemplatePart]) -> bool {
+    let strings = |parts: &[TemplatePart]| -> Vec<Option<String>> {
+        parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::String(value) => Some(value.clone()),
+                TemplatePart::Expression(_) => None,
+            })
+            .collect()
+    };
+
+    strings(left) == strings(right)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InfixExpression {
     pub span: Box<Span>,
     pub left: Box<Expression>,
@@ -124,7 +522,8 @@ pub struct InfixExpression {
     pub right: Box<Expression>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum InfixOperatorKind {
     Equals,
     EqualsEquals,
@@ -135,12 +534,19 @@ pub enum InfixOperatorKind {
     GreaterThan,
     GreaterThanEquals,
     GreaterThanGreaterThan,
+    /// Unsigned right shift, which fills the vacated bits with zeros rather than the sign bit.
+    GreaterThanGreaterThanGreaterThan,
+    /// Unsigned right shift assignment, `a >>>= b`.
+    GreaterThanGreaterThanGreaterThanEquals,
     Ampersand,
     AmpersandAmpersand,
+    /// Logical assignment, `a &&= b`, which only evaluates and assigns `b` if `a` is truthy.
+    AmpersandAmpersandEquals,
     Pipe,
     PipePipe,
+    /// Logical assignment, `a ||= b`, which only evaluates and assigns `b` if `a` is falsy.
+    PipePipeEquals,
     ColonEquals,
-    Dot,
     DotDot,
     Plus,
     Minus,
@@ -151,6 +557,35 @@ pub enum InfixOperatorKind {
 }
 
 impl InfixOperatorKind {
+    /// Every infix operator, in declaration order.
+    pub const ALL: [InfixOperatorKind; 25] = [
+        InfixOperatorKind::Equals,
+        InfixOperatorKind::EqualsEquals,
+        InfixOperatorKind::BangEquals,
+        InfixOperatorKind::LessThan,
+        InfixOperatorKind::LessThanEquals,
+        InfixOperatorKind::LessThanLessThan,
+        InfixOperatorKind::GreaterThan,
+        InfixOperatorKind::GreaterThanEquals,
+        InfixOperatorKind::GreaterThanGreaterThan,
+        InfixOperatorKind::GreaterThanGreaterThanGreaterThan,
+        InfixOperatorKind::GreaterThanGreaterThanGreaterThanEquals,
+        InfixOperatorKind::Ampersand,
+        InfixOperatorKind::AmpersandAmpersand,
+        InfixOperatorKind::AmpersandAmpersandEquals,
+        InfixOperatorKind::Pipe,
+        InfixOperatorKind::PipePipe,
+        InfixOperatorKind::PipePipeEquals,
+        InfixOperatorKind::ColonEquals,
+        InfixOperatorKind::DotDot,
+        InfixOperatorKind::Plus,
+        InfixOperatorKind::Minus,
+        InfixOperatorKind::Slash,
+        InfixOperatorKind::Star,
+        InfixOperatorKind::Caret,
+        InfixOperatorKind::Percent,
+    ];
+
     pub fn try_from_token(token: &Token) -> Option<Self> {
         match token.kind {
             TokenKind::Equals => Some(Self::Equals),
@@ -162,12 +597,15 @@ impl InfixOperatorKind {
             TokenKind::GreaterThan => Some(Self::GreaterThan),
             TokenKind::GreaterThanEquals => Some(Self::GreaterThanEquals),
             TokenKind::GreaterThanGreaterThan => Some(Self::GreaterThanGreaterThan),
+            TokenKind::GreaterThanGreaterThanGreaterThan => Some(Self::GreaterThanGreaterThanGreaterThan),
+            TokenKind::GreaterThanGreaterThanGreaterThanEquals => Some(Self::GreaterThanGreaterThanGreaterThanEquals),
             TokenKind::Ampersand => Some(Self::Ampersand),
             TokenKind::AmpersandAmpersand => Some(Self::AmpersandAmpersand),
+            TokenKind::AmpersandAmpersandEquals => Some(Self::AmpersandAmpersandEquals),
             TokenKind::Pipe => Some(Self::Pipe),
             TokenKind::PipePipe => Some(Self::PipePipe),
+            TokenKind::PipePipeEquals => Some(Self::PipePipeEquals),
             TokenKind::ColonEquals => Some(Self::ColonEquals),
-            TokenKind::Dot => Some(Self::Dot),
             TokenKind::DotDot => Some(Self::DotDot),
             TokenKind::Plus => Some(Self::Plus),
             TokenKind::Minus => Some(Self::Minus),
@@ -179,67 +617,159 @@ impl InfixOperatorKind {
         }
     }
 
+    /// Binding powers from loosest to tightest. Assignments and `^` (power) are right associative,
+    /// everything else is left associative.
     pub fn binding_power(&self) -> (u8, u8) {
         match self {
-            InfixOperatorKind::Equals => (2, 1),
-            InfixOperatorKind::EqualsEquals => todo!(),
-            InfixOperatorKind::BangEquals => todo!(),
-            InfixOperatorKind::LessThan => todo!(),
-            InfixOperatorKind::LessThanEquals => todo!(),
-            InfixOperatorKind::LessThanLessThan => todo!(),
-            InfixOperatorKind::GreaterThan => todo!(),
-            InfixOperatorKind::GreaterThanEquals => todo!(),
-            InfixOperatorKind::GreaterThanGreaterThan => todo!(),
-            InfixOperatorKind::Ampersand => todo!(),
-            InfixOperatorKind::AmpersandAmpersand => todo!(),
-            InfixOperatorKind::Pipe => todo!(),
-            InfixOperatorKind::PipePipe => todo!(),
-            InfixOperatorKind::ColonEquals => (2, 1),
-            InfixOperatorKind::Dot => (7, 8),
-            InfixOperatorKind::DotDot => todo!(),
-            InfixOperatorKind::Plus | InfixOperatorKind::Minus => (3, 4),
-            InfixOperatorKind::Slash | InfixOperatorKind::Star => (5, 6),
-            InfixOperatorKind::Caret => todo!(),
-            InfixOperatorKind::Percent => todo!(),
+            InfixOperatorKind::Equals
+            | InfixOperatorKind::ColonEquals
+            | InfixOperatorKind::GreaterThanGreaterThanGreaterThanEquals
+            | InfixOperatorKind::AmpersandAmpersandEquals
+            | InfixOperatorKind::PipePipeEquals => (2, 1),
+            InfixOperatorKind::DotDot => (3, 4),
+            InfixOperatorKind::PipePipe => (5, 6),
+            InfixOperatorKind::AmpersandAmpersand => (7, 8),
+            InfixOperatorKind::EqualsEquals | InfixOperatorKind::BangEquals => (9, 10),
+            InfixOperatorKind::LessThan
+            | InfixOperatorKind::LessThanEquals
+            | InfixOperatorKind::GreaterThan
+            | InfixOperatorKind::GreaterThanEquals => (11, 12),
+            InfixOperatorKind::Pipe => (13, 14),
+            InfixOperatorKind::Ampersand => (15, 16),
+            InfixOperatorKind::LessThanLessThan
+            | InfixOperatorKind::GreaterThanGreaterThan
+            | InfixOperatorKind::GreaterThanGreaterThanGreaterThan => (17, 18),
+            InfixOperatorKind::Plus | InfixOperatorKind::Minus => (19, 20),
+            InfixOperatorKind::Slash | InfixOperatorKind::Star | InfixOperatorKind::Percent => (21, 22),
+            // Binds tighter than prefix operators, so `-2 ^ 2` is `-(2 ^ 2)`
+            InfixOperatorKind::Caret => (25, 24),
+        }
+    }
+
+    /// Whether the operator assigns to its left operand, e.g. `=` or `||=`.
+    pub fn is_assignment(&self) -> bool {
+        matches!(
+            self,
+            InfixOperatorKind::Equals
+                | InfixOperatorKind::ColonEquals
+                | InfixOperatorKind::GreaterThanGreaterThanGreaterThanEquals
+                | InfixOperatorKind::AmpersandAmpersandEquals
+                | InfixOperatorKind::PipePipeEquals
+        )
+    }
+
+    /// The source text of the operator, e.g. `&&`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InfixOperatorKind::Equals => "=",
+            InfixOperatorKind::EqualsEquals => "==",
+            InfixOperatorKind::BangEquals => "!=",
+            InfixOperatorKind::LessThan => "<",
+            InfixOperatorKind::LessThanEquals => "<=",
+            InfixOperatorKind::LessThanLessThan => "<<",
+            InfixOperatorKind::GreaterThan => ">",
+            InfixOperatorKind::GreaterThanEquals => ">=",
+            InfixOperatorKind::GreaterThanGreaterThan => ">>",
+            InfixOperatorKind::GreaterThanGreaterThanGreaterThan => ">>>",
+            InfixOperatorKind::GreaterThanGreaterThanGreaterThanEquals => ">>>=",
+            InfixOperatorKind::Ampersand => "&",
+            InfixOperatorKind::AmpersandAmpersand => "&&",
+            InfixOperatorKind::AmpersandAmpersandEquals => "&&=",
+            InfixOperatorKind::Pipe => "|",
+            InfixOperatorKind::PipePipe => "||",
+            InfixOperatorKind::PipePipeEquals => "||=",
+            InfixOperatorKind::ColonEquals => ":=",
+            InfixOperatorKind::DotDot => "..",
+            InfixOperatorKind::Plus => "+",
+            InfixOperatorKind::Minus => "-",
+            InfixOperatorKind::Slash => "/",
+            InfixOperatorKind::Star => "*",
+            InfixOperatorKind::Caret => "^",
+            InfixOperatorKind::Percent => "%",
         }
     }
 }
 
-#[derive(Debug)]
+impl Display for InfixOperatorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum PostfixOperatorKind {
     BraceSquareOpen,
     BraceRoundOpen,
+    Dot,
+    /// Only postfix when written directly after its operand and not directly before another,
+    /// e.g. `a!` but not `a !b` or `a!b`.
+    Bang,
 }
 
 impl PostfixOperatorKind {
+    /// Every postfix operator, in declaration order.
+    pub const ALL: [PostfixOperatorKind; 4] = [
+        PostfixOperatorKind::BraceSquareOpen,
+        PostfixOperatorKind::BraceRoundOpen,
+        PostfixOperatorKind::Dot,
+        PostfixOperatorKind::Bang,
+    ];
+
     pub fn try_from_token(token: &Token) -> Option<Self> {
         match token.kind {
             TokenKind::BraceSquareOpen => Some(Self::BraceSquareOpen),
             TokenKind::BraceRoundOpen => Some(Self::BraceRoundOpen),
+            TokenKind::Dot => Some(Self::Dot),
+            TokenKind::Bang => Some(Self::Bang),
             _ => None,
         }
     }
 
     pub fn postfix_binding_power(&self) -> (u8, ()) {
-        (10, ())
+        (27, ())
+    }
+
+    /// The source text of the operator, or of its opening bracket for indexing and calls.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostfixOperatorKind::BraceSquareOpen => "[",
+            PostfixOperatorKind::BraceRoundOpen => "(",
+            PostfixOperatorKind::Dot => ".",
+            PostfixOperatorKind::Bang => "!",
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PostfixExpression {
+    pub span: Box<Span>,
+    pub operator: PostfixOperatorKind,
+    pub left: Box<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct PrefixExpression {
     pub span: Box<Span>,
     pub operator: PrefixOperatorKind,
     pub right: Box<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum PrefixOperatorKind {
     Bang,
+    /// Not produced by the parser, which parses `+x` as `x`.
     Plus,
     Minus,
 }
 
 impl PrefixOperatorKind {
+    /// Every prefix operator, in declaration order.
+    pub const ALL: [PrefixOperatorKind; 3] = [PrefixOperatorKind::Bang, PrefixOperatorKind::Plus, PrefixOperatorKind::Minus];
+
     pub fn try_from_token(token: &Token) -> Option<Self> {
         match token.kind {
             TokenKind::Bang => Some(Self::Bang),
@@ -250,50 +780,191 @@ impl PrefixOperatorKind {
     }
 
     pub fn prefix_binding_power(&self) -> ((), u8) {
-        ((), 8)
+        ((), 23)
+    }
+
+    /// The source text of the operator, e.g. `!`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrefixOperatorKind::Bang => "!",
+            PrefixOperatorKind::Plus => "+",
+            PrefixOperatorKind::Minus => "-",
+        }
+    }
+}
+
+impl Display for PrefixOperatorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct CallExpression {
     pub span: Box<Span>,
     pub callee: Box<Expression>,
     pub arguments: Box<Vec<Expression>>,
 }
 
-#[derive(Debug)]
+/// Access of a named member, e.g. `a.b`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MemberExpression {
+    pub span: Box<Span>,
+    pub object: Box<Expression>,
+    pub property: Box<IdentifierExpression>,
+}
+
+/// Access of an element by its index or key, e.g. `a[0]`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct IndexExpression {
+    pub span: Box<Span>,
+    pub object: Box<Expression>,
+    pub index: Box<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IfExpression {
     pub span: Box<Span>,
     pub conditions: Box<Vec<IfCondition>>,
     pub default: Option<Box<IfDefault>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IfCondition {
     pub span: Box<Span>,
     pub condition: Box<Expression>,
     pub consequence: Box<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IfDefault {
     pub span: Box<Span>,
     pub consequence: Box<Expression>,
 }
 
-#[derive(Debug)]
+/// A multi-way branch, e.g. `match x { 1 => a, other => b }`. Arms are tried in order.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MatchExpression {
+    pub span: Box<Span>,
+    pub scrutinee: Box<Expression>,
+    pub arms: Box<Vec<MatchArm>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MatchArm {
+    pub span: Box<Span>,
+    pub pattern: Box<MatchPattern>,
+    pub expression: Box<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum MatchPattern {
+    /// Matches a value equal to the literal.
+    Literal(Box<LiteralExpression>),
+    /// Matches any value, binding it to the identifier.
+    Identifier(Box<IdentifierExpression>),
+}
+
+impl MatchPattern {
+    pub fn span(&self) -> Span {
+        match self {
+            MatchPattern::Literal(pattern) => *pattern.span,
+            MatchPattern::Identifier(pattern) => *pattern.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct BlockExpression {
     pub span: Box<Span>,
-    pub expressions: Box<Vec<Expression>>,
+    pub statements: Box<Vec<Expression>>,
+    /// The final expression, whose value is the value of the block. None if the block is empty or
+    /// its last expression is followed by a semicolon, e.g. `{ a; }`.
+    pub tail: Option<Box<Expression>>,
+}
+
+/// The label of a loop, e.g. `'outer` in `'outer: loop {}`, or the loop a `break` or `continue`
+/// refers to.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Label {
+    pub span: Box<Span>,
+    /// The name without the leading `'`.
+    pub name: String,
 }
 
-#[derive(Debug)]
+fn same_label(left: &Option<Box<Label>>, right: &Option<Box<Label>>) -> bool {
+    left.as_ref().map(|label| &label.name) == right.as_ref().map(|label| &label.name)
+}
+
+/// `loop { ... }`, which repeats until broken out of.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LoopExpression {
+    pub span: Box<Span>,
+    pub label: Option<Box<Label>>,
+    /// Always a block.
+    pub body: Box<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WhileExpression {
+    pub span: Box<Span>,
+    pub label: Option<Box<Label>>,
+    pub condition: Box<Expression>,
+    /// Always a block.
+    pub body: Box<Expression>,
+}
+
+/// `for item in items { ... }`, which runs the body with `item` bound to each item in turn.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ForExpression {
+    pub span: Box<Span>,
+    pub label: Option<Box<Label>>,
+    pub binding: Box<IdentifierExpression>,
+    pub iterable: Box<Expression>,
+    /// Always a block.
+    pub body: Box<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct BreakExpression {
     pub span: Box<Span>,
+    /// The loop to break out of, or the innermost if None.
+    pub label: Option<Box<Label>>,
     pub expression: Option<Box<Expression>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ContinueExpression {
+    pub span: Box<Span>,
+    /// The loop to continue, or the innermost if None.
+    pub label: Option<Box<Label>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ReturnExpression {
+    pub span: Box<Span>,
+    pub value: Option<Box<Expression>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IdentifierExpression {
     pub span: Box<Span>,
     pub name: String,
@@ -301,25 +972,336 @@ pub struct IdentifierExpression {
 
 impl FromToken for IdentifierExpression {
     fn from_token(token: &Token) -> Result<Self, ParserError> {
-        assert!(token.kind == TokenKind::Identifier);
-        Ok(
-            IdentifierExpression {
+        match (&token.kind, &token.value) {
+            (TokenKind::Identifier, TokenValue::String(name)) => Ok(IdentifierExpression {
                 span: Box::new(Span::new(token.start, token.end)),
-                name: unwrap_token_value!(String, &token.value).to_string(),
-            }
-        )
+                name: name.to_string(),
+            }),
+            (kind, _) => Err(ParserError::new(format!("Token of kind {kind} is not a valid identifier"), token.start)),
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct GroupingExpression {
     pub span: Box<Span>,
     pub expression: Box<Expression>,
 }
 
-#[derive(Debug)]
+/// Comma separated expressions in round braces, e.g. `(a, b)`. A single element tuple is written
+/// with a trailing comma, `(a,)`, to distinguish it from a grouping. The empty tuple, `()`, is the
+/// unit value.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TupleExpression {
+    pub span: Box<Span>,
+    pub elements: Box<Vec<Expression>>,
+}
+
+/// A list of values, e.g. `[1, 2, 3]`. As the target of an assignment it's a pattern which
+/// destructures the assigned value, e.g. `[a, [b, c]] = xs`, and may only contain expressions
+/// which can be assigned to. See `Expression::is_assignable`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ArrayExpression {
+    pub span: Box<Span>,
+    pub elements: Box<Vec<Expression>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct FunctionDeclarationExpression {
     pub span: Box<Span>,
-    pub parameters: Box<Vec<IdentifierExpression>>,
+    /// The name of a function declared as `fn name(...)`, or None for an anonymous function.
+    pub name: Option<Box<IdentifierExpression>>,
+    pub parameters: Box<Vec<Parameter>>,
     pub body: Box<Expression>,
 }
+
+/// A function parameter with an optional type, e.g. `a` or `a: Number`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Parameter {
+    pub span: Box<Span>,
+    pub name: Box<IdentifierExpression>,
+    pub annotation: Option<Box<TypeExpression>>,
+}
+
+/// The type of a parameter, e.g. `Number` or `[]String`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TypeExpression {
+    /// A type referred to by name, e.g. `Number`.
+    Named(Box<IdentifierExpression>),
+    /// A list of elements of the same type, e.g. `[]Number`.
+    Array(Box<ArrayTypeExpression>),
+}
+
+impl TypeExpression {
+    pub fn span(&self) -> Span {
+        match self {
+            TypeExpression::Named(expression) => *expression.span,
+            TypeExpression::Array(expression) => *expression.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ArrayTypeExpression {
+    pub span: Box<Span>,
+    pub element: Box<TypeExpression>,
+}
+
+#[cfg(test)]
+mod tests {
+    use lexer::default_lexer;
+
+    use super::{Expression, InfixOperatorKind, LiteralExpressionValue, PrefixOperatorKind};
+
+    fn expression(source: &str) -> Expression {
+        let mut program = crate::parse_source(source).expect("source should parse");
+        program.ast.remove(0)
+    }
+
+    fn literal(source: &str) -> LiteralExpressionValue {
+        match expression(source) {
+            Expression::Literal(literal) => *literal.value,
+            expression => panic!("expected a literal, got {expression:?}"),
+        }
+    }
+
+    #[test]
+    fn walk_mut_renames_identifiers_throughout_a_nested_program() {
+        let source = "foo = 1\nfn f(a) { if foo { [foo, g(foo.x, a)] } }\nwhile foo > 0 { foo = foo - (foo) }\n";
+        let mut program = crate::parse_source(source).unwrap();
+
+        program.walk_mut(|expression| {
+            if let Expression::Identifier(identifier) = expression {
+                if identifier.name == "foo" {
+                    identifier.name = "bar".to_string();
+                }
+            }
+        });
+
+        let renamed = crate::parse_source(&source.replace("foo", "bar")).unwrap();
+        assert_eq!(program.to_string(), renamed.to_string());
+        assert!(program.ast.iter().zip(&renamed.ast).all(|(left, right)| left.structurally_eq(right)));
+    }
+
+    #[test]
+    fn walk_visits_parents_first_and_walk_mut_children_first() {
+        let mut program = crate::parse_source("a + -b").unwrap();
+
+        let mut visited = vec![];
+        program.walk(|expression| visited.push(expression.kind_name()));
+        assert_eq!(visited, ["infix", "identifier", "prefix", "identifier"]);
+
+        let mut visited = vec![];
+        program.walk_mut(|expression| visited.push(expression.kind_name()));
+        assert_eq!(visited, ["identifier", "identifier", "prefix", "infix"]);
+    }
+
+    #[test]
+    fn displays_each_operator_as_its_source_text() {
+        let infix: Vec<String> = InfixOperatorKind::ALL.iter().map(ToString::to_string).collect();
+        assert_eq!(infix, [
+            "=", "==", "!=", "<", "<=", "<<", ">", ">=", ">>", ">>>", ">>>=", "&", "&&", "&&=", "|", "||", "||=", ":=", "..", "+", "-",
+            "/", "*", "^", "%",
+        ]);
+
+        let prefix: Vec<String> = PrefixOperatorKind::ALL.iter().map(ToString::to_string).collect();
+        assert_eq!(prefix, ["!", "+", "-"]);
+    }
+
+    #[test]
+    fn operator_text_lexes_back_to_the_operator() {
+        for operator in InfixOperatorKind::ALL {
+            let tokens = default_lexer().lex(operator.as_str()).tokens;
+            assert_eq!(tokens.len(), 1, "{operator}");
+            assert_eq!(InfixOperatorKind::try_from_token(&tokens[0]), Some(operator));
+        }
+
+        for operator in PrefixOperatorKind::ALL {
+            let tokens = default_lexer().lex(operator.as_str()).tokens;
+            assert_eq!(tokens.len(), 1, "{operator}");
+            assert_eq!(PrefixOperatorKind::try_from_token(&tokens[0]), Some(operator));
+        }
+    }
+
+    fn children(source: &str) -> Vec<String> {
+        expression(source).children().into_iter().map(Expression::to_string).collect()
+    }
+
+    #[test]
+    fn lists_the_direct_children_in_source_order() {
+        assert_eq!(children("a + b * c"), ["a", "b * c"]);
+        assert_eq!(children("f(x, 1 + 2)"), ["f", "x", "1 + 2"]);
+        assert_eq!(children("if a { 1 } else if b { 2 } else { 3 }"), ["a", "{ 1 }", "b", "{ 2 }", "{ 3 }"]);
+        assert_eq!(children("x[i]"), ["x", "i"]);
+        assert!(children("x").is_empty());
+    }
+
+    #[test]
+    fn expressions_at_different_offsets_are_structurally_equal() {
+        let first = expression("f(a + 1, [b])");
+        let second = expression("   f( a+1 ,[ b ] )");
+
+        assert!(first.structurally_eq(&second));
+        assert!(second.structurally_eq(&first));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn structural_equality_compares_kinds_and_values() {
+        let first = expression("f(a + 1, [b])");
+
+        for other in ["f(a + 2, [b])", "f(a - 1, [b])", "g(a + 1, [b])", "f(a + 1, (b))", "f(a + 1)", "f(a + 1, [b], c)"] {
+            assert!(!first.structurally_eq(&expression(other)), "{other}");
+        }
+
+        assert!(!expression("1").structurally_eq(&expression("1.0")));
+        assert!(!expression("fn f() {}").structurally_eq(&expression("fn g() {}")));
+        assert!(expression("if a { 1 } else { 2 }").structurally_eq(&expression("if a{1}else{2}")));
+    }
+
+    #[test]
+    fn children_mut_rewrites_in_place() {
+        let mut call = expression("f(x, y)");
+
+        for child in call.children_mut().into_iter().skip(1) {
+            *child = expression("0");
+        }
+
+        assert_eq!(call.to_string(), "f(0, 0)");
+    }
+
+    #[test]
+    fn folds_signed_numeric_literals() {
+        assert_eq!(expression("-3").as_const_number(), Some(-3.0));
+        assert_eq!(expression("+3").as_const_number(), Some(3.0));
+        assert_eq!(expression("- -3").as_const_number(), Some(3.0));
+        assert_eq!(expression("(-(2.5))").as_const_number(), Some(-2.5));
+        assert_eq!(expression("3").as_const_number(), Some(3.0));
+    }
+
+    #[test]
+    fn does_not_fold_anything_else() {
+        for source in ["-x", "!3", "-\"a\"", "-true", "1 + 2", "-(1 - 2)"] {
+            assert_eq!(expression(source).as_const_number(), None, "{source}");
+        }
+    }
+
+    #[test]
+    fn parses_of_the_same_source_are_equal_and_clone_equal() {
+        let source = "fn add(a, b) { return a + b }\nx := [1, 2.5, \"s\"]\nif x {\n$ echo ${x}\n}";
+        let first = crate::parse_source(source).unwrap();
+        let second = crate::parse_source(source).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(first.clone(), second);
+        assert_eq!(first.ast[1].clone(), second.ast[1]);
+        assert_ne!(first, crate::parse_source("x := [1, 2.5]").unwrap());
+    }
+
+    #[test]
+    fn literal_accessors_return_only_their_variant() {
+        let cases = [
+            ("1", Some(1.0), None, None),
+            ("2.5", Some(2.5), None, None),
+            ("\"a\"", None, Some("a"), None),
+            ("true", None, None, Some(true)),
+            ("false", None, None, Some(false)),
+            ("'c'", None, None, None),
+        ];
+
+        for (source, number, string, bool) in cases {
+            let Expression::Literal(literal) = expression(source) else { panic!("expected a literal for {source}") };
+
+            assert_eq!(literal.as_number(), number, "{source}");
+            assert_eq!(literal.as_string(), string, "{source}");
+            assert_eq!(literal.as_bool(), bool, "{source}");
+            assert_eq!(literal.value.as_number(), number, "{source}");
+        }
+    }
+
+    #[test]
+    fn distinguishes_integer_and_float_literals() {
+        assert_eq!(literal("1"), LiteralExpressionValue::Integer { value: 1, raw: "1".to_string() });
+        assert_eq!(literal("0x1F"), LiteralExpressionValue::Integer { value: 31, raw: "0x1F".to_string() });
+        assert_eq!(literal("1.0"), LiteralExpressionValue::Float { value: 1.0, raw: "1.0".to_string() });
+        assert_eq!(literal("1e3"), LiteralExpressionValue::Float { value: 1000.0, raw: "1e3".to_string() });
+    }
+
+    #[test]
+    fn keeps_the_source_text_of_number_literals() {
+        assert_eq!(literal("9007199254740993"), LiteralExpressionValue::Integer {
+            value: 9007199254740993,
+            raw: "9007199254740993".to_string(),
+        });
+
+        let LiteralExpressionValue::Float { value, raw } = literal("9007199254740993.0") else { panic!("expected a float") };
+
+        assert_eq!(value, 9007199254740992.0);
+        assert_eq!(raw, "9007199254740993.0");
+
+        for source in ["9007199254740993.0", "0x1F", "1e3", "007"] {
+            assert_eq!(expression(source).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn overflowing_integer_literals_are_floats() {
+        assert_eq!(literal("9223372036854775807"), LiteralExpressionValue::Integer {
+            value: i64::MAX,
+            raw: "9223372036854775807".to_string(),
+        });
+        assert_eq!(literal("9223372036854775808"), LiteralExpressionValue::Float {
+            value: 9223372036854775808.0,
+            raw: "9223372036854775808".to_string(),
+        });
+    }
+
+    #[test]
+    fn is_assignable() {
+        for source in ["a", "a.b", "a[0]", "a.b[i + 1]", "f()[0]", "[a, b.c, d[0]]", "[[a], b]"] {
+            assert!(expression(source).is_assignable(), "{source} should be assignable");
+        }
+
+        for source in ["1", "f()", "a + b", "(a)", "[a, 1]", "\"s\"", "-a", "{ a }"] {
+            assert!(!expression(source).is_assignable(), "{source} shouldn't be assignable");
+        }
+    }
+
+    #[test]
+    fn parses_unsigned_right_shifts() {
+        let Expression::Infix(assignment) = expression("a >>>= b >>> 1") else { panic!("expected an infix expression") };
+
+        assert_eq!(assignment.operator, InfixOperatorKind::GreaterThanGreaterThanGreaterThanEquals);
+        assert!(assignment.operator.is_assignment());
+        assert_eq!(assignment.right.to_string(), "b >>> 1");
+        assert!(crate::parse_source("1 >>>= 2").is_err());
+        assert_eq!(expression("a >>> 1 + 2").to_string(), "a >>> 1 + 2");
+    }
+
+    #[test]
+    fn every_infix_operator_round_trips_through_its_token() {
+        for operator in InfixOperatorKind::ALL {
+            let source = format!("a {} b", operator.as_str());
+            let Expression::Infix(infix) = expression(&source) else { panic!("expected {source} to be infix") };
+
+            assert_eq!(infix.operator, operator);
+        }
+    }
+
+    #[test]
+    fn validates_assignment_targets() {
+        assert!(crate::parse_source("a[0] = 1").is_ok());
+        assert!(crate::parse_source("a.b[i] := 1").is_ok());
+        assert!(crate::parse_source("[a[0], b] = xs").is_ok());
+
+        let diagnostics = crate::parse_source("f() = 1").expect_err("source should fail to parse");
+        assert_eq!(diagnostics[0].message, "Cannot assign to this call expression");
+    }
+}
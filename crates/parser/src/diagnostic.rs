@@ -0,0 +1,315 @@
+use common::source_map::SourceMap;
+use lexer::reader_error::ReaderError;
+
+use crate::{parser_error::ParserError, span::Span};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Severity {
+    Error,
+    /// Something which is likely a mistake but doesn't stop the program from parsing.
+    Warning,
+}
+
+/// An error or warning from any stage of the pipeline, lexing or parsing.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[error("{message} at {}", span.start)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Where the error occurred. Errors which only know their position have an empty span.
+    pub span: Span,
+    /// Text printed after the underline, describing what's wrong at the span.
+    pub label: Option<String>,
+    /// Extra context printed below the source, e.g. how to fix the problem.
+    pub note: Option<String>,
+    /// Another region of source which explains the diagnostic, e.g. a bracket left open, and the
+    /// text printed after its underline.
+    pub secondary: Option<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            span,
+            label: None,
+            note: None,
+            secondary: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, label: impl Into<String>) -> Self {
+        self.secondary = Some((span, label.into()));
+        self
+    }
+
+    /// Formats the diagnostic in the style of rustc, with a location header, the source line with
+    /// the span underlined and labeled, and the note if there is one, e.g.
+    ///
+    /// ```text
+    /// error: Unexpected token of kind BraceRoundClose
+    ///  --> 1:5
+    ///   |
+    /// 1 | a = ) + 1
+    ///   |     ^ expected an expression
+    ///   = note: an assignment needs a value
+    /// ```
+    ///
+    /// Spans covering several lines are underlined to the end of their first line. Empty spans are
+    /// underlined with a single caret. The secondary span is underlined with `-` rather than `^`,
+    /// below its own line if it's on a different line to the span.
+    pub fn render(&self, source: &str) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let source_map = SourceMap::new(source);
+        let primary = Underline::new(source, &source_map, self.span);
+        let mut underlines = vec![(&primary, '^', self.label.as_deref())];
+        let secondary = self.secondary.as_ref().map(|(span, label)| (Underline::new(source, &source_map, *span), label));
+
+        if let Some((underline, label)) = &secondary {
+            underlines.push((underline, '-', Some(label.as_str())));
+        }
+
+        // In source order, with the primary span first if both are on the same line
+        underlines.sort_by_key(|(underline, _, _)| underline.line);
+
+        let width = underlines.iter().map(|(underline, _, _)| underline.line.to_string().len()).max().unwrap_or(1);
+        let gutter = " ".repeat(width);
+        let mut output = String::new();
+        let mut previous_line = None;
+
+        output.push_str(&format!("{}: {}\n", severity, self.message));
+        output.push_str(&format!("{}--> {}:{}\n", gutter, primary.line, primary.column));
+        output.push_str(&format!("{} |", gutter));
+        for (underline, marker, label) in underlines {
+            // Lines between the spans are elided
+            if matches!(previous_line, Some(line) if underline.line > line + 1) {
+                output.push_str("\n...");
+            }
+            if previous_line != Some(underline.line) {
+                output.push_str(&format!("\n{:>width$} | {}", underline.line, underline.text));
+                previous_line = Some(underline.line);
+            }
+            output.push_str(&format!("\n{} | {}", gutter, underline.marks(marker, label)));
+        }
+        if let Some(note) = &self.note {
+            output.push_str(&format!("\n{} = note: {}", gutter, note));
+        }
+        output
+    }
+}
+
+/// The line a span starts on and the columns to underline on it.
+struct Underline<'a> {
+    line: usize,
+    column: usize,
+    text: &'a str,
+    width: usize,
+}
+
+impl<'a> Underline<'a> {
+    fn new(source: &'a str, source_map: &SourceMap, span: Span) -> Self {
+        let (line, column) = source_map.line_col(span.start);
+        let (end_line, end_column) = source_map.line_col(span.end);
+        let text = source_map.line_text(source, line);
+        let end_column = match end_line == line {
+            true => end_column,
+            false => text.chars().count() + 1,
+        };
+        // An empty span still gets a caret to point at, as does a multi-line span starting at the
+        // end of its first line
+        let width = match span.is_empty() {
+            true => 1,
+            false => end_column.saturating_sub(column).max(1),
+        };
+
+        Self { line, column, text, width }
+    }
+
+    /// The given marker repeated under the span, followed by the label if there is one.
+    fn marks(&self, marker: char, label: Option<&str>) -> String {
+        let mut output = " ".repeat(self.column - 1);
+
+        output.push_str(&marker.to_string().repeat(self.width));
+        if let Some(label) = label {
+            output.push_str(&format!(" {}", label));
+        }
+        output
+    }
+}
+
+impl From<ReaderError> for Diagnostic {
+    fn from(error: ReaderError) -> Self {
+        Self {
+            severity: Severity::Error,
+            span: error.span.unwrap_or(Span::start_from(error.position)),
+            message: error.message,
+            label: error.label,
+            note: None,
+            secondary: None,
+        }
+    }
+}
+
+impl From<ParserError> for Diagnostic {
+    fn from(error: ParserError) -> Self {
+        Self {
+            severity: Severity::Error,
+            span: error.span.unwrap_or(Span::start_from(error.position)),
+            message: error.message,
+            label: error.label,
+            note: None,
+            secondary: error.secondary.map(|secondary| *secondary),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lexer::{default_lexer, reader_error::ReaderError};
+
+    use super::Diagnostic;
+    use crate::{parse_source, parser_error::ParserError, span::Span};
+
+    #[test]
+    fn renders_errors_with_label_and_note() {
+        let diagnostic = parse_source("a = ) + 1").expect_err("source should fail to parse").remove(0);
+        let diagnostic = Diagnostic { label: Some("expected an expression".to_string()), ..diagnostic }
+            .with_note("an assignment needs a value");
+
+        assert_eq!(
+            diagnostic.render("a = ) + 1"),
+            "error: Unexpected ')'; no parenthesis is open\n --> 1:5\n  |\n1 | a = ) + 1\n  |     ^ expected an expression\n  = note: an assignment needs a value"
+        );
+    }
+
+    #[test]
+    fn renders_lexer_and_parser_errors_with_their_span_and_label() {
+        let source = "x = \"abc";
+
+        let error = ReaderError::new("Unterminated string", 4).with_span(Span::new(4, 8)).with_label("no closing quote");
+        assert_eq!(error.to_string(), "Reader error: Unterminated string at 4");
+        assert_eq!(
+            Diagnostic::from(error).render(source),
+            "error: Unterminated string\n --> 1:5\n  |\n1 | x = \"abc\n  |     ^^^^ no closing quote"
+        );
+        assert_eq!(
+            Diagnostic::from(default_lexer().lex(source).errors.remove(0)).render(source),
+            Diagnostic::from(ReaderError::new("Unterminated string", 4).with_span(Span::new(4, 8)).with_label("no closing quote"))
+                .render(source)
+        );
+
+        let error = ParserError::new("Expected expression", 0).with_span(Span::new(0, 1)).with_label("assigned here");
+        assert_eq!(error.to_string(), "Parser error: Expected expression at 0");
+        assert_eq!(
+            Diagnostic::from(error).render(source),
+            "error: Expected expression\n --> 1:1\n  |\n1 | x = \"abc\n  | ^ assigned here"
+        );
+    }
+
+    #[test]
+    fn renders_errors_without_a_span_at_their_position() {
+        let diagnostic = Diagnostic::from(ParserError::new("Unexpected end of file", 3));
+
+        assert_eq!(diagnostic.span, Span::start_from(3));
+        assert_eq!(diagnostic.render("a +"), "error: Unexpected end of file\n --> 1:4\n  |\n1 | a +\n  |    ^");
+    }
+
+    #[test]
+    fn renders_errors_after_a_unicode_line_separator_on_the_same_line() {
+        let source = "a\u{2028}\u{a0}@";
+        let diagnostic = parse_source(source).expect_err("source should fail to parse").remove(0);
+
+        assert_eq!(diagnostic.render(source), "error: Unexpected character '@'\n --> 1:4\n  |\n1 | a\u{2028}\u{a0}@\n  |    ^");
+
+        let source = "a\r\n@";
+        let diagnostic = parse_source(source).expect_err("source should fail to parse").remove(0);
+
+        assert!(diagnostic.render(source).contains(" --> 2:1\n"), "{}", diagnostic.render(source));
+    }
+
+    #[test]
+    fn renders_warnings() {
+        let diagnostic = Diagnostic::warning("Unused value", Span::new(0, 3));
+
+        assert_eq!(diagnostic.render("abc"), "warning: Unused value\n --> 1:1\n  |\n1 | abc\n  | ^^^");
+    }
+
+    #[test]
+    fn underlines_empty_and_multi_line_spans() {
+        let source = "one\ntwo three\nfour";
+
+        assert!(Diagnostic::warning("", Span::new(8, 8)).render(source).ends_with("2 | two three\n  |     ^"));
+        assert!(Diagnostic::warning("", Span::new(8, 16)).render(source).ends_with("2 | two three\n  |     ^^^^^"));
+    }
+
+    #[test]
+    fn marks_empty_spans_with_one_caret() {
+        assert_eq!(Diagnostic::warning("", Span::start_from(0)).render(""), "warning: \n --> 1:1\n  |\n1 | \n  | ^");
+        assert!(Diagnostic::warning("", Span::start_from(3)).render("a +").ends_with("1 | a +\n  |    ^"));
+        assert!(Diagnostic::warning("", Span::new(2, 1)).render("abc").ends_with("1 | abc\n  |   ^"));
+    }
+
+    #[test]
+    fn widens_the_gutter_for_long_line_numbers() {
+        let source = format!("{}x", "\n".repeat(9));
+
+        assert!(Diagnostic::warning("", Span::new(9, 10)).render(&source).ends_with(" --> 10:1\n   |\n10 | x\n   | ^"));
+    }
+
+    #[test]
+    fn renders_the_secondary_span_on_the_same_line() {
+        let source = "f(a }";
+        let diagnostic = parse_source(source).expect_err("source should fail to parse").remove(0);
+
+        assert_eq!(
+            diagnostic.render(source),
+            "error: Unexpected '}'; the '(' is still open\n --> 1:5\n  |\n1 | f(a }\n  |     ^\n  |  - this '(' isn't closed"
+        );
+    }
+
+    #[test]
+    fn renders_the_secondary_span_on_an_earlier_line() {
+        let source = "x = [1,\n  2,\n  )";
+        let diagnostic = parse_source(source).expect_err("source should fail to parse").remove(0);
+
+        assert_eq!(
+            diagnostic.render(source),
+            "error: Unexpected ')'; the '[' is still open\n --> 3:3\n  |\n1 | x = [1,\n  |     - this '[' isn't closed\n...\n3 |   )\n  |   ^"
+        );
+    }
+
+    #[test]
+    fn counts_columns_in_chars() {
+        assert!(Diagnostic::warning("", Span::new(2, 3)).render("éé!").ends_with("1 | éé!\n  |   ^"));
+    }
+
+    #[test]
+    fn places_the_caret_after_multibyte_chars() {
+        let source = "café + €";
+        let diagnostics = parse_source(source).expect_err("source should fail to parse");
+        let diagnostic = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.message == "Unexpected character '€'")
+            .expect("the lexer should reject '€'");
+
+        // Positions are char offsets, so the byte lengths of 'é' and '€' don't shift them
+        assert_eq!(diagnostic.span, Span::new(7, 8));
+        assert_eq!(diagnostic.render(source), "error: Unexpected character '€'\n --> 1:8\n  |\n1 | café + €\n  |        ^");
+    }
+}
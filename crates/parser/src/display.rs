@@ -0,0 +1,461 @@
+use std::fmt::{Display, Formatter, Result};
+
+use crate::ast::{
+    Expression, Label, LiteralExpression, LiteralExpressionValue, MatchPattern, PostfixOperatorKind,
+    Parameter, PrefixOperatorKind, Program, TemplatePart, TypeExpression,
+};
+
+/// Prints each statement on its own line. A statement is ended with a semicolon where the next
+/// would otherwise continue it, e.g. when the next starts with a prefix operator.
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let statements: Vec<String> = self.ast.iter().map(|expression| expression.to_string()).collect();
+
+        for (index, statement) in statements.iter().enumerate() {
+            f.write_str(statement)?;
+
+            match statements.get(index + 1) {
+                Some(next) if next.starts_with(['-', '+', '!', '(', '[']) => f.write_str(";\n")?,
+                Some(_) if statement.ends_with('\n') => {}
+                Some(_) => f.write_str("\n")?,
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Prints the expression as source which parses back to the same tree. Parentheses are only
+/// added where precedence requires them, so `(a + b) * c` keeps its parentheses, while an
+/// operand which needs them but has no grouping, e.g. after constant folding, gets them.
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Expression::Infix(infix) => {
+                let (l_bp, r_bp) = infix.operator.binding_power();
+
+                write_operand(f, &infix.left, needs_parentheses_before(&infix.left, l_bp))?;
+                write!(f, " {} ", infix.operator)?;
+                write_operand(f, &infix.right, needs_parentheses_after(&infix.right, r_bp))
+            }
+            Expression::Prefix(prefix) => {
+                let ((), r_bp) = prefix.operator.prefix_binding_power();
+
+                f.write_str(prefix.operator.as_str())?;
+                write_operand(f, &prefix.right, needs_parentheses_after(&prefix.right, r_bp))
+            }
+            Expression::Postfix(postfix) => {
+                write_operand(f, &postfix.left, needs_parentheses_before(&postfix.left, postfix.operator.postfix_binding_power().0))?;
+
+                match postfix.operator {
+                    PostfixOperatorKind::Bang => f.write_str("!"),
+                    PostfixOperatorKind::BraceRoundOpen | PostfixOperatorKind::BraceSquareOpen | PostfixOperatorKind::Dot => {
+                        unreachable!("Calls, indexing and member access are parsed into their own expressions")
+                    }
+                }
+            }
+            Expression::Grouping(grouping) => write!(f, "({})", grouping.expression),
+            Expression::Tuple(tuple) => match tuple.elements.as_slice() {
+                [element] => write!(f, "({},)", element),
+                elements => {
+                    f.write_str("(")?;
+                    write_list(f, elements)?;
+                    f.write_str(")")
+                }
+            },
+            Expression::Array(array) => {
+                f.write_str("[")?;
+                write_list(f, &array.elements)?;
+                f.write_str("]")
+            }
+            Expression::Block(block) => {
+                if block.statements.is_empty() && block.tail.is_none() {
+                    return f.write_str("{}");
+                }
+
+                f.write_str("{ ")?;
+
+                for statement in block.statements.iter() {
+                    write!(f, "{}; ", statement)?;
+                }
+
+                if let Some(tail) = &block.tail {
+                    write!(f, "{} ", tail)?;
+                }
+
+                f.write_str("}")
+            }
+            Expression::Literal(literal) => write_literal(f, &literal.value),
+            Expression::TemplateString(template) => {
+                f.write_str("\"")?;
+
+                for part in template.parts.iter() {
+                    match part {
+                        TemplatePart::String(value) => write_escaped(f, value, '"')?,
+                        TemplatePart::Expression(expression) => write!(f, "${{{}}}", expression)?,
+                    }
+                }
+
+                f.write_str("\"")
+            }
+            // Commands run to the end of the line, so the line is ended for whatever follows
+            Expression::Command(command) => {
+                f.write_str("$ ")?;
+
+                for part in command.parts.iter() {
+                    match part {
+                        TemplatePart::String(value) => f.write_str(value)?,
+                        TemplatePart::Expression(expression) => write!(f, "${{{}}}", expression)?,
+                    }
+                }
+
+                f.write_str("\n")
+            }
+            Expression::Identifier(identifier) => f.write_str(&identifier.name),
+            Expression::Call(call) => {
+                write_operand(f, &call.callee, needs_parentheses_before(&call.callee, PostfixOperatorKind::BraceRoundOpen.postfix_binding_power().0))?;
+                f.write_str("(")?;
+                write_list(f, &call.arguments)?;
+                f.write_str(")")
+            }
+            Expression::Index(index) => {
+                write_operand(f, &index.object, needs_parentheses_before(&index.object, PostfixOperatorKind::BraceSquareOpen.postfix_binding_power().0))?;
+                write!(f, "[{}]", index.index)
+            }
+            Expression::Member(member) => {
+                write_operand(f, &member.object, needs_parentheses_before(&member.object, PostfixOperatorKind::Dot.postfix_binding_power().0))?;
+                write!(f, ".{}", member.property.name)
+            }
+            Expression::If(expression) => {
+                for (index, condition) in expression.conditions.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(" else ")?;
+                    }
+
+                    f.write_str("if ")?;
+                    write_condition(f, &condition.condition)?;
+                    write!(f, " {}", condition.consequence)?;
+                }
+
+                if let Some(default) = &expression.default {
+                    write!(f, " else {}", default.consequence)?;
+                }
+
+                Ok(())
+            }
+            Expression::Loop(expression) => {
+                write_label(f, &expression.label)?;
+                write!(f, "loop {}", expression.body)
+            }
+            Expression::While(expression) => {
+                write_label(f, &expression.label)?;
+                f.write_str("while ")?;
+                write_condition(f, &expression.condition)?;
+                write!(f, " {}", expression.body)
+            }
+            Expression::For(expression) => {
+                write_label(f, &expression.label)?;
+                write!(f, "for {} in {} {}", expression.binding.name, expression.iterable, expression.body)
+            }
+            Expression::Break(expression) => {
+                f.write_str("break")?;
+
+                if let Some(label) = &expression.label {
+                    write!(f, " '{}", label.name)?;
+                }
+
+                match &expression.expression {
+                    Some(value) => write!(f, " {}", value),
+                    None => Ok(()),
+                }
+            }
+            Expression::Continue(expression) => match &expression.label {
+                Some(label) => write!(f, "continue '{}", label.name),
+                None => f.write_str("continue"),
+            },
+            Expression::Return(expression) => match &expression.value {
+                Some(value) => write!(f, "return {}", value),
+                None => f.write_str("return"),
+            },
+            Expression::FunctionDeclaration(function) => {
+                let parameters: Vec<String> = function.parameters.iter().map(Parameter::to_string).collect();
+
+                f.write_str("fn")?;
+
+                if let Some(name) = &function.name {
+                    write!(f, " {}", name.name)?;
+                }
+
+                write!(f, "({}) {}", parameters.join(", "), function.body)
+            }
+            Expression::Match(expression) => {
+                write!(f, "match {} ", expression.scrutinee)?;
+
+                if expression.arms.is_empty() {
+                    return f.write_str("{}");
+                }
+
+                f.write_str("{")?;
+
+                for (index, arm) in expression.arms.iter().enumerate() {
+                    f.write_str(if index == 0 { " " } else { ", " })?;
+
+                    match &*arm.pattern {
+                        MatchPattern::Literal(literal) => write_literal(f, &literal.value)?,
+                        MatchPattern::Identifier(identifier) => f.write_str(&identifier.name)?,
+                    }
+
+                    write!(f, " => {}", arm.expression)?;
+                }
+
+                f.write_str(" }")
+            }
+        }
+    }
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str(&self.name.name)?;
+
+        match &self.annotation {
+            Some(annotation) => write!(f, ": {}", annotation),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for TypeExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            TypeExpression::Named(name) => f.write_str(&name.name),
+            TypeExpression::Array(array) => write!(f, "[]{}", array.element),
+        }
+    }
+}
+
+/// Whether an operand followed by an operator with the given left binding power must be
+/// parenthesised so that the operator doesn't take part of it as its own operand.
+fn needs_parentheses_before(operand: &Expression, l_bp: u8) -> bool {
+    if ends_with_open_operand(operand) {
+        return true;
+    }
+
+    match operand {
+        Expression::Infix(infix) => l_bp >= infix.operator.binding_power().1,
+        Expression::Prefix(prefix) => l_bp >= prefix.operator.prefix_binding_power().1,
+        Expression::Literal(literal) if is_negative(literal) => {
+            l_bp >= PrefixOperatorKind::Minus.prefix_binding_power().1
+        }
+        // These end a statement, so can't be the first operand of one
+        Expression::If(_)
+        | Expression::Block(_)
+        | Expression::Match(_)
+        | Expression::Loop(_)
+        | Expression::While(_)
+        | Expression::For(_) => true,
+        Expression::FunctionDeclaration(function) => function.name.is_some(),
+        _ => false,
+    }
+}
+
+/// Whether an operand following an operator with the given right binding power must be
+/// parenthesised so that it isn't split by the operator.
+fn needs_parentheses_after(operand: &Expression, r_bp: u8) -> bool {
+    match operand {
+        Expression::Infix(infix) => infix.operator.binding_power().0 < r_bp,
+        _ => false,
+    }
+}
+
+/// Whether the expression ends with an operand which would extend over anything following it,
+/// e.g. the `x` of `return x`.
+fn ends_with_open_operand(expression: &Expression) -> bool {
+    match expression {
+        // A parenthesised operand is closed
+        Expression::Infix(infix) => {
+            !needs_parentheses_after(&infix.right, infix.operator.binding_power().1) && ends_with_open_operand(&infix.right)
+        }
+        Expression::Prefix(prefix) => {
+            !needs_parentheses_after(&prefix.right, prefix.operator.prefix_binding_power().1)
+                && ends_with_open_operand(&prefix.right)
+        }
+        Expression::Return(expression) => expression.value.is_some(),
+        Expression::Break(expression) => expression.expression.is_some(),
+        Expression::FunctionDeclaration(function) => !matches!(*function.body, Expression::Block(_)),
+        Expression::If(expression) => {
+            let last = match &expression.default {
+                Some(default) => &default.consequence,
+                None => &expression.conditions.last().expect("If expressions have a condition").consequence,
+            };
+
+            !matches!(**last, Expression::Block(_))
+        }
+        _ => false,
+    }
+}
+
+/// Folded constants may be negative literals, which are written like a prefix expression.
+fn is_negative(literal: &LiteralExpression) -> bool {
+    match &*literal.value {
+        LiteralExpressionValue::Integer { raw, .. } | LiteralExpressionValue::Float { raw, .. } => raw.starts_with('-'),
+        _ => false,
+    }
+}
+
+fn write_label(f: &mut Formatter<'_>, label: &Option<Box<Label>>) -> Result {
+    match label {
+        Some(label) => write!(f, "'{}: ", label.name),
+        None => Ok(()),
+    }
+}
+
+/// An unparenthesised assignment isn't allowed as the condition of an `if` or `while`.
+fn write_condition(f: &mut Formatter<'_>, condition: &Expression) -> Result {
+    let parentheses = matches!(condition, Expression::Infix(infix) if infix.operator.is_assignment());
+
+    write_operand(f, condition, parentheses)
+}
+
+fn write_operand(f: &mut Formatter<'_>, operand: &Expression, parentheses: bool) -> Result {
+    match parentheses {
+        true => write!(f, "({})", operand),
+        false => write!(f, "{}", operand),
+    }
+}
+
+fn write_list(f: &mut Formatter<'_>, expressions: &[Expression]) -> Result {
+    for (index, expression) in expressions.iter().enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+
+        write!(f, "{}", expression)?;
+    }
+
+    Ok(())
+}
+
+fn write_literal(f: &mut Formatter<'_>, value: &LiteralExpressionValue) -> Result {
+    match value {
+        LiteralExpressionValue::String(value) => {
+            f.write_str("\"")?;
+            write_escaped(f, value, '"')?;
+            f.write_str("\"")
+        }
+        LiteralExpressionValue::Integer { raw, .. } | LiteralExpressionValue::Float { raw, .. } => f.write_str(raw),
+        LiteralExpressionValue::Boolean(value) => write!(f, "{}", value),
+        LiteralExpressionValue::Char(value) => {
+            f.write_str("'")?;
+            write_escaped(f, &value.to_string(), '\'')?;
+            f.write_str("'")
+        }
+    }
+}
+
+/// Writes the text with the escapes understood by `lexer::escape::read_escape`. `$` is always
+/// escaped so that it isn't read as the start of an interpolation.
+fn write_escaped(f: &mut Formatter<'_>, value: &str, quote: char) -> Result {
+    for char in value.chars() {
+        match char {
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\0' => f.write_str("\\0")?,
+            '\\' => f.write_str("\\\\")?,
+            '$' => f.write_str("\\$")?,
+            char if char == quote => write!(f, "\\{}", char)?,
+            char if char.is_control() => write!(f, "\\u{{{:X}}}", char as u32)?,
+            char => write!(f, "{}", char)?,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::Expression, parse_source};
+
+    fn parse(source: &str) -> Expression {
+        let mut program = parse_source(source).expect("source should parse");
+        program.ast.remove(0)
+    }
+
+    /// Prints the expression and checks that the output parses back to the same tree.
+    fn round_trip(expression: &Expression) -> String {
+        let printed = expression.to_string();
+
+        assert!(parse(&printed).structurally_eq(expression), "{printed} parsed to a different tree");
+
+        printed
+    }
+
+    /// Removes every grouping, as constant folding or other rewrites may leave an operand which needs
+    /// parentheses without them.
+    fn without_groupings(mut expression: Expression) -> Expression {
+        expression.walk_mut(&mut |expression| {
+            if let Expression::Grouping(grouping) = expression {
+                *expression = (*grouping.expression).clone();
+            }
+        });
+
+        expression
+    }
+
+    #[test]
+    fn prints_source_as_written() {
+        for source in [
+            "a + b + c",
+            "a + (b + c)",
+            "(a + b) * c",
+            "a * b + c",
+            "2 ^ 3 ^ 4",
+            "-a ^ 2",
+            "a = b = c",
+            "!a.b(c)[d]",
+            "x := if a { 1 } else { 2 }",
+            "(if a { 1 } else { 2 }) + 3",
+            "f(a, b)[0].c!",
+            "a >>> 1 >= b && c || d",
+            "[a, b] = (1, 2)",
+        ] {
+            assert_eq!(round_trip(&parse(source)), source);
+        }
+    }
+
+    #[test]
+    fn prints_function_names_and_empty_matches() {
+        for source in ["fn f(a, b) { a }", "fn(a) { a }", "match x {}", "match x { 1 => 2, y => y }"] {
+            assert_eq!(round_trip(&parse(source)), source);
+        }
+    }
+
+    #[test]
+    fn adds_parentheses_only_where_precedence_requires_them() {
+        let cases = [
+            ("(a + b) * c", "(a + b) * c"),
+            ("a + (b * c)", "a + b * c"),
+            ("(a - b) - c", "a - b - c"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("2 ^ (3 ^ 4)", "2 ^ 3 ^ 4"),
+            ("(2 ^ 3) ^ 4", "(2 ^ 3) ^ 4"),
+            ("-(a + b)", "-(a + b)"),
+            ("(-a).b", "(-a).b"),
+            ("(a + b)(c)", "(a + b)(c)"),
+            ("(a + b)[0]", "(a + b)[0]"),
+            ("(fn f() {})()", "(fn f() {})()"),
+            ("a = (b = c)", "a = b = c"),
+        ];
+
+        for (source, expected) in cases {
+            let expression = without_groupings(parse(source));
+            let printed = expression.to_string();
+
+            assert_eq!(printed, expected, "{source}");
+            // The parentheses which were added are parsed as groupings
+            assert!(without_groupings(parse(&printed)).structurally_eq(&expression), "{printed} parsed to a different tree");
+        }
+    }
+}
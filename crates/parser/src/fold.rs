@@ -0,0 +1,189 @@
+use crate::ast::{
+    Expression, InfixOperatorKind, LiteralExpression, LiteralExpressionValue, PrefixOperatorKind, Program,
+};
+
+/// Evaluates infix and prefix expressions over numeric and boolean literals ahead of time, e.g. `2 * 3` becomes `6` and
+/// `!true` becomes `false`. Anything which can't be evaluated without running the program, such as
+/// identifiers, calls and commands, is left as is, as are strings. So are expressions which would fail at runtime,
+/// like division by zero or integer overflow, so that they still fail when the program runs.
+pub fn fold_constants(program: &mut Program) {
+    // Children are folded first so that nested constants collapse from the bottom up
+    program.walk_mut(fold_expression);
+}
+
+fn fold_expression(expression: &mut Expression) {
+    let value = match expression {
+        Expression::Infix(infix) => match (&*infix.left, &*infix.right) {
+            (Expression::Literal(left), Expression::Literal(right)) => {
+                fold_infix(&infix.operator, &left.value, &right.value)
+            }
+            // The right hand side is never evaluated, so it doesn't matter whether it is constant
+            (Expression::Literal(left), _) => match (&infix.operator, left.as_bool()) {
+                (InfixOperatorKind::AmpersandAmpersand, Some(false)) => Some(LiteralExpressionValue::Boolean(false)),
+                (InfixOperatorKind::PipePipe, Some(true)) => Some(LiteralExpressionValue::Boolean(true)),
+                _ => None,
+            },
+            _ => None,
+        },
+        Expression::Prefix(prefix) => match &*prefix.right {
+            Expression::Literal(right) => fold_prefix(&prefix.operator, &right.value),
+            _ => None,
+        },
+        Expression::Grouping(grouping) => match &*grouping.expression {
+            Expression::Literal(literal) => Some((*literal.value).clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    if let Some(value) = value {
+        *expression = Expression::Literal(Box::new(LiteralExpression {
+            span: Box::new(expression.span()),
+            value: Box::new(value),
+        }));
+    }
+}
+
+fn fold_prefix(operator: &PrefixOperatorKind, value: &LiteralExpressionValue) -> Option<LiteralExpressionValue> {
+    match (operator, value) {
+        (PrefixOperatorKind::Bang, LiteralExpressionValue::Boolean(value)) => Some(LiteralExpressionValue::Boolean(!value)),
+        (PrefixOperatorKind::Plus, LiteralExpressionValue::Integer { .. } | LiteralExpressionValue::Float { .. }) => {
+            Some(value.clone())
+        }
+        (PrefixOperatorKind::Minus, LiteralExpressionValue::Integer { value, .. }) => value.checked_neg().map(integer),
+        (PrefixOperatorKind::Minus, LiteralExpressionValue::Float { value, .. }) => float(-value),
+        _ => None,
+    }
+}
+
+fn fold_infix(
+    operator: &InfixOperatorKind,
+    left: &LiteralExpressionValue,
+    right: &LiteralExpressionValue,
+) -> Option<LiteralExpressionValue> {
+    use LiteralExpressionValue::{Boolean, Integer};
+
+    match (left, right) {
+        (Integer { value: left, .. }, Integer { value: right, .. }) => fold_integer_infix(operator, *left, *right),
+        (Boolean(left), Boolean(right)) => match operator {
+            InfixOperatorKind::AmpersandAmpersand => Some(Boolean(*left && *right)),
+            InfixOperatorKind::PipePipe => Some(Boolean(*left || *right)),
+            InfixOperatorKind::EqualsEquals => Some(Boolean(left == right)),
+            InfixOperatorKind::BangEquals => Some(Boolean(left != right)),
+            _ => None,
+        },
+        _ => match (left.as_number(), right.as_number()) {
+            (Some(left), Some(right)) => fold_float_infix(operator, left, right),
+            _ => None,
+        },
+    }
+}
+
+fn fold_integer_infix(operator: &InfixOperatorKind, left: i64, right: i64) -> Option<LiteralExpressionValue> {
+    match operator {
+        InfixOperatorKind::Plus => left.checked_add(right).map(integer),
+        InfixOperatorKind::Minus => left.checked_sub(right).map(integer),
+        InfixOperatorKind::Star => left.checked_mul(right).map(integer),
+        // Only exact division is folded, as whether `7 / 2` is an integer is up to the interpreter
+        InfixOperatorKind::Slash => match left.checked_rem(right)? {
+            0 => left.checked_div(right).map(integer),
+            _ => None,
+        },
+        InfixOperatorKind::Percent => left.checked_rem(right).map(integer),
+        InfixOperatorKind::Caret => left.checked_pow(u32::try_from(right).ok()?).map(integer),
+        _ => fold_comparison(operator, left, right),
+    }
+}
+
+fn fold_float_infix(operator: &InfixOperatorKind, left: f64, right: f64) -> Option<LiteralExpressionValue> {
+    match operator {
+        InfixOperatorKind::Plus => float(left + right),
+        InfixOperatorKind::Minus => float(left - right),
+        InfixOperatorKind::Star => float(left * right),
+        InfixOperatorKind::Slash if right != 0.0 => float(left / right),
+        InfixOperatorKind::Percent if right != 0.0 => float(left % right),
+        InfixOperatorKind::Caret => float(left.powf(right)),
+        _ => fold_comparison(operator, left, right),
+    }
+}
+
+fn fold_comparison<T: PartialOrd>(operator: &InfixOperatorKind, left: T, right: T) -> Option<LiteralExpressionValue> {
+    let result = match operator {
+        InfixOperatorKind::EqualsEquals => left == right,
+        InfixOperatorKind::BangEquals => left != right,
+        InfixOperatorKind::LessThan => left < right,
+        InfixOperatorKind::LessThanEquals => left <= right,
+        InfixOperatorKind::GreaterThan => left > right,
+        InfixOperatorKind::GreaterThanEquals => left >= right,
+        _ => return None,
+    };
+
+    Some(LiteralExpressionValue::Boolean(result))
+}
+
+fn integer(value: i64) -> LiteralExpressionValue {
+    LiteralExpressionValue::Integer { value, raw: value.to_string() }
+}
+
+/// Results which aren't finite, e.g. overflow or NaN, have no literal form so aren't folded.
+fn float(value: f64) -> Option<LiteralExpressionValue> {
+    match value.is_finite() {
+        true => Some(LiteralExpressionValue::Float { value, raw: format!("{value:?}") }),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fold_constants;
+    use crate::ast::Program;
+
+    fn fold(source: &str) -> String {
+        let mut program: Program = source.parse().expect("source should parse");
+        fold_constants(&mut program);
+
+        program.to_string()
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        assert_eq!(fold("2 * 3"), "6");
+        assert_eq!(fold("1 + 2 * 3"), "7");
+        assert_eq!(fold("(1 + 2) * 3"), "9");
+        assert_eq!(fold("-2 ^ 2"), "-4");
+        assert_eq!(fold("7 % 4"), "3");
+        assert_eq!(fold("1.5 + 1"), "2.5");
+    }
+
+    #[test]
+    fn folds_comparisons_and_logic() {
+        assert_eq!(fold("!true"), "false");
+        assert_eq!(fold("1 < 2"), "true");
+        assert_eq!(fold("true && false"), "false");
+        assert_eq!(fold("true == true"), "true");
+    }
+
+    #[test]
+    fn short_circuits_logic() {
+        assert_eq!(fold("false && f()"), "false");
+        assert_eq!(fold("true || f()"), "true");
+        assert_eq!(fold("true && f()"), "true && f()");
+        assert_eq!(fold("f() || true"), "f() || true");
+    }
+
+    #[test]
+    fn leaves_non_constant_expressions() {
+        assert_eq!(fold("a + 1"), "a + 1");
+        assert_eq!(fold("f(1 + 2)"), "f(3)");
+        assert_eq!(fold("\"a\" + \"b\""), "\"a\" + \"b\"");
+        assert_eq!(fold("\"a\" == \"a\""), "\"a\" == \"a\"");
+    }
+
+    #[test]
+    fn leaves_expressions_which_fail_at_runtime() {
+        assert_eq!(fold("1 / 0"), "1 / 0");
+        assert_eq!(fold("1.0 / 0"), "1.0 / 0");
+        assert_eq!(fold("7 / 2"), "7 / 2");
+        assert_eq!(fold("9223372036854775807 + 1"), "9223372036854775807 + 1");
+    }
+}
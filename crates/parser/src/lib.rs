@@ -1,28 +1,32 @@
-use ast::{BreakExpression, Expression, InfixExpression, LiteralExpression, Program, IdentifierExpression, GroupingExpression, PrefixExpression, PrefixOperatorKind, BlockExpression, IfExpression, CallExpression, FunctionDeclarationExpression};
+use std::str::FromStr;
+
+use ast::{DocComment, BreakExpression, Expression, InfixExpression, LiteralExpression, Program, IdentifierExpression, GroupingExpression, PrefixExpression, PrefixOperatorKind, BlockExpression, IfExpression, CallExpression, MemberExpression, IndexExpression, Label, LoopExpression, WhileExpression, ForExpression, FunctionDeclarationExpression, TemplateStringExpression, CommandExpression, TemplatePart, TupleExpression, ArrayExpression, MatchExpression, MatchArm, MatchPattern, Parameter, TypeExpression, ArrayTypeExpression, ContinueExpression, ReturnExpression, PostfixExpression};
 use from_token::FromToken;
-use lexer::token::{Token, TokenKind};
-use parser_error::ParserError;
+use lexer::{default_lexer, token::{Interpolation, TemplateSegment, Token, TokenKind, TokenValue}};
+use diagnostic::Diagnostic;
+use parser_error::{ParserError, ParserErrorKind};
+use precedence::PrattConfig;
 
 use crate::{ast::{InfixOperatorKind, IfCondition, IfDefault, PostfixOperatorKind}, span::Span};
 
+mod display;
 mod from_token;
 
 pub mod ast;
+pub mod diagnostic;
+pub mod fold;
 pub mod parser_error;
+pub mod precedence;
 pub mod span;
 
 macro_rules! unexpected_token_error {
     ($token:expr) => {
-        ParserError {
-            message: format!("Unexpected token of kind {}", $token.kind),
-            position: $token.start,
-        }
+        ParserError::new(format!("Unexpected token of kind {}", $token.kind), $token.start)
+            .with_span(Span::new($token.start, $token.end))
     };
     ($token:expr, $message:expr) => {
-        ParserError {
-            message: format!("Unexpected token of kind {}: {}", $token.kind, $message),
-            position: $token.start,
-        }
+        ParserError::new(format!("Unexpected token of kind {}: {}", $token.kind, $message), $token.start)
+            .with_span(Span::new($token.start, $token.end))
     };
 }
 
@@ -32,98 +36,152 @@ macro_rules! expected_expression_error {
     };
 }
 
+// Parsing an expression only yields nothing at the end of the input
 macro_rules! expected_expression_at_error {
     ($at:expr) => {
-        ParserError {
-            message: "Expected expression".to_string(),
-            position: $at,
-        }
+        ParserError::new("Expected expression", $at).with_kind(ParserErrorKind::UnexpectedEof)
     };
     ($at:expr, $message:expr) => {
-        ParserError {
-            message: format!("Expected expression: {}", $message),
-            position: $at,
-        }
+        ParserError::new(format!("Expected expression: {}", $message), $at).with_kind(ParserErrorKind::UnexpectedEof)
     };
 }
 
 macro_rules! unexpected_expression_at_error {
     ($at:expr) => {
-        ParserError {
-            message: format!("Unexpected expression at {}", $at),
-            position: $at,
-        }
+        ParserError::new(format!("Unexpected expression at {}", $at), $at)
     };
     ($at:expr, $message:expr) => {
-        ParserError {
-            message: format!("Unexpected expression at {}: {}", $at, $message),
-            position: $at,
-        }
+        ParserError::new(format!("Unexpected expression at {}: {}", $at, $message), $at)
     };
 }
 
 macro_rules! peek_token {
     ($self:expr) => {
-        $self.peek().ok_or(ParserError {
-            message: "Unexpected end of file".to_string(),
-            position: match $self.tokens.last() {
+        $self.peek().ok_or_else(|| {
+            let position = match $self.tokens.last() {
                 Some(token) => token.end,
                 None => 0,
-            },
+            };
+
+            ParserError::new("Unexpected end of file", position)
+                .with_kind(ParserErrorKind::UnexpectedEof)
+                .with_token_index($self.position)
         })?
     };
 }
 
-macro_rules! assert_token {
-    ($self:ident, $kind:ident) => {
-        let token = peek_token!($self);
+/// Expressions which end with a block and so end a statement, see `Parser::parse_statement`. Named
+/// functions are declarations, so end a statement too.
+fn is_block_like(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::If(_)
+            | Expression::Block(_)
+            | Expression::Match(_)
+            | Expression::Loop(_)
+            | Expression::While(_)
+            | Expression::For(_)
+    ) || matches!(expression, Expression::FunctionDeclaration(function) if function.name.is_some())
+}
 
-        assert!(
-            token.kind == TokenKind::$kind,
-            "Expected token of kind {}, found token of kind {}",
-            stringify!($kind),
-            token.kind
-        );
+/// The kind of token which closes the given opening bracket, e.g. `}` for `{`.
+fn closing_delimiter(kind: &TokenKind) -> Option<TokenKind> {
+    match kind {
+        TokenKind::BraceCurlyOpen => Some(TokenKind::BraceCurlyClose),
+        TokenKind::BraceSquareOpen => Some(TokenKind::BraceSquareClose),
+        TokenKind::BraceRoundOpen => Some(TokenKind::BraceRoundClose),
+        _ => None,
     }
 }
 
-macro_rules! peek_assert_token {
-    ($self:ident, $kind:ident) => {{
-        let token = peek_token!($self);
+/// The span of a keyword expression such as `return 1`, from the keyword to the end of its value.
+fn keyword_span(keyword: &Token, value: &Option<Box<Expression>>) -> Span {
+    match value {
+        Some(value) => Span::new(keyword.start, value.span().end),
+        None => Span::new(keyword.start, keyword.end),
+    }
+}
 
-        assert!(
-            token.kind == TokenKind::$kind,
-            "Expected token of kind {}, found token of kind {}",
-            stringify!($kind),
-            token.kind
-        );
+/// Lexes and parses the source with the default lexer. Lexer errors don't stop parsing, so the
+/// diagnostics from both stages are returned together, ordered by position.
+pub fn parse_source(source: &str) -> Result<Program, Vec<Diagnostic>> {
+    let result = default_lexer().lex(source);
+    let mut diagnostics: Vec<Diagnostic> = result.errors.into_iter().map(Diagnostic::from).collect();
+
+    match Parser::new(&result.tokens).parse() {
+        Ok(program) if diagnostics.is_empty() => return Ok(program),
+        Ok(_) => {}
+        Err(error) => diagnostics.push(error.into()),
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
 
-        token
-    }};
+    Err(diagnostics)
 }
 
-macro_rules! peek_assert_matching_kind {
-    ($self:ident, $kind:pat) => {{
-        let token = peek_token!($self);
+/// Parses the source with the default lexer, e.g. `"1 + 2".parse::<Program>()`. See `parse_source`.
+impl FromStr for Program {
+    type Err = Vec<Diagnostic>;
 
-        assert!(
-            matches!(token.kind, $kind),
-            "Expected token of kind {}, found token of kind {}",
-            stringify!($kind),
-            token.kind
-        );
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        parse_source(source)
+    }
+}
+
+/// Errors on a condition which is an assignment, as it's almost certainly a typo for `==`. Wrapping
+/// the assignment in parentheses makes the intent explicit.
+fn reject_assignment_condition(condition: &Expression) -> Result<(), ParserError> {
+    let infix = match condition {
+        Expression::Infix(infix) if infix.operator.is_assignment() => infix,
+        _ => return Ok(()),
+    };
+
+    let message = match infix.operator {
+        InfixOperatorKind::Equals => "Assignment in condition; did you mean '=='?",
+        _ => "Assignment in condition; wrap it in parentheses if this is intended",
+    };
+
+    Err(ParserError::new(message, infix.span.start).with_span(*infix.span))
+}
+
+/// Errors on an assignment to an expression which can't be assigned to, see
+/// `Expression::is_assignable`. Within an array pattern, the error is for the element which can't
+/// be assigned to.
+fn validate_assignment_target(target: &Expression) -> Result<(), ParserError> {
+    if target.is_assignable() {
+        return Ok(());
+    }
+
+    if let Expression::Array(array) = target {
+        if let Some(element) = array.elements.iter().find(|element| !element.is_assignable()) {
+            return validate_assignment_target(element);
+        }
+    }
 
-        token
-    }};
+    let span = target.span();
+
+    Err(
+        ParserError::new(format!("Cannot assign to this {} expression", target.kind_name()), span.start)
+            .with_span(span)
+            .with_label("expected an identifier, member, index or array pattern"),
+    )
 }
 
-struct ParserContext {
+/// Where the tokens being parsed are, which decides whether `break`, `continue` and `return` are
+/// allowed. See `Parser::with_context`.
+#[derive(Debug, Clone)]
+pub struct ParserContext {
+    /// Within a loop, so `break` and `continue` are allowed.
     pub is_loop: bool,
+    /// Within a function, so `return` is allowed.
+    pub is_function: bool,
+    /// Labels of the enclosing loops, which `break` and `continue` may refer to.
+    pub labels: Vec<String>,
 }
 
 impl Default for ParserContext {
     fn default() -> Self {
-        Self { is_loop: false }
+        Self { is_loop: false, is_function: false, labels: vec![] }
     }
 }
 
@@ -131,6 +189,17 @@ pub struct Parser<'a> {
     tokens: &'a Vec<Token>,
     position: usize,
     context: ParserContext,
+    config: PrattConfig,
+    newline_separators: bool,
+    depth: usize,
+    max_depth: usize,
+    docs: Vec<DocComment>,
+    warnings: Vec<Diagnostic>,
+    /// The opening brackets of the blocks, calls, groupings and so on being parsed, innermost last.
+    open_delimiters: Vec<Token>,
+    /// The number of open delimiters where the head of an if, while, for or match is being parsed,
+    /// see `parse_condition`.
+    condition_delimiters: Option<usize>,
 }
 
 impl<'a> Parser<'a> {
@@ -139,37 +208,203 @@ impl<'a> Parser<'a> {
             tokens,
             position: 0,
             context: ParserContext::default(),
+            config: PrattConfig::default(),
+            newline_separators: false,
+            depth: 0,
+            max_depth: 96,
+            docs: vec![],
+            warnings: vec![],
+            open_delimiters: vec![],
+            condition_delimiters: None,
         }
     }
 
+    /// When enabled, a newline terminates the current expression unless the line ends with an
+    /// operator, so `a\n- b` parses as two expressions while `a -\nb` parses as one subtraction.
+    /// Newlines within parentheses and square brackets never terminate it.
+    pub fn with_newline_separators(mut self, enabled: bool) -> Self {
+        self.newline_separators = enabled;
+
+        self
+    }
+
+    /// Parses the tokens as though they were within the given context, e.g. a fragment of a loop
+    /// body containing `break`. Parsing starts at the top level by default.
+    pub fn with_context(mut self, context: ParserContext) -> Self {
+        self.context = context;
+
+        self
+    }
+
+    /// Parses operators with the binding powers of the config rather than the default ones, e.g. to
+    /// make `+` bind tighter than `*`.
+    pub fn with_config(mut self, config: PrattConfig) -> Self {
+        self.config = config;
+
+        self
+    }
+
+    /// Sets how deeply expressions may be nested before parsing fails, which prevents
+    /// pathological input from overflowing the stack. Defaults to 96, which fits within the 2MB stack
+    /// of a spawned thread in a debug build.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+
+        self
+    }
+
     // === Parser ===
 
     pub fn parse(&mut self) -> Result<Program, ParserError> {
-        let mut program = Program { ast: vec![] };
+        let mut program = Program { ast: vec![], docs: vec![], warnings: vec![] };
+
+        // TODO: If parse error is returned, advance to the next newline token
+        //       and collect the error in a vector of errors to be returned
+        // Errors which don't record the failing token fail at the current one
+        program.ast = self.parse_statements().map_err(|error| match error.token_index {
+            Some(_) => error,
+            None => error.with_token_index(self.position),
+        })?;
+
+        // Every token should belong to an expression by now, so anything left over would otherwise
+        // be silently dropped from the program
+        self.skip_whitespace();
 
-        while self.position < self.tokens.len() {
-            // TODO: If parse error is returned, advance to the next newline token
-            //       and collect the error in a vector of errors to be returned
-            let expression = self.parse_expression()?;
+        if let Some(token) = self.peek() {
+            return Err(unexpected_token_error!(token, "Trailing tokens after the end of the program")
+                .with_token_index(self.position));
+        }
 
-            // Skip whitespace and newlines
-            if let Some(expression) = expression {
-                program.ast.push(expression);
+        // Docs within a statement are recorded before the statement's own
+        program.docs = std::mem::take(&mut self.docs);
+        program.docs.sort_by_key(|doc| doc.span.start);
+        program.warnings = std::mem::take(&mut self.warnings);
+
+        Ok(program)
+    }
+
+    /// Parses the remaining tokens as a list of statements, as though the whole input were the
+    /// body of a block. Statements are separated by the same newlines and semicolons as in a block.
+    pub fn parse_statements(&mut self) -> Result<Vec<Expression>, ParserError> {
+        let (statements, _) = self.parse_statement_list(None)?;
+
+        Ok(statements)
+    }
+
+    /// Parses statements until the closing token, which is left to the caller, or until the end of
+    /// the input if there is none. Also returns whether the last statement ended with a semicolon.
+    fn parse_statement_list(&mut self, close: Option<TokenKind>) -> Result<(Vec<Expression>, bool), ParserError> {
+        let mut statements = vec![];
+        let mut terminated = false;
+
+        loop {
+            self.skip_whitespace();
+
+            match (self.peek(), &close) {
+                (Some(token), Some(close)) if token.kind == *close => break,
+                (None, None) => break,
+                _ => {}
+            }
+
+            let position = self.position;
+
+            if let Some(statement) = self.parse_statement()? {
+                statements.push(statement);
+            }
+
+            // Statements may optionally be separated by a semicolon as well as a newline
+            self.skip_whitespace();
+            terminated = self.try_consume_token(TokenKind::Semicolon).is_some();
+
+            // Guards against looping forever on a token which is neither parsed nor skipped, and
+            // fails at the end of the input if the closing token is missing
+            if self.position == position {
+                let token = peek_token!(self);
+
+                return Err(unexpected_token_error!(token).with_token_index(position));
             }
         }
 
-        Ok(program)
+        Ok((statements, terminated))
     }
 
     fn parse_expression(&mut self) -> Result<Option<Expression>, ParserError> {
-        self.pratt_parse_expression(0)
+        self.pratt_parse_expression(0, false)
+    }
+
+    /// Parses the expression before the block of an if, while, for or match. The block isn't part of
+    /// the expression, so `if (a) { }` isn't a function declaration, unless it's nested in brackets,
+    /// e.g. `if f((a) { a }) { }`.
+    fn parse_condition(&mut self) -> Result<Option<Expression>, ParserError> {
+        let outer = self.condition_delimiters.replace(self.open_delimiters.len());
+        let condition = self.parse_expression();
+        self.condition_delimiters = outer;
+
+        condition
+    }
+
+    /// Parses an expression at the start of a statement, i.e. at the top level or directly within a
+    /// block. As in Rust, a block-like expression there ends the statement, so `if a {1} else {2} + 3`
+    /// is the if followed by `+3`. Parentheses make it an operand: `(if a {1} else {2}) + 3`.
+    ///
+    /// Doc comments preceding the statement are recorded against it.
+    fn parse_statement(&mut self) -> Result<Option<Expression>, ParserError> {
+        self.skip_whitespace();
+
+        let start = self.position;
+        let statement = self.pratt_parse_expression(0, true)?;
+
+        if let Some(statement) = &statement {
+            self.record_doc(start, statement.span());
+        }
+
+        Ok(statement)
+    }
+
+    /// Records the `///` comments in the trivia directly before the token at the given index as
+    /// documenting the span. The trivia may already have been skipped, so it's found by looking
+    /// back from the token.
+    fn record_doc(&mut self, index: usize, span: Span) {
+        let lines: Vec<&str> = self.tokens[..index]
+            .iter()
+            .rev()
+            .take_while(|token| token.kind.is_trivia())
+            .filter_map(|token| match (&token.kind, &token.value) {
+                (TokenKind::DocComment, TokenValue::String(text)) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        if !lines.is_empty() {
+            let text = lines.into_iter().rev().collect::<Vec<_>>().join("\n");
+
+            self.docs.push(DocComment { span, text });
+        }
+    }
+
+    fn pratt_parse_expression(&mut self, min_bp: u8, statement: bool) -> Result<Option<Expression>, ParserError> {
+        // All nested expressions are parsed through here, so this bounds the recursion depth
+        if self.depth >= self.max_depth {
+            let position = match self.peek() {
+                Some(token) => token.start,
+                None => self.tokens.last().map_or(0, |token| token.end),
+            };
+
+            return Err(ParserError::new("Expression nesting too deep", position));
+        }
+
+        self.depth += 1;
+        let expression = self.pratt_parse_nested_expression(min_bp, statement);
+        self.depth -= 1;
+
+        expression
     }
 
     // Pratt parser for expressions based on https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html
-    fn pratt_parse_expression(&mut self, min_bp: u8) -> Result<Option<Expression>, ParserError> {
+    fn pratt_parse_nested_expression(&mut self, min_bp: u8, statement: bool) -> Result<Option<Expression>, ParserError> {
         macro_rules! wrap_lhs {
-            ($expression_type:expr, $value:expr) => {
-                $expression_type(Box::new($value))
+            ($expression_type:expr, $result:expr) => {
+                $result.map(|value| $expression_type(Box::new(value)))
             };
         }
 
@@ -179,74 +414,125 @@ impl<'a> Parser<'a> {
             Some(token) => token,
             None => return Ok(None),
         };
-        let span = Span::start_from(token.start);
 
+        // Each arm is a result which is unwrapped once below. This frame is on the stack once per
+        // level of nesting, and in debug builds every `?` and error in it gets its own stack slot.
         let mut lhs = match token.kind {
-            TokenKind::Whitespace | TokenKind::NewLine => unreachable!("Whitespace and newlines should be skipped"),
-            TokenKind::Identifier => wrap_lhs!(Expression::Identifier, self.parse_identifier_expression()?),
-            TokenKind::String | TokenKind::Number | TokenKind::Boolean => {
-                wrap_lhs!(Expression::Literal, self.parse_literal_expression()?)
+            TokenKind::Whitespace
+            | TokenKind::NewLine
+            | TokenKind::Comment
+            | TokenKind::DocComment
+            | TokenKind::Shebang => {
+                unreachable!("Whitespace, newlines, comments and the shebang should be skipped")
+            }
+            TokenKind::Identifier => wrap_lhs!(Expression::Identifier, self.parse_identifier_expression()),
+            TokenKind::String | TokenKind::Number | TokenKind::Boolean | TokenKind::Char => {
+                wrap_lhs!(Expression::Literal, self.parse_literal_expression())
+            },
+            TokenKind::TemplateString => {
+                wrap_lhs!(Expression::TemplateString, self.parse_template_string_expression())
             },
-            TokenKind::Command => todo!(),
-            TokenKind::Equals => return Err(unexpected_token_error!(token)),
-            TokenKind::EqualsEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::BangEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::LessThan => return Err(unexpected_token_error!(token)),
-            TokenKind::LessThanEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::LessThanLessThan => return Err(unexpected_token_error!(token)),
-            TokenKind::LessThanLessThanEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::GreaterThan => return Err(unexpected_token_error!(token)),
-            TokenKind::GreaterThanEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::GreaterThanGreaterThan => return Err(unexpected_token_error!(token)),
-            TokenKind::GreaterThanGreaterThanEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::SlashEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::StarEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::PlusEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::MinusEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::PercentEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::CaretEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::AmpersandEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::AmpersandAmpersandEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::Ampersand => return Err(unexpected_token_error!(token)),
-            TokenKind::AmpersandAmpersand => return Err(unexpected_token_error!(token)),
-            TokenKind::PipeEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::PipePipeEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::Pipe => return Err(unexpected_token_error!(token)),
-            TokenKind::PipePipe => return Err(unexpected_token_error!(token)),
-            TokenKind::Colon => return Err(unexpected_token_error!(token)),
-            TokenKind::ColonEquals => return Err(unexpected_token_error!(token)),
-            TokenKind::Dot => return Err(unexpected_token_error!(token)),
-            TokenKind::DotDot => todo!(),
+            TokenKind::Command => wrap_lhs!(Expression::Command, self.parse_command_expression()),
+            TokenKind::Dot => match self.tokens.get(self.position + 1) {
+                // Most likely a float missing its leading zero, such as `.5`
+                Some(next) if next.kind == TokenKind::Number && next.start == token.end => {
+                    Err(ParserError::new("Numbers must start with a digit, e.g. '0.5' rather than '.5'", token.start)
+                        .with_span(Span::new(token.start, next.end)))
+                }
+                _ => Err(unexpected_token_error!(token)),
+            },
+            TokenKind::DotDot => {
+                Err(ParserError::new("Ranges must have a start, e.g. '0..n'", token.start)
+                    .with_span(Span::new(token.start, token.end)))
+            }
             TokenKind::Bang | TokenKind::Plus | TokenKind::Minus => {
-                wrap_lhs!(Expression::Prefix, self.parse_prefix_expression()?)
+                self.parse_prefix_expression()
             },
-            TokenKind::Slash => return Err(unexpected_token_error!(token)),
-            TokenKind::Star => return Err(unexpected_token_error!(token)),
-            TokenKind::Caret => return Err(unexpected_token_error!(token)),
-            TokenKind::Percent => return Err(unexpected_token_error!(token)),
-            TokenKind::Comma => return Err(unexpected_token_error!(token)),
-            TokenKind::Comment => todo!(),
-            TokenKind::BraceCurlyOpen => wrap_lhs!(Expression::Block, self.parse_block_expression()?),
-            TokenKind::BraceCurlyClose => return Err(unexpected_token_error!(token)),
-            TokenKind::BraceSquareOpen => todo!(),
-            TokenKind::BraceSquareClose => return Err(unexpected_token_error!(token)),
-            TokenKind::BraceRoundOpen => wrap_lhs!(Expression::Grouping, self.parse_grouping_expression()?),
-            TokenKind::BraceRoundClose => return Err(unexpected_token_error!(token)),
-            TokenKind::If => wrap_lhs!(Expression::If, self.parse_if_expression()?),
-            TokenKind::Else => return Err(unexpected_token_error!(token)),
-            TokenKind::For => todo!(),
-            TokenKind::While => todo!(),
-            TokenKind::Loop => todo!(),
+            TokenKind::BraceCurlyOpen => wrap_lhs!(Expression::Block, self.parse_block_expression()),
+            TokenKind::BraceCurlyClose => Err(self.stray_closing_delimiter_error(token)),
+            TokenKind::BraceSquareOpen => wrap_lhs!(Expression::Array, self.parse_array_expression()),
+            TokenKind::BraceSquareClose => Err(self.stray_closing_delimiter_error(token)),
+            TokenKind::BraceRoundOpen if self.is_function_shorthand() => {
+                wrap_lhs!(Expression::FunctionDeclaration, self.parse_function_declaration_expression())
+            }
+            TokenKind::BraceRoundOpen => self.parse_grouping_or_tuple_expression(),
+            TokenKind::BraceRoundClose => Err(self.stray_closing_delimiter_error(token)),
+            TokenKind::If => wrap_lhs!(Expression::If, self.parse_if_expression()),
+            TokenKind::Else => {
+                Err(ParserError::new("'else' without matching 'if'", token.start).with_span(Span::new(token.start, token.end)))
+            }
+            TokenKind::Label => self.parse_labeled_loop(),
+            TokenKind::For => wrap_lhs!(Expression::For, self.parse_for_expression(None)),
+            TokenKind::While => wrap_lhs!(Expression::While, self.parse_while_expression(None)),
+            TokenKind::Loop => wrap_lhs!(Expression::Loop, self.parse_loop_expression(None)),
             TokenKind::Break => {
-                wrap_lhs!(Expression::Break, self.parse_break_expression()?)
+                wrap_lhs!(Expression::Break, self.parse_break_expression())
             }
-            TokenKind::Continue => todo!(),
-            TokenKind::Return => todo!(),
-            TokenKind::Function => wrap_lhs!(Expression::FunctionDeclaration, self.parse_function_declaration_expression()?),
-        };
+            TokenKind::Continue => wrap_lhs!(Expression::Continue, self.parse_continue_expression()),
+            TokenKind::Return => wrap_lhs!(Expression::Return, self.parse_return_expression()),
+            TokenKind::Function => wrap_lhs!(Expression::FunctionDeclaration, self.parse_function_declaration_expression()),
+            TokenKind::Match => wrap_lhs!(Expression::Match, self.parse_match_expression()),
+            TokenKind::Equals
+            | TokenKind::EqualsEquals
+            | TokenKind::FatArrow
+            | TokenKind::BangEquals
+            | TokenKind::LessThan
+            | TokenKind::LessThanEquals
+            | TokenKind::LessThanLessThan
+            | TokenKind::LessThanLessThanEquals
+            | TokenKind::GreaterThan
+            | TokenKind::GreaterThanEquals
+            | TokenKind::GreaterThanGreaterThan
+            | TokenKind::GreaterThanGreaterThanEquals
+            | TokenKind::GreaterThanGreaterThanGreaterThan
+            | TokenKind::GreaterThanGreaterThanGreaterThanEquals
+            | TokenKind::SlashEquals
+            | TokenKind::StarEquals
+            | TokenKind::PlusEquals
+            | TokenKind::MinusEquals
+            | TokenKind::PercentEquals
+            | TokenKind::CaretEquals
+            | TokenKind::AmpersandEquals
+            | TokenKind::AmpersandAmpersandEquals
+            | TokenKind::Ampersand
+            | TokenKind::AmpersandAmpersand
+            | TokenKind::PipeEquals
+            | TokenKind::PipePipeEquals
+            | TokenKind::Pipe
+            | TokenKind::PipePipe
+            | TokenKind::Colon
+            | TokenKind::ColonEquals
+            | TokenKind::Slash
+            | TokenKind::Star
+            | TokenKind::Caret
+            | TokenKind::Percent
+            | TokenKind::Comma
+            | TokenKind::Semicolon
+            | TokenKind::In => Err(unexpected_token_error!(token)),
+            TokenKind::Reserved => {
+                let keyword = match &token.value {
+                    TokenValue::String(keyword) => keyword,
+                    _ => unreachable!("Token of kind Reserved must have a value of type String"),
+                };
+
+                Err(
+                    ParserError::new(format!("'{}' is a reserved keyword", keyword), token.start)
+                        .with_span(Span::new(token.start, token.end)),
+                )
+            }
+        }?;
+
+        if statement && is_block_like(&lhs) {
+            return Ok(Some(lhs));
+        }
 
         loop {
-            self.skip_whitespace();
+            if self.newline_terminates() {
+                // Stop at the end of the line, operators consume any newlines following them
+                self.skip_inline_whitespace();
+            } else {
+                self.skip_whitespace();
+            }
 
             let token = match self.peek() {
                 Some(token) => token.clone(),
@@ -254,20 +540,37 @@ impl<'a> Parser<'a> {
             };
 
             if let Some(operator) = PostfixOperatorKind::try_from_token(&token) {
-                let (l_bp, ()) = operator.postfix_binding_power();
+                let (l_bp, ()) = self.config.postfix_binding_power(operator);
 
                 if l_bp < min_bp {
                     break;
                 }
 
                 lhs = match operator {
-                    PostfixOperatorKind::BraceSquareOpen => todo!(),
+                    PostfixOperatorKind::BraceSquareOpen => Expression::Index(Box::new(self.parse_index_expression(lhs)?)),
                     PostfixOperatorKind::BraceRoundOpen => Expression::Call(Box::new(self.parse_call_expression(lhs)?)),
+                    PostfixOperatorKind::Dot => Expression::Member(Box::new(self.parse_member_expression(lhs)?)),
+                    PostfixOperatorKind::Bang => {
+                        // Otherwise the `!` is a prefix operator of the next expression
+                        if !self.is_postfix_bang(&token, &lhs) {
+                            break;
+                        }
+
+                        self.advance();
+
+                        Expression::Postfix(Box::new(PostfixExpression {
+                            span: Box::new(lhs.span().extend(token.end)),
+                            operator,
+                            left: Box::new(lhs),
+                        }))
+                    }
                 }
             }
 
             else if let Some(operator) = InfixOperatorKind::try_from_token(&token) {
-                let (l_bp, r_bp) = operator.binding_power();
+                self.reject_postfix_update(&token)?;
+
+                let (l_bp, r_bp) = self.config.infix_binding_power(operator);
 
                 if l_bp < min_bp {
                     break;
@@ -275,13 +578,19 @@ impl<'a> Parser<'a> {
 
                 self.advance_and_skip_whitespace();
 
-                let rhs = match self.pratt_parse_expression(r_bp)? {
+                if operator.is_assignment() {
+                    validate_assignment_target(&lhs)?;
+                }
+
+                let rhs = match self.pratt_parse_expression(r_bp, false)? {
                     Some(rhs) => rhs,
                     None => return Err(expected_expression_error!(token)),
                 };
 
+                // From the left operand rather than the first token, which may not be part of it,
+                // e.g. the folded `+` of `+a + b`
                 lhs = Expression::Infix(Box::new(InfixExpression {
-                    span: Box::new(span.extend(rhs.span().end)),
+                    span: Box::new(lhs.span().extend(rhs.span().end)),
                     left: Box::new(lhs),
                     operator,
                     right: Box::new(rhs),
@@ -294,19 +603,150 @@ impl<'a> Parser<'a> {
         Ok(Some(lhs))
     }
 
+    /// Whether the `(` at the current position opens the parameters of a function declared without
+    /// `fn`, i.e. its matching `)` is followed by a `{` on the same line, as in `(name) { name }`.
+    /// A `{` on the next line starts a block, so `x = (a)\n{ b }` is an assignment and a block.
+    fn is_function_shorthand(&self) -> bool {
+        if self.condition_delimiters == Some(self.open_delimiters.len()) {
+            return false;
+        }
+
+        let mut depth = 0;
+
+        for (index, token) in self.tokens.iter().enumerate().skip(self.position) {
+            match token.kind {
+                TokenKind::BraceRoundOpen | TokenKind::BraceSquareOpen | TokenKind::BraceCurlyOpen => depth += 1,
+                TokenKind::BraceRoundClose | TokenKind::BraceSquareClose | TokenKind::BraceCurlyClose => depth -= 1,
+                _ => continue,
+            }
+
+            if depth > 0 {
+                continue;
+            }
+
+            let next = self.tokens[index + 1..]
+                .iter()
+                .find(|token| !token.kind.is_trivia() || token.kind == TokenKind::NewLine);
+
+            return token.kind == TokenKind::BraceRoundClose
+                && matches!(next, Some(token) if token.kind == TokenKind::BraceCurlyOpen);
+        }
+
+        false
+    }
+
+    /// Whether a newline ends the current expression, see `with_newline_separators`. Within
+    /// parentheses and square brackets the expression can't end before the bracket is closed, so
+    /// newlines there are only whitespace, e.g. in `f(a\n- b)`.
+    fn newline_terminates(&self) -> bool {
+        if !self.newline_separators {
+            return false;
+        }
+
+        match self.open_delimiters.last() {
+            Some(token) => token.kind == TokenKind::BraceCurlyOpen,
+            None => true,
+        }
+    }
+
+    /// Errors on `++` or `--` following an operand. Without this, `i++` would silently parse as
+    /// `i + (+...)`.
+    fn reject_postfix_update(&self, token: &Token) -> Result<(), ParserError> {
+        let message = match token.kind {
+            TokenKind::Plus => "Postfix increment is not supported",
+            TokenKind::Minus => "Postfix decrement is not supported",
+            _ => return Ok(()),
+        };
+
+        match self.tokens.get(self.position + 1) {
+            Some(next) if next.kind == token.kind && next.start == token.end => {
+                Err(ParserError::new(message, token.start).with_span(Span::new(token.start, next.end)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the `!` at the current position directly follows the operand and isn't directly
+    /// followed by another operand, e.g. `a!` or `a!.b` but not `a !b` or `a!b`.
+    fn is_postfix_bang(&self, token: &Token, operand: &Expression) -> bool {
+        if token.start != operand.span().end {
+            return false;
+        }
+
+        match self.tokens.get(self.position + 1) {
+            Some(next) if next.start == token.end => !matches!(
+                next.kind,
+                TokenKind::Identifier
+                    | TokenKind::String
+                    | TokenKind::TemplateString
+                    | TokenKind::Number
+                    | TokenKind::Boolean
+                    | TokenKind::Char
+            ),
+            _ => true,
+        }
+    }
+
+    fn parse_member_expression(&mut self, object: Expression) -> Result<MemberExpression, ParserError> {
+        self.expect(TokenKind::Dot, "before a member")?;
+        self.advance_and_skip_whitespace();
+
+        let token = self.expect(TokenKind::Identifier, "after '.'")?;
+        let property = IdentifierExpression::from_token(token)?;
+
+        self.advance();
+
+        Ok(MemberExpression {
+            span: Box::new(object.span().extend(property.span.end)),
+            object: Box::new(object),
+            property: Box::new(property),
+        })
+    }
+
+    fn parse_index_expression(&mut self, object: Expression) -> Result<IndexExpression, ParserError> {
+        let open = self.expect(TokenKind::BraceSquareOpen, "before an index")?.clone();
+
+        self.open_delimiters.push(open.clone());
+        self.advance_and_skip_whitespace();
+
+        let index = self.parse_expression()?.ok_or(expected_expression_error!(open))?;
+
+        self.skip_whitespace();
+
+        let token = peek_token!(self).clone();
+
+        if token.kind != TokenKind::BraceSquareClose {
+            return Err(unexpected_token_error!(token, "Expected ']'"));
+        }
+
+        self.advance();
+        self.open_delimiters.pop();
+
+        Ok(IndexExpression {
+            span: Box::new(object.span().extend(token.end)),
+            object: Box::new(object),
+            index: Box::new(index),
+        })
+    }
+
     fn parse_call_expression(&mut self, callee: Expression) -> Result<CallExpression, ParserError> {
-        assert_token!(self, BraceRoundOpen);
+        let open = self.expect(TokenKind::BraceRoundOpen, "before call arguments")?.clone();
         let span = callee.span();
 
+        self.open_delimiters.push(open);
         self.advance_and_skip_whitespace();
 
         let mut arguments = vec![];
 
         loop {
+            // Arguments and the closing parenthesis may be on their own lines
+            self.skip_whitespace();
+
             let token = peek_token!(self).clone();
 
             if token.kind == TokenKind::BraceRoundClose {
                 self.advance();
+                self.open_delimiters.pop();
 
                 return Ok(CallExpression {
                     span: Box::new(span.extend(token.end)),
@@ -331,138 +771,350 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_block_expression(&mut self) -> Result<BlockExpression, ParserError> {
-        let token = peek_assert_token!(self, BraceCurlyOpen).clone();
+        let token = self.expect(TokenKind::BraceCurlyOpen, "to open a block")?.clone();
         let span = Span::start_from(token.start);
 
+        self.open_delimiters.push(token);
         self.advance_and_skip_whitespace();
 
-        let mut expressions = vec![];
-
-        loop {
-            let token = peek_token!(self).clone();
-
-            if token.kind == TokenKind::BraceCurlyClose {
-                self.advance();
+        let (mut statements, terminated) = self.parse_statement_list(Some(TokenKind::BraceCurlyClose))?;
+        let token = self.expect(TokenKind::BraceCurlyClose, "to close the block")?.clone();
 
-                return Ok(BlockExpression {
-                    span: Box::new(span.extend(token.end)),
-                    expressions: Box::new(expressions),
-                });
-            }
+        self.advance();
+        self.open_delimiters.pop();
 
-            // TODO: If parse error is returned, advance to the next newline token
-            //       and collect the error in a vector of errors to be returned
-            let expression = self.parse_expression()?;
+        // The last statement is the value of the block, unless it's followed by a semicolon
+        let tail = match terminated {
+            true => None,
+            false => statements.pop().map(Box::new),
+        };
 
-            // Skip whitespace and newlines
-            if let Some(expression) = expression {
-                expressions.push(expression);
-            }
-        }
+        Ok(BlockExpression {
+            span: Box::new(span.extend(token.end)),
+            statements: Box::new(statements),
+            tail,
+        })
     }
 
-    fn parse_prefix_expression(&mut self) -> Result<PrefixExpression, ParserError> {
-        let token = peek_assert_matching_kind!(
-            self,
-            TokenKind::Bang | TokenKind::Minus | TokenKind::Plus
-        ).clone();
+    /// Parses a prefix operator and its operand. A prefix `+` does nothing, so `+x` is parsed as just
+    /// `x`.
+    fn parse_prefix_expression(&mut self) -> Result<Expression, ParserError> {
+        let token = peek_token!(self).clone();
         let span = Span::start_from(token.start);
-        let operator = match PrefixOperatorKind::try_from_token(&token) {
-            Some(operator) => operator,
-            None => unreachable!(),
-        };
-        let ((), r_bp) = operator.prefix_binding_power();
+        let operator = PrefixOperatorKind::try_from_token(&token)
+            .ok_or_else(|| unexpected_token_error!(token, "Expected a prefix operator"))?;
+        let ((), r_bp) = self.config.prefix_binding_power(operator);
 
         self.advance_and_skip_whitespace();
 
-        let expression = self.pratt_parse_expression(r_bp)?.ok_or(expected_expression_error!(token))?;
+        let expression = self.pratt_parse_expression(r_bp, false)?.ok_or(expected_expression_error!(token))?;
+
+        if operator == PrefixOperatorKind::Plus {
+            return Ok(expression);
+        }
 
-        Ok(PrefixExpression {
+        Ok(Expression::Prefix(Box::new(PrefixExpression {
             span: Box::new(span.extend(expression.span().end)),
             operator,
             right: Box::new(expression),
-        })
+        })))
     }
 
-    fn parse_grouping_expression(&mut self) -> Result<GroupingExpression, ParserError> {
-        let token = peek_assert_token!(self, BraceRoundOpen).clone();
+    fn parse_grouping_or_tuple_expression(&mut self) -> Result<Expression, ParserError> {
+        let token = self.expect(TokenKind::BraceRoundOpen, "to open a grouping or tuple")?.clone();
         let span = Span::start_from(token.start);
 
+        self.open_delimiters.push(token.clone());
         self.advance_and_skip_whitespace();
 
+        // The empty tuple, i.e. the unit value
+        if let Some(token) = self.try_consume_token(TokenKind::BraceRoundClose) {
+            self.open_delimiters.pop();
+
+            return Ok(Expression::Tuple(Box::new(TupleExpression {
+                span: Box::new(span.extend(token.end)),
+                elements: Box::new(vec![]),
+            })));
+        }
+
         let expression = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
 
         self.skip_whitespace();
 
+        let mut elements = vec![];
+        let mut is_tuple = false;
+
+        // A comma after the first expression makes this a tuple
+        if self.try_consume_token(TokenKind::Comma).is_some() {
+            is_tuple = true;
+            elements.push(expression);
+
+            loop {
+                self.skip_whitespace();
+
+                if peek_token!(self).kind == TokenKind::BraceRoundClose {
+                    break;
+                }
+
+                let token = peek_token!(self).clone();
+                let element = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
+
+                elements.push(element);
+
+                self.skip_whitespace();
+
+                if self.try_consume_token(TokenKind::Comma).is_none() {
+                    break;
+                }
+            }
+        } else {
+            elements.push(expression);
+        }
+
         let token = peek_token!(self).clone();
-        
+
         if token.kind != TokenKind::BraceRoundClose {
             return Err(unexpected_token_error!(token, "Expected ')'"));
         }
 
         self.advance();
+        self.open_delimiters.pop();
 
-        Ok(GroupingExpression {
-            span: Box::new(span.extend(token.end)),
-            expression: Box::new(expression),
-        })
-    }
+        let span = span.extend(token.end);
 
-    fn parse_literal_expression(&mut self) -> Result<LiteralExpression, ParserError> {
-        let expression = LiteralExpression::from_token(peek_token!(self));
-        self.advance();
-        expression
-    }
+        if is_tuple {
+            return Ok(Expression::Tuple(Box::new(TupleExpression {
+                span: Box::new(span),
+                elements: Box::new(elements),
+            })));
+        }
 
-    fn parse_identifier_expression(&mut self) -> Result<IdentifierExpression, ParserError> {
-        let token = peek_assert_token!(self, Identifier);
-        let identifier = IdentifierExpression::from_token(token);
-        self.advance();
-        identifier
+        Ok(Expression::Grouping(Box::new(GroupingExpression {
+            span: Box::new(span),
+            expression: Box::new(elements.pop().unwrap()),
+        })))
     }
 
-    fn parse_if_expression(&mut self) -> Result<IfExpression, ParserError> {
-        let token = peek_assert_token!(self, If).clone();
-        let mut outer_span = Span::start_from(token.start);
+    fn parse_array_expression(&mut self) -> Result<ArrayExpression, ParserError> {
+        let token = self.expect(TokenKind::BraceSquareOpen, "to open an array")?.clone();
+        let span = Span::start_from(token.start);
 
+        self.open_delimiters.push(token);
         self.advance_and_skip_whitespace();
 
-        let mut conditions = vec![{
-            let condition = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
-            let token = peek_token!(self).clone();
-            let consequence = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
-            let span = outer_span.clone().extend(consequence.span().end);
+        let mut elements = vec![];
 
-            outer_span = outer_span.extend(span.end);
+        loop {
+            self.skip_whitespace();
 
-            IfCondition {
-                span: Box::new(span),
-                condition: Box::new(condition),
-                consequence: Box::new(consequence),
+            let token = peek_token!(self).clone();
+
+            if token.kind == TokenKind::BraceSquareClose {
+                break;
             }
-        }];
-        let mut default = None;
 
-        self.skip_whitespace();
+            let element = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
 
-        while let Some(token) = self.peek() {
-            if token.kind != TokenKind::Else {
+            elements.push(element);
+
+            self.skip_whitespace();
+
+            if self.try_consume_token(TokenKind::Comma).is_none() {
                 break;
             }
+        }
 
-            let span = Span::start_from(token.start);
+        let token = peek_token!(self).clone();
 
-            self.advance_and_skip_whitespace();
+        if token.kind != TokenKind::BraceSquareClose {
+            return Err(unexpected_token_error!(token, "Expected ']'"));
+        }
 
-            let token = peek_token!(self).clone();
+        self.advance();
+        self.open_delimiters.pop();
+
+        Ok(ArrayExpression {
+            span: Box::new(span.extend(token.end)),
+            elements: Box::new(elements),
+        })
+    }
+
+    fn parse_literal_expression(&mut self) -> Result<LiteralExpression, ParserError> {
+        let expression = LiteralExpression::from_token(peek_token!(self));
+        self.advance();
+        expression
+    }
+
+    fn parse_template_string_expression(&mut self) -> Result<TemplateStringExpression, ParserError> {
+        let token = self.expect(TokenKind::TemplateString, "for a template string expression")?.clone();
+        let segments = match &token.value {
+            TokenValue::Template(segments) => segments,
+            _ => unreachable!("Token of kind TemplateString must have a value of type Template"),
+        };
+
+        let mut parts = vec![];
+
+        for segment in segments {
+            parts.push(match segment {
+                TemplateSegment::Literal(literal) => TemplatePart::String(literal.clone()),
+                TemplateSegment::Interpolation(interpolation) => {
+                    // Token indexes within the interpolation don't exist in this token stream, and
+                    // the interpolation is closed so running out of tokens in it is a syntax error
+                    let expression = self
+                        .parse_interpolation(interpolation)
+                        .map_err(|error| error.with_token_index(self.position).with_kind(ParserErrorKind::Syntax))?;
+
+                    TemplatePart::Expression(expression)
+                },
+            });
+        }
+
+        self.advance();
+
+        Ok(TemplateStringExpression {
+            span: Box::new(Span::new(token.start, token.end)),
+            parts: Box::new(parts),
+        })
+    }
+
+    fn parse_command_expression(&mut self) -> Result<CommandExpression, ParserError> {
+        let token = self.expect(TokenKind::Command, "for a command expression")?.clone();
+        let value = match &token.value {
+            TokenValue::Command(value) => value,
+            _ => unreachable!("Token of kind Command must have a value of type Command"),
+        };
+
+        let mut parts = vec![];
+        let mut rest = value.command.as_str();
+
+        for interpolation in value.interpolations.iter() {
+            // Interpolations are left in the command as written, so split the command around them
+            let hole = format!("${{{}}}", interpolation.source);
+            let index = rest.find(&hole).ok_or_else(|| {
+                ParserError::new("Interpolation is missing from the command", interpolation.start)
+                    .with_span(Span::new(interpolation.start, interpolation.end))
+            })?;
+
+            if index > 0 {
+                parts.push(TemplatePart::String(rest[..index].to_string()));
+            }
+
+            // See `parse_template_string_expression`
+            let expression = self
+                .parse_interpolation(interpolation)
+                .map_err(|error| error.with_token_index(self.position).with_kind(ParserErrorKind::Syntax))?;
+
+            parts.push(TemplatePart::Expression(expression));
+            rest = &rest[index + hole.len()..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(TemplatePart::String(rest.to_string()));
+        }
+
+        self.advance();
+
+        Ok(CommandExpression {
+            span: Box::new(Span::new(token.start, token.end)),
+            parts: Box::new(parts),
+        })
+    }
+
+    fn parse_interpolation(&self, interpolation: &Interpolation) -> Result<Expression, ParserError> {
+        let result = default_lexer().lex(&interpolation.source);
+
+        if let Some(error) = result.errors.first() {
+            let span = error.span.unwrap_or(Span::start_from(error.position));
+            let mut shifted = ParserError::new(error.message.clone(), interpolation.start + error.position)
+                .with_span(Span::new(interpolation.start + span.start, interpolation.start + span.end));
+
+            if let Some(label) = &error.label {
+                shifted = shifted.with_label(label.clone());
+            }
+
+            return Err(shifted);
+        }
+
+        // Shift the token positions so they are relative to the whole source
+        let tokens = result.tokens.into_iter().map(|mut token| {
+            token.start += interpolation.start;
+            token.end += interpolation.start;
+            token
+        }).collect();
+
+        let mut parser = Parser::new(&tokens)
+            .with_config(self.config.clone())
+            .with_newline_separators(self.newline_separators)
+            .with_max_depth(self.max_depth - self.depth);
+
+        parser.context = self.context.clone();
+
+        let expression = parser.parse_expression()?.ok_or(expected_expression_at_error!(interpolation.start))?;
+
+        parser.skip_whitespace();
+
+        if let Some(token) = parser.peek() {
+            return Err(unexpected_token_error!(token, "Expected end of interpolation"));
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_identifier_expression(&mut self) -> Result<IdentifierExpression, ParserError> {
+        let token = self.expect(TokenKind::Identifier, "for an identifier expression")?;
+        let identifier = IdentifierExpression::from_token(token);
+        self.advance();
+        identifier
+    }
+
+    fn parse_if_expression(&mut self) -> Result<IfExpression, ParserError> {
+        let token = self.expect(TokenKind::If, "to start an if expression")?.clone();
+        let mut outer_span = Span::start_from(token.start);
+
+        self.advance_and_skip_whitespace();
+
+        let mut conditions = vec![{
+            let condition = self.parse_condition()?.ok_or(expected_expression_error!(token))?;
+
+            reject_assignment_condition(&condition)?;
+
+            let token = peek_token!(self).clone();
+            let consequence = self.parse_statement()?.ok_or(expected_expression_error!(token))?;
+            let span = outer_span.clone().extend(consequence.span().end);
+
+            outer_span = outer_span.extend(span.end);
+
+            IfCondition {
+                span: Box::new(span),
+                condition: Box::new(condition),
+                consequence: Box::new(consequence),
+            }
+        }];
+        let mut default = None;
+
+        self.skip_whitespace();
+
+        while let Some(token) = self.peek() {
+            if token.kind != TokenKind::Else {
+                break;
+            }
+
+            let span = Span::start_from(token.start);
+
+            self.advance_and_skip_whitespace();
+
+            let token = peek_token!(self).clone();
 
             if token.kind == TokenKind::If {
                 self.advance_and_skip_whitespace();
 
                 conditions.push({
-                    let condition = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
+                    let condition = self.parse_condition()?.ok_or(expected_expression_error!(token))?;
+
+                    reject_assignment_condition(&condition)?;
+
                     let token = peek_token!(self).clone();
-                    let consequence = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
+                    let consequence = self.parse_statement()?.ok_or(expected_expression_error!(token))?;
                     let span = span.extend(consequence.span().end);
 
                     outer_span = outer_span.extend(span.end);
@@ -477,7 +1129,7 @@ impl<'a> Parser<'a> {
                 });
             }
             else {
-                let consequence = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
+                let consequence = self.parse_statement()?.ok_or(expected_expression_error!(token))?;
                 let span = span.extend(consequence.span().end);
 
                 outer_span = outer_span.extend(span.end);
@@ -491,6 +1143,14 @@ impl<'a> Parser<'a> {
             }
         }
 
+        for condition in conditions.iter() {
+            self.warn_empty_block(&condition.consequence, "Empty if block");
+        }
+
+        if let Some(default) = &default {
+            self.warn_empty_block(&default.consequence, "Empty else block");
+        }
+
         Ok(IfExpression {
             span: Box::new(outer_span),
             conditions: Box::new(conditions),
@@ -498,114 +1158,1658 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_break_expression(&mut self) -> Result<BreakExpression, ParserError> {
-        let token = peek_assert_token!(self, Break);
-
-        if !self.context.is_loop {
-            return Err(ParserError {
-                message: "Break expression outside of loop".to_string(),
-                position: token.start,
-            });
-        }
-
-        self.advance();
+    fn parse_match_expression(&mut self) -> Result<MatchExpression, ParserError> {
+        let token = self.expect(TokenKind::Match, "to start a match expression")?.clone();
+        let span = Span::start_from(token.start);
 
-        todo!("Parse break expression");
-    }
+        self.advance_and_skip_whitespace();
 
-    fn parse_function_declaration_expression(&mut self) -> Result<FunctionDeclarationExpression, ParserError> {
-        let token = peek_assert_token!(self, Function).clone();
-        let mut outer_span = Span::start_from(token.start);
+        let scrutinee = self.parse_condition()?.ok_or(expected_expression_error!(token))?;
 
-        self.advance_and_skip_whitespace();
+        self.skip_whitespace();
+        let open = self.consume_token(TokenKind::BraceCurlyOpen)?;
 
-        self.consume_token(TokenKind::BraceRoundOpen)?;
+        self.open_delimiters.push(open);
 
-        let mut parameters = vec![];
+        let mut arms = vec![];
 
         loop {
             self.skip_whitespace();
 
             let token = peek_token!(self).clone();
-            outer_span = outer_span.extend(token.end);
 
-            if token.kind == TokenKind::BraceRoundClose {
+            if token.kind == TokenKind::BraceCurlyClose {
                 self.advance();
+                self.open_delimiters.pop();
 
-                break;
+                return Ok(MatchExpression {
+                    span: Box::new(span.extend(token.end)),
+                    scrutinee: Box::new(scrutinee),
+                    arms: Box::new(arms),
+                });
             }
 
-            let parameter = {
-                let expression = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
+            arms.push(self.parse_match_arm()?);
 
-                match expression {
-                    Expression::Identifier(identifier_expression) => *identifier_expression,
-                    _ => return Err(unexpected_expression_at_error!(expression.span().start, format!("Expected identifier or ')' but found {} expression", expression.kind_name()))),
+            self.skip_whitespace();
+
+            // Arms are separated by commas, the last one may omit it
+            if self.try_consume_token(TokenKind::Comma).is_none() {
+                let token = peek_token!(self);
+
+                if token.kind != TokenKind::BraceCurlyClose {
+                    return Err(unexpected_token_error!(token, "Expected ',' or '}'"));
                 }
-            };
+            }
+        }
+    }
 
-            outer_span = outer_span.extend(parameter.span.end);
-            parameters.push(parameter);
+    fn parse_match_arm(&mut self) -> Result<MatchArm, ParserError> {
+        let token = peek_token!(self).clone();
 
-            self.skip_whitespace();
+        let pattern = match token.kind {
+            TokenKind::String | TokenKind::Number | TokenKind::Boolean | TokenKind::Char => {
+                MatchPattern::Literal(Box::new(self.parse_literal_expression()?))
+            }
+            TokenKind::Identifier => MatchPattern::Identifier(Box::new(self.parse_identifier_expression()?)),
+            _ => return Err(unexpected_token_error!(token, "Expected a literal or identifier pattern")),
+        };
 
-            // Optionally consume a comma
-            self.try_consume_token(TokenKind::Comma);
+        self.skip_whitespace();
+
+        let arrow = peek_token!(self).clone();
+
+        if arrow.kind != TokenKind::FatArrow {
+            return Err(unexpected_token_error!(arrow, "Expected '=>'"));
         }
 
-        let body = self.parse_expression()?.ok_or(expected_expression_at_error!(outer_span.end))?;
+        self.advance_and_skip_whitespace();
 
-        Ok(FunctionDeclarationExpression {
-            span: Box::new(outer_span.extend(body.span().end)),
-            parameters: Box::new(parameters),
+        let expression = self.parse_expression()?.ok_or(expected_expression_error!(arrow))?;
+
+        Ok(MatchArm {
+            span: Box::new(pattern.span().extend(expression.span().end)),
+            pattern: Box::new(pattern),
+            expression: Box::new(expression),
+        })
+    }
+
+    /// Parses a loop preceded by its label, e.g. `'outer: loop {}`.
+    fn parse_labeled_loop(&mut self) -> Result<Expression, ParserError> {
+        let label = self.parse_label()?;
+
+        self.skip_whitespace();
+        self.expect(TokenKind::Colon, "after a loop label")?;
+        self.advance_and_skip_whitespace();
+
+        let token = peek_token!(self);
+
+        Ok(match token.kind {
+            TokenKind::Loop => Expression::Loop(Box::new(self.parse_loop_expression(Some(label))?)),
+            TokenKind::While => Expression::While(Box::new(self.parse_while_expression(Some(label))?)),
+            TokenKind::For => Expression::For(Box::new(self.parse_for_expression(Some(label))?)),
+            _ => return Err(unexpected_token_error!(token, "Only loops can be labelled")),
+        })
+    }
+
+    fn parse_label(&mut self) -> Result<Box<Label>, ParserError> {
+        let token = self.expect(TokenKind::Label, "for a label")?;
+        let name = match &token.value {
+            TokenValue::String(name) => name.clone(),
+            _ => unreachable!("Token of kind Label must have a value of type String"),
+        };
+
+        self.advance();
+
+        Ok(Box::new(Label {
+            span: Box::new(Span::new(token.start, token.end)),
+            name,
+        }))
+    }
+
+    fn parse_loop_expression(&mut self, label: Option<Box<Label>>) -> Result<LoopExpression, ParserError> {
+        let token = self.expect(TokenKind::Loop, "to start a loop")?;
+        let span = Span::start_from(label.as_ref().map_or(token.start, |label| label.span.start));
+
+        self.advance_and_skip_whitespace();
+
+        let body = self.parse_loop_body(&label)?;
+
+        Ok(LoopExpression {
+            span: Box::new(span.extend(body.span().end)),
+            label,
             body: Box::new(body),
         })
     }
 
-    // === Helpers ===
+    fn parse_while_expression(&mut self, label: Option<Box<Label>>) -> Result<WhileExpression, ParserError> {
+        let token = self.expect(TokenKind::While, "to start a while loop")?.clone();
+        let span = Span::start_from(label.as_ref().map_or(token.start, |label| label.span.start));
 
-    fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.position)
+        self.advance_and_skip_whitespace();
+
+        let condition = self.parse_condition()?.ok_or(expected_expression_error!(token))?;
+
+        reject_assignment_condition(&condition)?;
+        self.skip_whitespace();
+
+        let body = self.parse_loop_body(&label)?;
+
+        Ok(WhileExpression {
+            span: Box::new(span.extend(body.span().end)),
+            label,
+            condition: Box::new(condition),
+            body: Box::new(body),
+        })
     }
 
-    fn advance(&mut self) {
-        self.position += 1;
+    fn parse_for_expression(&mut self, label: Option<Box<Label>>) -> Result<ForExpression, ParserError> {
+        let token = self.expect(TokenKind::For, "to start a for loop")?;
+        let span = Span::start_from(label.as_ref().map_or(token.start, |label| label.span.start));
+
+        self.advance_and_skip_whitespace();
+
+        let binding = self.parse_identifier_expression()?;
+
+        self.skip_whitespace();
+
+        let token = self.expect(TokenKind::In, "after the for loop binding")?.clone();
+
+        self.advance_and_skip_whitespace();
+
+        let iterable = self.parse_condition()?.ok_or(expected_expression_error!(token))?;
+
+        self.skip_whitespace();
+
+        let body = self.parse_loop_body(&label)?;
+
+        Ok(ForExpression {
+            span: Box::new(span.extend(body.span().end)),
+            label,
+            binding: Box::new(binding),
+            iterable: Box::new(iterable),
+            body: Box::new(body),
+        })
     }
 
-    fn skip_whitespace(&mut self) {
-        while let Some(token) = self.peek() {
-            match token.kind {
-                TokenKind::Whitespace | TokenKind::NewLine => self.advance(),
-                _ => break,
-            }
+    /// Parses the block of a loop, within which `break` and `continue` refer to the loop.
+    fn parse_loop_body(&mut self, label: &Option<Box<Label>>) -> Result<Expression, ParserError> {
+        let mut context = self.context.clone();
+
+        context.is_loop = true;
+        context.labels.extend(label.iter().map(|label| label.name.clone()));
+
+        let context = std::mem::replace(&mut self.context, context);
+        let body = self.parse_block_expression();
+        self.context = context;
+
+        Ok(Expression::Block(Box::new(body?)))
+    }
+
+    fn parse_break_expression(&mut self) -> Result<BreakExpression, ParserError> {
+        let token = self.expect(TokenKind::Break, "to start a break expression")?.clone();
+
+        if !self.context.is_loop {
+            return Err(
+                ParserError::new("Break expression outside of loop", token.start)
+                    .with_span(Span::new(token.start, token.end)),
+            );
         }
+
+        self.advance();
+
+        let label = self.parse_label_reference()?;
+        let expression = self.parse_keyword_value(&token)?;
+        let span = match (&label, &expression) {
+            (Some(label), None) => Span::new(token.start, label.span.end),
+            _ => keyword_span(&token, &expression),
+        };
+
+        Ok(BreakExpression {
+            span: Box::new(span),
+            label,
+            expression,
+        })
     }
 
-    fn advance_and_skip_whitespace(&mut self) {
+    fn parse_continue_expression(&mut self) -> Result<ContinueExpression, ParserError> {
+        let token = self.expect(TokenKind::Continue, "to start a continue expression")?.clone();
+
+        if !self.context.is_loop {
+            return Err(
+                ParserError::new("Continue expression outside of loop", token.start)
+                    .with_span(Span::new(token.start, token.end)),
+            );
+        }
+
         self.advance();
-        self.skip_whitespace();
+
+        let label = self.parse_label_reference()?;
+        let end = label.as_ref().map_or(token.end, |label| label.span.end);
+
+        Ok(ContinueExpression {
+            span: Box::new(Span::new(token.start, end)),
+            label,
+        })
     }
 
-    fn try_consume_token(&mut self, kind: TokenKind) -> Option<Token> {
-        let token = self.peek()?.clone();
+    /// Parses the label following `break` or `continue`, if there is one. It must be the label of an
+    /// enclosing loop.
+    fn parse_label_reference(&mut self) -> Result<Option<Box<Label>>, ParserError> {
+        self.skip_inline_whitespace();
 
-        if token.kind == kind {
-            self.advance();
-            return Some(token.clone());
+        if !matches!(self.peek(), Some(token) if token.kind == TokenKind::Label) {
+            return Ok(None);
         }
 
-        None
+        let label = self.parse_label()?;
+
+        if !self.context.labels.contains(&label.name) {
+            return Err(
+                ParserError::new(format!("Undefined label '{}", label.name), label.span.start)
+                    .with_span(*label.span)
+                    .with_label("no enclosing loop has this label"),
+            );
+        }
+
+        Ok(Some(label))
     }
 
-    fn consume_token(&mut self, kind: TokenKind) -> Result<Token, ParserError> {
-        let token = peek_token!(self).clone();
+    fn parse_return_expression(&mut self) -> Result<ReturnExpression, ParserError> {
+        let token = self.expect(TokenKind::Return, "to start a return expression")?.clone();
 
-        if token.kind != kind {
-            return Err(unexpected_token_error!(token, format!("Expected '{}'", kind)));
+        if !self.context.is_function {
+            return Err(
+                ParserError::new("Return outside of function", token.start)
+                    .with_span(Span::new(token.start, token.end)),
+            );
         }
 
         self.advance();
 
-        Ok(token)
+        let value = self.parse_keyword_value(&token)?;
+
+        Ok(ReturnExpression {
+            span: Box::new(keyword_span(&token, &value)),
+            value,
+        })
+    }
+
+    /// Parses the optional value following `return` or `break`. Without a value, the keyword is
+    /// followed by the end of the line, a separator or an enclosing closing brace.
+    fn parse_keyword_value(&mut self, keyword: &Token) -> Result<Option<Box<Expression>>, ParserError> {
+        self.skip_inline_whitespace();
+
+        let value = match self.peek() {
+            Some(next) if !matches!(
+                next.kind,
+                TokenKind::NewLine
+                    | TokenKind::Comma
+                    | TokenKind::Semicolon
+                    | TokenKind::BraceCurlyClose
+                    | TokenKind::BraceRoundClose
+                    | TokenKind::BraceSquareClose
+            ) => Some(Box::new(self.parse_expression()?.ok_or(expected_expression_error!(keyword))?)),
+            _ => None,
+        };
+
+        Ok(value)
+    }
+
+    fn parse_function_declaration_expression(&mut self) -> Result<FunctionDeclarationExpression, ParserError> {
+        let token = peek_token!(self).clone();
+        let mut outer_span = Span::start_from(token.start);
+
+        // The `fn` is optional when the body is a block, see `is_function_shorthand`
+        if token.kind == TokenKind::Function {
+            self.advance_and_skip_whitespace();
+        }
+
+        let mut name = None;
+
+        if peek_token!(self).kind == TokenKind::Identifier {
+            name = Some(Box::new(self.parse_identifier_expression()?));
+            self.skip_whitespace();
+        }
+
+        let open = self.consume_token(TokenKind::BraceRoundOpen)?;
+
+        self.open_delimiters.push(open);
+
+        let mut parameters: Vec<Parameter> = vec![];
+
+        loop {
+            self.skip_whitespace();
+
+            let token = peek_token!(self).clone();
+            outer_span = outer_span.extend(token.end);
+
+            if token.kind == TokenKind::BraceRoundClose {
+                self.advance();
+                self.open_delimiters.pop();
+
+                break;
+            }
+
+            let index = self.position;
+            let name = {
+                let expression = self.parse_expression()?.ok_or(expected_expression_error!(token))?;
+
+                match expression {
+                    Expression::Identifier(identifier_expression) => identifier_expression,
+                    _ => return Err(unexpected_expression_at_error!(expression.span().start, format!("Expected identifier or ')' but found {} expression", expression.kind_name()))),
+                }
+            };
+
+            if parameters.iter().any(|existing| existing.name.name == name.name) {
+                let message = format!("Duplicate parameter '{}'", name.name);
+
+                return Err(
+                    ParserError::new(message, name.span.start)
+                        .with_token_index(index)
+                        .with_span(*name.span)
+                        .with_label("already used by an earlier parameter"),
+                );
+            }
+
+            self.skip_whitespace();
+
+            let annotation = match self.try_consume_token(TokenKind::Colon) {
+                Some(colon) => {
+                    self.skip_whitespace();
+
+                    Some(Box::new(self.parse_type_annotation(&colon)?))
+                }
+                None => None,
+            };
+            let span = match &annotation {
+                Some(annotation) => name.span.extend(annotation.span().end),
+                None => *name.span,
+            };
+
+            outer_span = outer_span.extend(span.end);
+            parameters.push(Parameter {
+                span: Box::new(span),
+                name,
+                annotation,
+            });
+
+            self.skip_whitespace();
+
+            // Optionally consume a comma
+            self.try_consume_token(TokenKind::Comma);
+        }
+
+        // Loops enclosing the declaration can't be broken out of from within the body
+        let context = std::mem::replace(
+            &mut self.context,
+            ParserContext { is_loop: false, is_function: true, labels: vec![] },
+        );
+        let body = self.parse_statement();
+        self.context = context;
+
+        let body = body?.ok_or(expected_expression_at_error!(outer_span.end))?;
+
+        Ok(FunctionDeclarationExpression {
+            span: Box::new(outer_span.extend(body.span().end)),
+            name,
+            parameters: Box::new(parameters),
+            body: Box::new(body),
+        })
+    }
+
+    // === Helpers ===
+
+    /// Parses a type following the given token, e.g. the `Number` of `a: Number`.
+    fn parse_type_annotation(&mut self, after: &Token) -> Result<TypeExpression, ParserError> {
+        let message = format!("Expected a type after '{}'", after.kind.as_str().unwrap_or_default());
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(ParserError::new(message, after.end).with_kind(ParserErrorKind::UnexpectedEof)),
+        };
+
+        match token.kind {
+            TokenKind::Identifier => {
+                let name = IdentifierExpression::from_token(&token)?;
+
+                self.advance();
+
+                Ok(TypeExpression::Named(Box::new(name)))
+            }
+            TokenKind::BraceSquareOpen => {
+                self.advance();
+
+                let close = self.expect(TokenKind::BraceSquareClose, "after '[' in an array type")?.clone();
+
+                self.advance();
+
+                let element = self.parse_type_annotation(&close)?;
+
+                Ok(TypeExpression::Array(Box::new(ArrayTypeExpression {
+                    span: Box::new(Span::new(token.start, element.span().end)),
+                    element: Box::new(element),
+                })))
+            }
+            _ => Err(ParserError::new(message, token.start).with_span(Span::new(token.start, token.end))),
+        }
+    }
+
+    /// The error for a closing bracket where an expression was expected, e.g. the `}` of `f(})`.
+    /// Any errors after an unclosed bracket are likely caused by it, so it's pointed out.
+    fn stray_closing_delimiter_error(&self, token: &Token) -> ParserError {
+        let closing = token.kind.as_str().unwrap_or_default();
+        let error = match self.open_delimiters.last() {
+            Some(open) => {
+                let opening = open.kind.as_str().unwrap_or_default();
+                let (message, label) = match closing_delimiter(&open.kind) == Some(token.kind.clone()) {
+                    true => (
+                        format!("Unexpected '{}'; expected an expression before closing the '{}'", closing, opening),
+                        format!("the '{}' opened here", opening),
+                    ),
+                    false => (
+                        format!("Unexpected '{}'; the '{}' is still open", closing, opening),
+                        format!("this '{}' isn't closed", opening),
+                    ),
+                };
+
+                ParserError::new(message, token.start).with_secondary(Span::new(open.start, open.end), label)
+            }
+            None => {
+                let name = match token.kind {
+                    TokenKind::BraceCurlyClose => "block",
+                    TokenKind::BraceSquareClose => "bracket",
+                    _ => "parenthesis",
+                };
+
+                ParserError::new(format!("Unexpected '{}'; no {} is open", closing, name), token.start)
+            }
+        };
+
+        error.with_span(Span::new(token.start, token.end))
+    }
+
+    fn warn_empty_block(&mut self, expression: &Expression, message: &str) {
+        if let Expression::Block(block) = expression {
+            if block.statements.is_empty() && block.tail.is_none() {
+                self.warnings.push(Diagnostic::warning(message, *block.span));
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Returns the current token without consuming it, or an error if it isn't of the given kind.
+    /// The context describes where the token was expected, e.g. "to open a block".
+    fn expect(&self, kind: TokenKind, context: &str) -> Result<&'a Token, ParserError> {
+        let token = peek_token!(self);
+
+        if token.kind != kind {
+            return Err(unexpected_token_error!(token, format!("Expected '{}' {}", kind, context)));
+        }
+
+        Ok(token)
+    }
+
+    fn advance(&mut self) {
+        self.position += 1;
+    }
+
+    /// Skips whitespace, newlines and comments.
+    fn skip_whitespace(&mut self) {
+        while let Some(token) = self.peek() {
+            match token.kind.is_trivia() {
+                true => self.advance(),
+                false => break,
+            }
+        }
+    }
+
+    /// Skips whitespace and comments up to the end of the line. Comments run to the end of the
+    /// line, so the newline after a trailing comment is kept.
+    fn skip_inline_whitespace(&mut self) {
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Whitespace | TokenKind::Comment | TokenKind::DocComment | TokenKind::Shebang => self.advance(),
+                _ => break,
+            }
+        }
+    }
+
+    fn advance_and_skip_whitespace(&mut self) {
+        self.advance();
+        self.skip_whitespace();
+    }
+
+    fn try_consume_token(&mut self, kind: TokenKind) -> Option<Token> {
+        let token = self.peek()?.clone();
+
+        if token.kind == kind {
+            self.advance();
+            return Some(token.clone());
+        }
+
+        None
+    }
+
+    fn consume_token(&mut self, kind: TokenKind) -> Result<Token, ParserError> {
+        let token = peek_token!(self).clone();
+
+        if token.kind != kind {
+            return Err(unexpected_token_error!(token, format!("Expected '{}'", kind)));
+        }
+
+        self.advance();
+
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{catch_unwind, AssertUnwindSafe},
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use lexer::{default_lexer, token::{Token, TokenKind, TokenValue}};
+
+    use crate::{
+        ast::{Expression, InfixOperatorKind, LiteralExpressionValue, MatchPattern, PostfixOperatorKind, PrefixOperatorKind, Program, TemplatePart},
+        diagnostic::Severity,
+        parse_source,
+        precedence::PrattConfig,
+        parser_error::ParserErrorKind,
+        span::Span,
+        Parser,
+        ParserContext,
+    };
+
+    fn parse(source: &str) -> Program {
+        match parse_source(source) {
+            Ok(program) => program,
+            Err(diagnostics) => panic!("failed to parse {source:?}: {diagnostics:?}"),
+        }
+    }
+
+    /// The position of the error parsing the source.
+    fn parse_error(source: &str) -> usize {
+        let tokens = default_lexer().lex(source).tokens;
+
+        match Parser::new(&tokens).parse() {
+            Ok(program) => panic!("expected {source:?} to fail, got {:?}", program.ast),
+            Err(error) => error.position,
+        }
+    }
+
+    /// The message and position of the error parsing the source.
+    fn error(source: &str) -> (String, usize) {
+        let tokens = default_lexer().lex(source).tokens;
+
+        match Parser::new(&tokens).parse() {
+            Ok(program) => panic!("expected {source:?} to fail, got {:?}", program.ast),
+            Err(error) => (error.message, error.position),
+        }
+    }
+
+    #[test]
+    fn parses_blank_and_comment_only_sources_to_an_empty_program() {
+        for source in ["", "   \n  ", "\r\n\t", "// comment", "// one\n\n// two\n"] {
+            let program = parse(source);
+
+            assert!(program.ast.is_empty(), "{source:?}: {:?}", program.ast);
+            assert!(program.warnings.is_empty(), "{source:?}");
+        }
+    }
+
+    /// The message and span of each warning, checking that they are all warnings.
+    fn warnings(source: &str) -> Vec<(String, Span)> {
+        parse(source)
+            .warnings
+            .into_iter()
+            .inspect(|warning| assert_eq!(warning.severity, Severity::Warning))
+            .map(|warning| (warning.message, warning.span))
+            .collect()
+    }
+
+    #[test]
+    fn empty_if_and_else_blocks_are_warnings() {
+        assert_eq!(warnings("if a {}"), [("Empty if block".to_string(), Span::new(5, 7))]);
+        assert_eq!(warnings("if a { 1 } else {}"), [("Empty else block".to_string(), Span::new(16, 18))]);
+        assert_eq!(warnings("if a {} else if b {} else { 2 }"), [
+            ("Empty if block".to_string(), Span::new(5, 7)),
+            ("Empty if block".to_string(), Span::new(18, 20)),
+        ]);
+        assert_eq!(parse("if a {}").to_string(), "if a {}");
+    }
+
+    #[test]
+    fn non_empty_blocks_are_not_warnings() {
+        assert!(warnings("if a { 1 } else { 2 }").is_empty());
+        assert!(warnings("while a {}").is_empty());
+    }
+
+    #[test]
+    fn parse_source_parses_a_clean_program() {
+        let program = parse("x := 1 + 2\n$ echo ${x}");
+
+        assert_eq!(program.to_string(), "x := 1 + 2\n$ echo ${x}\n");
+    }
+
+    #[test]
+    fn parse_source_returns_lexer_and_parser_errors_in_order() {
+        let diagnostics = parse_source("a := )\nb := @").expect_err("source should fail to parse");
+        let errors: Vec<(&str, Span)> =
+            diagnostics.iter().map(|diagnostic| (diagnostic.message.as_str(), diagnostic.span)).collect();
+
+        assert_eq!(errors, [
+            ("Unexpected ')'; no parenthesis is open", Span::new(5, 6)),
+            ("Unexpected character '@'", Span::new(12, 13)),
+        ]);
+    }
+
+    #[test]
+    fn explains_stray_closing_brackets() {
+        assert_eq!(error("x = }"), ("Unexpected '}'; no block is open".to_string(), 4));
+        assert_eq!(error("f(a, ]"), ("Unexpected ']'; the '(' is still open".to_string(), 5));
+        assert_eq!(error("{ x = ) }"), ("Unexpected ')'; the '{' is still open".to_string(), 6));
+        assert_eq!(error("[a, )").0, "Unexpected ')'; the '[' is still open");
+
+        // Closed brackets are no longer open
+        assert_eq!(error("(a) + )"), ("Unexpected ')'; no parenthesis is open".to_string(), 6));
+        assert_eq!(error("() + )"), ("Unexpected ')'; no parenthesis is open".to_string(), 5));
+        assert_eq!(error("f(a)[0] + ]").0, "Unexpected ']'; no bracket is open");
+    }
+
+    #[test]
+    fn program_from_str() {
+        let program: Program = "1 + 2\n$ ls".parse().expect("source should parse");
+        assert_eq!(program.ast.len(), 2);
+
+        let diagnostics = "1 +".parse::<Program>().expect_err("source should fail to parse");
+        assert_eq!(diagnostics[0].message, "Expected expression");
+
+        // Lexer errors fail the parse even when the tokens which were read parse
+        assert!("'ab'".parse::<Program>().is_err());
+    }
+
+    /// Parses with newlines separating statements, giving each statement as source.
+    fn parse_lines(source: &str) -> Vec<String> {
+        let tokens = default_lexer().lex(source).tokens;
+        let program = Parser::new(&tokens).with_newline_separators(true).parse().expect("source should parse");
+
+        program.ast.iter().map(Expression::to_string).collect()
+    }
+
+    #[test]
+    fn newlines_separate_statements_when_enabled() {
+        assert_eq!(parse_lines("a\n-b"), ["a", "-b"]);
+        assert_eq!(parse_lines("a -\nb"), ["a - b"]);
+        assert_eq!(parse_lines("a = 1\nb = 2"), ["a = 1", "b = 2"]);
+        assert_eq!(parse_lines("{ a\n-b }"), ["{ a; -b }"]);
+        assert_eq!(parse_lines("(if c {\n a\n -b\n})"), ["(if c { a; -b })"]);
+
+        // Without the option, the newline is only whitespace
+        assert_eq!(parse("a\n-b").to_string(), "a - b");
+    }
+
+    /// The text of each recorded doc and the source of the statement it documents.
+    fn docs(source: &str) -> Vec<(String, String)> {
+        let chars: Vec<char> = source.chars().collect();
+
+        parse(source)
+            .docs
+            .into_iter()
+            .map(|doc| (doc.text, chars[doc.span.start..doc.span.end].iter().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn records_doc_comments_against_the_following_statement() {
+        let source = "/// Says hello\n/// to someone\nsayHello = (name) {\n$ echo ${name}\n}\nsayHello(\"you\")";
+
+        assert_eq!(docs(source), [(
+            "Says hello\nto someone".to_string(),
+            "sayHello = (name) {\n$ echo ${name}\n}".to_string(),
+        )]);
+        assert_eq!(docs("f = () {\n/// inner\nx = 1\n}"), [("inner".to_string(), "x = 1".to_string())]);
+        assert_eq!(docs("/// a\n// plain\n\nx = 1"), [("a".to_string(), "x = 1".to_string())]);
+    }
+
+    #[test]
+    fn plain_comments_are_not_docs() {
+        assert!(docs("// plain\nx = 1").is_empty());
+        assert!(docs("x = 1\n/// dangling").is_empty());
+    }
+
+    #[test]
+    fn trailing_comments_are_skipped_without_hiding_the_newline() {
+        assert_eq!(parse_lines("x = 1 // set x\ny = 2"), ["x = 1", "y = 2"]);
+        assert_eq!(parse_lines("f(x) // call f\n-1"), ["f(x)", "-1"]);
+        assert_eq!(parse_lines("x = 1 // one\n// two\n-1"), ["x = 1", "-1"]);
+        assert_eq!(parse_lines("f(x, // first\ny)"), ["f(x, y)"]);
+
+        assert_eq!(kinds("x = 1 // set x\nf(x) // call f\n"), ["infix", "call"]);
+    }
+
+    #[test]
+    fn skips_a_leading_shebang() {
+        assert_eq!(kinds("#!/usr/bin/env cs\nx = 1"), ["infix"]);
+        assert_eq!(parse("#!/usr/bin/env cs\nx = 1").to_string(), "x = 1");
+        assert!(parse("#!cs").ast.is_empty());
+
+        let diagnostics = parse_source("x\n#!a").expect_err("a shebang after the first line should fail to lex");
+        assert_eq!((diagnostics[0].message.as_str(), diagnostics[0].span.start), ("Unexpected character '#'", 2));
+    }
+
+    #[test]
+    fn folds_prefix_plus_into_its_operand() {
+        assert_eq!(kinds("+x"), ["identifier"]);
+        assert_eq!(parse("+x").to_string(), "x");
+        assert_eq!(kinds("+ +x"), ["identifier"]);
+        assert_eq!(parse("+(x)").to_string(), "(x)");
+        assert_eq!(parse("a + +b").to_string(), "a + b");
+
+        let program = parse("-+x");
+        let [Expression::Prefix(prefix)] = program.ast.as_slice() else { panic!("expected a prefix, got {:?}", program.ast) };
+        assert_eq!(prefix.operator, PrefixOperatorKind::Minus);
+        assert_eq!(prefix.right.kind_name(), "identifier");
+
+        assert_eq!(error("+").0, "Expected expression");
+    }
+
+    #[test]
+    fn block_like_expressions_end_a_statement() {
+        assert_eq!(kinds("if a {1} else {2} + 3"), ["if", "literal"]);
+        assert_eq!(kinds("while a {} - 1"), ["while", "prefix"]);
+        assert_eq!(kinds("loop {} b"), ["loop", "identifier"]);
+        assert_eq!(kinds("{ a } [1]"), ["block", "array"]);
+        assert_eq!(kinds("match x {} + 1"), ["match", "literal"]);
+        assert_eq!(kinds("fn f() {} - 1"), ["function declaration", "prefix"]);
+        assert_eq!(parse("{ if a {1} else {2} + 3 }").to_string(), "{ if a { 1 } else { 2 }; 3 }");
+    }
+
+    #[test]
+    fn block_like_expressions_are_operands_outside_of_statement_position() {
+        assert_eq!(kinds("(if a {1} else {2}) + 3"), ["infix"]);
+        assert_eq!(parse("x = if a {1} else {2} + 3").to_string(), "x = (if a { 1 } else { 2 }) + 3");
+        assert_eq!(parse("f(loop {} + 1)").to_string(), "f((loop {}) + 1)");
+    }
+
+    #[test]
+    fn call_arguments_may_be_surrounded_by_whitespace() {
+        for source in ["f(a,b)", "f( a , b )", "f(\n a,\n b\n)", "f(\ta ,\r\n\tb ,\n)", "f( // first\n a, /// second\n b)"] {
+            assert_eq!(parse(source).to_string(), "f(a, b)", "{source:?}");
+        }
+
+        assert_eq!(parse("f( )").to_string(), "f()");
+        assert_eq!(parse("f(\n)").to_string(), "f()");
+    }
+
+    #[test]
+    fn newlines_within_brackets_are_whitespace() {
+        assert_eq!(parse_lines("f(a\n- b)"), ["f(a - b)"]);
+        assert_eq!(parse_lines("f(a,\nb)"), ["f(a, b)"]);
+        assert_eq!(parse_lines("(a\n+ b)"), ["(a + b)"]);
+        assert_eq!(parse_lines("[1\n-2]"), ["[1 - 2]"]);
+        assert_eq!(parse_lines("x[a\n+ 1]"), ["x[a + 1]"]);
+        assert_eq!(parse_lines("fn(a,\nb) { a\n-b }"), ["fn(a, b) { a; -b }"]);
+    }
+
+    fn kinds(source: &str) -> Vec<String> {
+        parse(source).ast.iter().map(Expression::kind_name).collect()
+    }
+
+    #[test]
+    fn parses_parentheses_as_unit_grouping_or_function() {
+        assert_eq!(kinds("()"), ["tuple"]);
+        assert_eq!(parse_lines("x = ()\n-1"), ["x = ()", "-1"]);
+        assert_eq!(kinds("(a)"), ["grouping"]);
+        assert_eq!(kinds("(a) + 1"), ["infix"]);
+        assert_eq!(kinds("() { }"), ["function declaration"]);
+        assert_eq!(kinds("(a, b) {\n a + b\n}"), ["function declaration"]);
+        assert_eq!(parse("sayHello = (name) { name }").to_string(), "sayHello = fn(name) { name }");
+    }
+
+    #[test]
+    fn a_block_on_the_line_after_parentheses_is_not_a_function_body() {
+        assert_eq!(kinds("x = (a)\n{ b }"), ["infix", "block"]);
+        assert_eq!(parse("x = (a)\n{ b }").to_string(), "x = (a)\n{ b }");
+        assert_eq!(kinds("(a) // c\n{ b }"), ["grouping", "block"]);
+        assert_eq!(kinds("()\n{ }"), ["tuple", "block"]);
+        assert_eq!(parse_lines("x = (a)\n{ b }"), ["x = (a)", "{ b }"]);
+
+        // Within the parameters newlines are still whitespace
+        assert_eq!(kinds("x = (\n  a,\n) { a }"), ["infix"]);
+    }
+
+    #[test]
+    fn parses_comma_separated_parentheses_as_tuples() {
+        for (source, elements) in [("(a, b)", 2), ("(a,)", 1), ("(a, b + 1, (c, d),)", 3)] {
+            let program = parse(source);
+            let [Expression::Tuple(tuple)] = program.ast.as_slice() else { panic!("expected a tuple for {source:?}, got {:?}", program.ast) };
+
+            assert_eq!((tuple.span.start, tuple.span.end), (0, source.len()), "{source}");
+            assert_eq!(tuple.elements.len(), elements, "{source}");
+        }
+
+        assert!(matches!(parse("(a)").ast.as_slice(), [Expression::Grouping(_)]));
+        assert_eq!(parse_error("(a b)"), 3);
+        assert_eq!(parse_error("(,)"), 1);
+    }
+
+    #[test]
+    fn parses_parentheses_before_the_block_of_a_condition_as_grouping() {
+        assert_eq!(parse("if (a) { 1 } else if (b) { 2 }").to_string(), "if (a) { 1 } else if (b) { 2 }");
+        assert_eq!(parse("while (a) { }").to_string(), "while (a) {}");
+        assert_eq!(parse("for x in (xs) { }").to_string(), "for x in (xs) {}");
+        assert_eq!(parse("match (x) { 1 => 2 }").to_string(), "match (x) { 1 => 2 }");
+
+        // Unless the block is nested within the condition
+        assert_eq!(parse("if f((a) { a }) { }").to_string(), "if f(fn(a) { a }) {}");
+        assert_eq!(parse("if { (a) { a } } { }").to_string(), "if { fn(a) { a } } {}");
+    }
+
+    #[test]
+    fn parses_named_functions() {
+        let name = |expression: &Expression| match expression {
+            Expression::FunctionDeclaration(function) => function.name.as_ref().map(|name| name.name.clone()),
+            expression => panic!("expected a function declaration, found {}", expression.kind_name()),
+        };
+
+        assert_eq!(name(&parse("fn f() { return 1 }").ast[0]), Some("f".to_string()));
+        assert_eq!(name(&parse("fn() { return 1 }").ast[0]), None);
+
+        let program = parse("x := fn g(a) a");
+        let [Expression::Infix(assignment)] = program.ast.as_slice() else { panic!("expected an assignment, got {:?}", program.ast) };
+
+        assert_eq!(name(&assignment.right), Some("g".to_string()));
+    }
+
+    #[test]
+    fn return_is_only_allowed_in_functions() {
+        assert_eq!(error("return 1"), ("Return outside of function".to_string(), 0));
+        parse("fn f() { fn g() { return 1 }\n return 2 }");
+
+        // Leaving a nested function restores the outer context
+        assert_eq!(error("fn f() { fn g() {} }\nreturn"), ("Return outside of function".to_string(), 21));
+    }
+
+    #[test]
+    fn reports_keywords_outside_of_their_context() {
+        assert_eq!(error("else { }"), ("'else' without matching 'if'".to_string(), 0));
+        assert_eq!(error("if x {} else {} else {}"), ("'else' without matching 'if'".to_string(), 16));
+        assert_eq!(error("break"), ("Break expression outside of loop".to_string(), 0));
+        assert_eq!(error("continue"), ("Continue expression outside of loop".to_string(), 0));
+
+        let program = parse("if x { }\nelse { }");
+        let [Expression::If(expression)] = program.ast.as_slice() else { panic!("expected an if, got {:?}", program.ast) };
+
+        assert!(expression.default.is_some());
+    }
+
+    #[test]
+    fn rejects_assignments_in_conditions() {
+        let message = |source: &str| parse_source(source).expect_err("source should fail to parse")[0].message.clone();
+
+        assert_eq!(message("if x = 1 {}"), "Assignment in condition; did you mean '=='?");
+        assert_eq!(message("if a {} else if b = 1 {}"), "Assignment in condition; did you mean '=='?");
+
+        for source in ["if x := 1 {}", "if x ||= 1 {}", "if x &&= 1 {}", "while x := 1 {}", "while x >>>= 1 {}"] {
+            assert_eq!(message(source), "Assignment in condition; wrap it in parentheses if this is intended", "{source}");
+        }
+
+        assert_eq!(parse_source("if x = 1 {}").expect_err("source should fail to parse")[0].span, Span::new(3, 8));
+        parse("if (x := 1) {}");
+        parse("if (x = next()) {}");
+        parse("while (x = next()) {}");
+        parse("if x == 1 {}");
+    }
+
+    #[test]
+    fn parses_boolean_literals() {
+        for (source, expected) in [("true", true), ("false", false)] {
+            let program = parse(source);
+            let [Expression::Literal(literal)] = program.ast.as_slice() else { panic!("expected a literal, got {:?}", program.ast) };
+
+            assert_eq!(*literal.span, Span::new(0, source.len()));
+            assert_eq!(*literal.value, LiteralExpressionValue::Boolean(expected));
+        }
+    }
+
+    #[test]
+    fn parses_negated_boolean_literals() {
+        let program = parse("!true");
+        let [Expression::Prefix(prefix)] = program.ast.as_slice() else { panic!("expected a prefix, got {:?}", program.ast) };
+
+        assert_eq!(*prefix.span, Span::new(0, 5));
+        assert_eq!(prefix.operator, PrefixOperatorKind::Bang);
+
+        let Expression::Literal(literal) = prefix.right.as_ref() else { panic!("expected a literal, got {:?}", prefix.right) };
+
+        assert_eq!(*literal.span, Span::new(1, 5));
+        assert_eq!(*literal.value, LiteralExpressionValue::Boolean(true));
+    }
+
+    #[test]
+    fn parses_interpolated_strings_into_templates() {
+        let program = parse("\"a${1+2}b\"");
+        let [Expression::TemplateString(template)] = program.ast.as_slice() else { panic!("expected a template string, got {:?}", program.ast) };
+
+        assert_eq!(*template.span, Span::new(0, 10));
+
+        let [TemplatePart::String(a), TemplatePart::Expression(sum), TemplatePart::String(b)] = template.parts.as_slice() else {
+            panic!("expected three parts, got {:?}", template.parts)
+        };
+
+        assert_eq!((a.as_str(), b.as_str()), ("a", "b"));
+
+        let Expression::Infix(sum) = sum else { panic!("expected an infix expression, got {sum:?}") };
+
+        assert_eq!(sum.operator, InfixOperatorKind::Plus);
+        assert_eq!(*sum.span, Span::new(4, 7));
+        assert_eq!((sum.left.to_string(), sum.right.to_string()), ("1".to_string(), "2".to_string()));
+    }
+
+    #[test]
+    fn parses_named_interpolations() {
+        let program = parse("\"Hi $name!\"");
+        let [Expression::TemplateString(template)] = program.ast.as_slice() else { panic!("expected a template string, got {:?}", program.ast) };
+
+        let [_, TemplatePart::Expression(name), _] = template.parts.as_slice() else { panic!("expected three parts, got {:?}", template.parts) };
+
+        assert_eq!(name.span(), Span::new(5, 9));
+        assert_eq!(program.to_string(), "\"Hi ${name}!\"");
+    }
+
+    #[test]
+    fn parses_strings_without_interpolations_as_literals() {
+        let program = parse("\"a b\"");
+
+        assert!(matches!(program.ast.as_slice(), [Expression::Literal(_)]), "{:?}", program.ast);
+    }
+
+    #[test]
+    fn parses_match_expressions() {
+        let program = parse("match x {\n  1 => \"one\",\n  other => other\n}");
+        let [Expression::Match(expression)] = program.ast.as_slice() else { panic!("expected a match, got {:?}", program.ast) };
+
+        assert_eq!((expression.span.start, expression.span.end), (0, 42));
+        assert!(matches!(&*expression.scrutinee, Expression::Identifier(identifier) if identifier.name == "x"));
+
+        let [one, other] = expression.arms.as_slice() else { panic!("expected two arms, got {:?}", expression.arms) };
+
+        assert!(matches!(*one.pattern, MatchPattern::Literal(_)));
+        assert_eq!((one.span.start, one.span.end), (12, 22));
+        assert!(matches!(*one.expression, Expression::Literal(_)));
+        assert!(matches!(&*other.pattern, MatchPattern::Identifier(identifier) if identifier.name == "other"));
+        assert!(matches!(&*other.expression, Expression::Identifier(identifier) if identifier.name == "other"));
+
+        for (source, arms) in [("match x { 1 => 2, }", 1), ("match x {}", 0)] {
+            let program = parse(source);
+            let [Expression::Match(expression)] = program.ast.as_slice() else { panic!("expected a match for {source:?}, got {:?}", program.ast) };
+
+            assert_eq!(expression.arms.len(), arms, "{source}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_match_arms() {
+        let error = |source: &str| {
+            let tokens = default_lexer().lex(source).tokens;
+            let error = Parser::new(&tokens).parse().expect_err("source should fail to parse");
+            (error.message, error.position)
+        };
+
+        assert_eq!(error("match x { 1 2 }"), ("Unexpected token of kind Number: Expected '=>'".to_string(), 12));
+        assert_eq!(error("match x { 1 => 2 3 => 4 }"), ("Unexpected token of kind Number: Expected ',' or '}'".to_string(), 17));
+        assert_eq!(error("match x { 1 + 1 => 2 }").0, "Unexpected token of kind Plus: Expected '=>'");
+        assert_eq!(error("match x { -1 => 2 }").0, "Unexpected token of kind Minus: Expected a literal or identifier pattern");
+        assert_eq!(error("match x { 1 => }"), ("Unexpected '}'; expected an expression before closing the '{'".to_string(), 15));
+    }
+
+    #[test]
+    fn parses_postfix_bang_only_directly_after_its_operand() {
+        let program = parse("a!");
+        let [Expression::Postfix(postfix)] = program.ast.as_slice() else { panic!("expected a postfix, got {:?}", program.ast) };
+        assert_eq!(postfix.operator, PostfixOperatorKind::Bang);
+        assert_eq!(*postfix.span, Span::new(0, 2));
+        assert_eq!(postfix.left.to_string(), "a");
+
+        assert_eq!(kinds("a ! b"), ["identifier", "prefix"]);
+        assert_eq!(kinds("a!b"), ["identifier", "prefix"]);
+        assert_eq!(kinds("a != b"), ["infix"]);
+        assert_eq!(kinds("a! + 1"), ["infix"]);
+        assert_eq!(kinds("f(x)!"), ["postfix"]);
+
+        // Postfix binds tighter than prefix
+        let program = parse("!a!");
+        let [Expression::Prefix(prefix)] = program.ast.as_slice() else { panic!("expected a prefix, got {:?}", program.ast) };
+        assert_eq!(prefix.right.kind_name(), "postfix");
+    }
+
+    #[test]
+    fn rejects_postfix_increment_and_decrement() {
+        assert_eq!(error("i++"), ("Postfix increment is not supported".to_string(), 1));
+        assert_eq!(error("i--"), ("Postfix decrement is not supported".to_string(), 1));
+        assert_eq!(error("a = i++ + 1"), ("Postfix increment is not supported".to_string(), 5));
+
+        // Unless the operators are separate, or the second is a prefix of the next operand
+        assert_eq!(parse("i + +j").to_string(), "i + j");
+        assert_eq!(parse("i - -j").to_string(), "i - -j");
+        assert_eq!(parse("i + ++j").to_string(), "i + j");
+        assert_eq!(parse("--i").to_string(), "--i");
+    }
+
+    #[test]
+    fn parses_commands_with_interpolations() {
+        let program = parse("$ echo ${a} ${b + 1}");
+        let [Expression::Command(command)] = program.ast.as_slice() else { panic!("expected a command, got {:?}", program.ast) };
+
+        assert_eq!(*command.span, Span::new(0, 20));
+
+        let parts: Vec<String> = command
+            .parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::String(value) => format!("{value:?}"),
+                TemplatePart::Expression(expression) => format!("{} at {:?}", expression, expression.span()),
+            })
+            .collect();
+
+        assert_eq!(parts, [
+            "\"echo \"",
+            "a at Span { start: 9, end: 10 }",
+            "\" \"",
+            "b + 1 at Span { start: 14, end: 19 }",
+        ]);
+    }
+
+    #[test]
+    fn parses_commands_as_operands() {
+        assert_eq!(parse("x := $ ls -la\ny").to_string(), "x := $ ls -la\ny");
+        assert_eq!(parse("$ ${1}").to_string(), "$ ${1}\n");
+    }
+
+    #[test]
+    fn reports_errors_inside_command_interpolations() {
+        let span = |source: &str| parse_source(source).expect_err("source should fail to parse")[0].span;
+
+        // Just after the `+`, rather than relative to the interpolation
+        assert_eq!(span("$ echo ${a +}"), Span::new(12, 12));
+        assert_eq!(span("x\n$ echo ${) }").start, 11);
+        assert_eq!(span("$ echo ${a @}"), Span::new(11, 12));
+    }
+
+    /// Fragments covering every token kind, joined at random into (mostly invalid) sources.
+    const FRAGMENTS: &[&str] = &[
+        "a", "b", "1", "2.5", "\"s\"", "\"t ${a}\"", "\"${\"", "'c'", "true", "false", "$ echo ${a} b", "$ ${a +}",
+        "=", "==", "=>", "!=", "<", "<=", "<<", "<<=", ">", ">=", ">>", ">>=", ">>>", ">>>=", "/", "/=", "*", "*=",
+        "+", "+=", "-", "-=", "%", "%=", "^", "^=", "&", "&=", "&&", "&&=", "|", "|=", "||", "||=", ":", ":=", ".",
+        "..", "!", ",", ";", "{", "}", "[", "]", "(", ")", "if", "else", "while", "for", "in", "loop", "break",
+        "continue", "return", "fn", "match", "let", "'outer", "// c", "/// d", "#!/bin/sh", "\n", " ", "@", "\u{2028}",
+    ];
+
+    /// Xorshift, so that failures are reproducible without a dependency.
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    fn assert_no_panic(source: &str) {
+        if catch_unwind(AssertUnwindSafe(|| parse_source(source))).is_err() {
+            panic!("parse_source panicked on {source:?}");
+        }
+    }
+
+    /// Parses on the test thread, whose stack is the 2MB default of a spawned thread, so the
+    /// default max depth must fit within it in a debug build. Returns the printed program or the
+    /// first error.
+    fn parse_nested(source: String) -> Result<String, String> {
+        match parse_source(&source) {
+            Ok(program) => Ok(program.to_string()),
+            Err(diagnostics) => Err(diagnostics[0].message.clone()),
+        }
+    }
+
+    #[test]
+    fn rejects_deeply_nested_expressions_without_overflowing() {
+        let depth = 10_000;
+
+        for source in [
+            format!("{}x{}", "(".repeat(depth), ")".repeat(depth)),
+            format!("{}x", "-".repeat(depth)),
+            format!("{}{}", "{".repeat(depth), "}".repeat(depth)),
+            format!("{}x{}", "[".repeat(depth), "]".repeat(depth)),
+            format!("{}x{}", "f(".repeat(depth), ")".repeat(depth)),
+            format!("{}{}", "if x {".repeat(depth), "}".repeat(depth)),
+            format!("{}{}", "() {".repeat(depth), "}".repeat(depth)),
+            format!("{}1", "a = ".repeat(depth)),
+            format!("{}1{}", "\"${".repeat(depth), "}\"".repeat(depth)),
+        ] {
+            assert_eq!(parse_nested(source).unwrap_err(), "Expression nesting too deep");
+        }
+    }
+
+    #[test]
+    fn parses_nesting_up_to_the_max_depth() {
+        let parse_with_max_depth = |source: &str, max_depth: usize| {
+            let tokens = default_lexer().lex(source).tokens;
+            Parser::new(&tokens).with_max_depth(max_depth).parse().map(|program| program.to_string())
+        };
+
+        assert_eq!(parse_with_max_depth("((x))", 3).unwrap(), "((x))");
+        assert_eq!(parse_with_max_depth("(((x)))", 3).unwrap_err().message, "Expression nesting too deep");
+        // Interpolations count towards the depth of the string they're in
+        assert_eq!(parse_with_max_depth("(\"${x}\")", 3).unwrap(), "(\"${x}\")");
+        assert_eq!(parse_with_max_depth("(\"${(x)}\")", 3).unwrap_err().message, "Expression nesting too deep");
+    }
+
+    #[test]
+    fn parses_nesting_up_to_the_default_max_depth_on_a_test_thread() {
+        for (open, close) in [("(", ")"), ("[", "]"), ("f(", ")"), ("-", ""), ("\"${", "}\"")] {
+            let source = format!("{}x{}", open.repeat(95), close.repeat(95));
+            assert_eq!(parse_nested(source.clone()), Ok(source), "{open:?}");
+
+            let source = format!("{}x{}", open.repeat(96), close.repeat(96));
+            assert_eq!(parse_nested(source).unwrap_err(), "Expression nesting too deep", "{open:?}");
+        }
+    }
+
+    /// The token index of the error and the kind of the token at that index.
+    fn error_token(source: &str) -> (Option<usize>, Option<TokenKind>) {
+        let tokens = default_lexer().lex(source).tokens;
+        let error = Parser::new(&tokens).parse().expect_err("source should fail to parse");
+
+        (error.token_index, error.token_index.map(|index| tokens[index].kind.clone()))
+    }
+
+    #[test]
+    fn parser_errors_record_the_failing_token_index() {
+        assert_eq!(error_token("x = )"), (Some(4), Some(TokenKind::BraceRoundClose)));
+        assert_eq!(error_token("x = [1 2]"), (Some(7), Some(TokenKind::Number)));
+        assert_eq!(error_token("fn f(a, a) {}"), (Some(7), Some(TokenKind::Identifier)));
+    }
+
+    fn error_kind(source: &str) -> ParserErrorKind {
+        let tokens = default_lexer().lex(source).tokens;
+
+        Parser::new(&tokens).parse().expect_err("source should fail to parse").kind
+    }
+
+    #[test]
+    fn incomplete_input_is_an_unexpected_end_of_file() {
+        for source in ["if x {", "if x { 1", "x = ", "f(1,", "[1, 2", "fn f(a", "match x {"] {
+            assert_eq!(error_kind(source), ParserErrorKind::UnexpectedEof, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_input_is_a_syntax_error() {
+        for source in ["1 +)", "x = )", "if x { ) }", "x = 1 }"] {
+            assert_eq!(error_kind(source), ParserErrorKind::Syntax, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn parses_labelled_loops_and_labelled_break_and_continue() {
+        let program = parse("'outer: loop { loop { break 'outer } }");
+        let [Expression::Loop(outer)] = program.ast.as_slice() else { panic!("expected a loop, got {:?}", program.ast) };
+        assert_eq!(outer.label.as_ref().map(|label| (label.name.as_str(), *label.span)), Some(("outer", Span::new(0, 6))));
+
+        let mut breaks = vec![];
+        program.walk(|expression| {
+            if let Expression::Break(expression) = expression {
+                breaks.push(expression.label.as_ref().map(|label| label.name.clone()));
+            }
+        });
+        assert_eq!(breaks, [Some("outer".to_string())]);
+
+        for source in ["'a: while x { continue 'a }", "'a: for i in xs { break 'a 1 }", "'a: loop { 'a: loop {} }"] {
+            assert_eq!(parse(source).to_string(), source);
+        }
+    }
+
+    fn parse_in(source: &str, context: ParserContext) -> Result<String, String> {
+        let tokens = default_lexer().lex(source).tokens;
+
+        Parser::new(&tokens).with_context(context).parse().map(|program| program.to_string()).map_err(|error| error.message)
+    }
+
+    #[test]
+    fn parses_fragments_within_a_preset_context() {
+        let in_loop = || ParserContext { is_loop: true, ..ParserContext::default() };
+        let in_function = ParserContext { is_function: true, ..ParserContext::default() };
+        let in_labelled_loop = ParserContext { is_loop: true, labels: vec!["outer".to_string()], ..ParserContext::default() };
+
+        assert_eq!(parse_in("break 1", in_loop()), Ok("break 1".to_string()));
+        assert_eq!(parse_in("continue", in_loop()), Ok("continue".to_string()));
+        assert_eq!(parse_in("return 1", in_function), Ok("return 1".to_string()));
+        assert_eq!(parse_in("break 'outer", in_labelled_loop), Ok("break 'outer".to_string()));
+        assert_eq!(parse_in("break 'outer", in_loop()), Err("Undefined label 'outer".to_string()));
+        assert!(parse_in("return 1", in_loop()).is_err());
+        assert!(parse_in("break 1", ParserContext::default()).is_err());
+    }
+
+    #[test]
+    fn rejects_labels_which_are_not_in_scope() {
+        let error = |source| {
+            let error = &parse_source(source).expect_err("the label should be rejected")[0];
+            (error.message.clone(), error.span)
+        };
+
+        assert_eq!(error("loop { break 'outer }"), ("Undefined label 'outer".to_string(), Span::new(13, 19)));
+        assert_eq!(error("loop { continue 'b }"), ("Undefined label 'b".to_string(), Span::new(16, 18)));
+        assert_eq!(error("'a: loop {}\nloop { break 'a }"), ("Undefined label 'a".to_string(), Span::new(25, 27)));
+        assert_eq!(error("'a: loop { f = () { break 'a } }").0, "Break expression outside of loop");
+        assert_eq!(error("'a: x").0, "Unexpected token of kind Identifier: Only loops can be labelled");
+    }
+
+    /// The span of the first expression of the kind in the program.
+    fn span_of(source: &str, kind: &str) -> Span {
+        let mut spans = vec![];
+        parse(source).walk(|expression| {
+            if expression.kind_name() == kind {
+                spans.push(expression.span());
+            }
+        });
+
+        spans.first().copied().unwrap_or_else(|| panic!("no {kind} in {source:?}"))
+    }
+
+    #[test]
+    fn control_flow_spans_cover_the_keyword_and_any_value() {
+        assert_eq!(span_of("f = () { return 1 + 2 }", "return"), Span::new(9, 21));
+        assert_eq!(span_of("f = () { return }", "return"), Span::new(9, 15));
+        assert_eq!(span_of("loop { break }", "break"), Span::new(7, 12));
+        assert_eq!(span_of("loop { break 1 + 2 }", "break"), Span::new(7, 18));
+        assert_eq!(span_of("loop { continue }", "continue"), Span::new(7, 15));
+        assert_eq!(span_of("'a: loop { break 'a }", "break"), Span::new(11, 19));
+        assert_eq!(span_of("'a: loop { break 'a 5 }", "break"), Span::new(11, 21));
+        assert_eq!(span_of("'a: loop { continue 'a }", "continue"), Span::new(11, 22));
+    }
+
+    /// The operator, left and right operands of the infix expression.
+    fn infix(source: &str) -> (InfixOperatorKind, String, String) {
+        match parse(source).ast.remove(0) {
+            Expression::Infix(infix) => (infix.operator, infix.left.to_string(), infix.right.to_string()),
+            expression => panic!("expected an infix expression, got {expression:?}"),
+        }
+    }
+
+    /// The spans of every infix expression in the source, checking each covers exactly its operands.
+    fn infix_spans(source: &str) -> Vec<Span> {
+        let mut spans = vec![];
+        parse(source).walk(|expression| {
+            if let Expression::Infix(infix) = expression {
+                assert_eq!(*infix.span, Span::new(infix.left.span().start, infix.right.span().end), "{source:?}");
+                spans.push(*infix.span);
+            }
+        });
+
+        spans
+    }
+
+    #[test]
+    fn infix_spans_cover_the_left_and_right_operands() {
+        assert_eq!(infix_spans("a + b * c"), [Span::new(0, 9), Span::new(4, 9)]);
+        assert_eq!(infix_spans("a * b + c"), [Span::new(0, 9), Span::new(0, 5)]);
+        assert_eq!(infix_spans("(a + b) + c"), [Span::new(0, 11), Span::new(1, 6)]);
+        assert_eq!(infix_spans("f(x) + 1"), [Span::new(0, 8)]);
+        assert_eq!(infix_spans("a.b(c) * d[0]"), [Span::new(0, 13)]);
+        assert_eq!(infix_spans("x = y = 1"), [Span::new(0, 9), Span::new(4, 9)]);
+    }
+
+    #[test]
+    fn infix_spans_start_at_the_operand_after_a_folded_prefix_plus() {
+        assert_eq!(infix_spans("+a + b"), [Span::new(1, 6)]);
+        assert_eq!(infix_spans("-a + b"), [Span::new(0, 6)]);
+    }
+
+    #[test]
+    fn parses_logical_assignments() {
+        let (and, or) = (InfixOperatorKind::AmpersandAmpersandEquals, InfixOperatorKind::PipePipeEquals);
+
+        assert_eq!(infix("a &&= b"), (and, "a".to_string(), "b".to_string()));
+        assert_eq!(infix("a ||= b"), (or, "a".to_string(), "b".to_string()));
+        assert!(and.is_assignment() && or.is_assignment());
+    }
+
+    #[test]
+    fn logical_assignments_are_right_associative_with_assignment_precedence() {
+        assert_eq!(infix("a ||= b ||= c"), (InfixOperatorKind::PipePipeEquals, "a".to_string(), "b ||= c".to_string()));
+        assert_eq!(infix("a ||= b || c"), (InfixOperatorKind::PipePipeEquals, "a".to_string(), "b || c".to_string()));
+        assert_eq!(infix("a &&= b = c"), (InfixOperatorKind::AmpersandAmpersandEquals, "a".to_string(), "b = c".to_string()));
+        assert_eq!(infix("x = a &&= b"), (InfixOperatorKind::Equals, "x".to_string(), "a &&= b".to_string()));
+    }
+
+    #[test]
+    fn parses_array_literals() {
+        for (source, elements) in [("[]", 0), ("[1]", 1), ("[a, b + 1, [c]]", 3), ("[\n  1,\n  2,\n]", 2)] {
+            let program = parse(source);
+            let [Expression::Array(array)] = program.ast.as_slice() else { panic!("expected an array for {source:?}, got {:?}", program.ast) };
+
+            assert_eq!((array.span.start, array.span.end), (0, source.len()), "{source}");
+            assert_eq!(array.elements.len(), elements, "{source}");
+        }
+
+        assert_eq!(parse("[a, b + 1, [c],]").to_string(), "[a, b + 1, [c]]");
+        assert_eq!(parse_error("[1 2]"), 3);
+        assert_eq!(parse_error("[,]"), 1);
+    }
+
+    #[test]
+    fn parses_array_destructuring_assignments() {
+        let program = parse("[a, [b, c]] = xs");
+        let [Expression::Infix(assignment)] = program.ast.as_slice() else { panic!("expected an assignment, got {:?}", program.ast) };
+        let Expression::Array(pattern) = &*assignment.left else { panic!("expected an array pattern, got {:?}", assignment.left) };
+
+        assert_eq!(assignment.operator, InfixOperatorKind::Equals);
+        assert_eq!(pattern.elements.iter().map(Expression::kind_name).collect::<Vec<_>>(), ["identifier", "array"]);
+
+        assert_eq!(infix("[a, b] = xs"), (InfixOperatorKind::Equals, "[a, b]".to_string(), "xs".to_string()));
+        assert_eq!(infix("[a, b] := xs"), (InfixOperatorKind::ColonEquals, "[a, b]".to_string(), "xs".to_string()));
+        assert_eq!(infix("[a.b, c[0]] = xs").1, "[a.b, c[0]]");
+    }
+
+    #[test]
+    fn rejects_array_patterns_with_non_assignable_elements() {
+        assert_eq!(error("[1, b] = xs"), ("Cannot assign to this literal expression".to_string(), 1));
+        assert_eq!(error("[a, b + 1] = xs"), ("Cannot assign to this infix expression".to_string(), 4));
+        assert_eq!(error("[a, [1]] = xs").1, 5);
+
+        // Comparison isn't assignment, so any elements are fine
+        assert_eq!(kinds("[1, b] == xs"), ["infix"]);
+    }
+
+    /// The nesting of calls and member accesses, e.g. `Call(Member(a, b))` for `a.b()`.
+    fn shape(expression: &Expression) -> String {
+        match expression {
+            Expression::Call(call) => format!("Call({})", shape(&call.callee)),
+            Expression::Member(member) => format!("Member({}, {})", shape(&member.object), member.property.name),
+            expression => expression.to_string(),
+        }
+    }
+
+    fn parse_with(source: &str, config: PrattConfig) -> Program {
+        let tokens = default_lexer().lex(source).tokens;
+
+        Parser::new(&tokens).with_config(config).parse().expect("source should parse")
+    }
+
+    /// The operators of the infix expressions in the program, outermost first.
+    fn infix_operators(program: &Program) -> Vec<InfixOperatorKind> {
+        let mut operators = vec![];
+        program.walk(|expression| {
+            if let Expression::Infix(infix) = expression {
+                operators.push(infix.operator);
+            }
+        });
+
+        operators
+    }
+
+    #[test]
+    fn overriding_a_binding_power_changes_the_parse_tree() {
+        let (plus, star) = (InfixOperatorKind::Plus, InfixOperatorKind::Star);
+
+        assert_eq!(infix_operators(&parse("a + b * c")), [plus, star]);
+        assert_eq!(infix_operators(&parse_with("a + b * c", PrattConfig::default())), [plus, star]);
+
+        let program = parse_with("a + b * c", PrattConfig::default().with_infix(plus, 30, 31));
+        assert_eq!(infix_operators(&program), [star, plus]);
+        let Expression::Infix(infix) = &program.ast[0] else { panic!("expected an infix") };
+        assert_eq!((infix.left.to_string(), infix.right.to_string()), ("a + b".to_string(), "c".to_string()));
+    }
+
+    #[test]
+    fn overridden_associativity_applies() {
+        let minus = InfixOperatorKind::Minus;
+        let program = parse_with("a - b - c", PrattConfig::default().with_infix(minus, 12, 11));
+        let Expression::Infix(infix) = &program.ast[0] else { panic!("expected an infix") };
+
+        assert_eq!((infix.left.to_string(), infix.right.to_string()), ("a".to_string(), "b - c".to_string()));
+    }
+
+    #[test]
+    fn interpolations_are_parsed_with_the_same_config() {
+        let config = PrattConfig::default().with_infix(InfixOperatorKind::Plus, 30, 31);
+        let program = parse_with("\"${a + b * c}\"", config);
+
+        assert_eq!(infix_operators(&program), [InfixOperatorKind::Star, InfixOperatorKind::Plus]);
+    }
+
+    #[test]
+    fn parses_member_and_call_chains_left_to_right() {
+        assert_eq!(shape(&parse("a.b.c()").ast[0]), "Call(Member(Member(a, b), c))");
+        assert_eq!(shape(&parse("a.b().c").ast[0]), "Member(Call(Member(a, b)), c)");
+        assert_eq!(shape(&parse("f().g()").ast[0]), "Call(Member(Call(f), g))");
+
+        let program = parse("f = () { return result.code }");
+        let mut returned = vec![];
+        program.walk(|expression| {
+            if let Expression::Return(expression) = expression {
+                returned.extend(expression.value.as_deref().map(shape));
+            }
+        });
+        assert_eq!(returned, ["Member(result, code)"]);
+    }
+
+    #[test]
+    fn numbers_must_start_with_a_digit() {
+        let error = |source: &str| {
+            let error = Parser::new(&default_lexer().lex(source).tokens).parse().expect_err("source should fail to parse");
+            (error.message, error.span)
+        };
+        let message = "Numbers must start with a digit, e.g. '0.5' rather than '.5'".to_string();
+
+        assert_eq!(error(".5"), (message.clone(), Some(Span::new(0, 2))));
+        assert_eq!(error("x = .5"), (message, Some(Span::new(4, 6))));
+        assert_eq!(error(". 5"), ("Unexpected token of kind Dot".to_string(), Some(Span::new(0, 1))));
+        assert_eq!(kinds("0.5"), ["literal"]);
+    }
+
+    #[test]
+    fn member_access_with_a_number_is_an_error() {
+        let error = |source: &str| {
+            let error = Parser::new(&default_lexer().lex(source).tokens).parse().expect_err("source should fail to parse");
+            (error.message, error.span)
+        };
+        let message = "Unexpected token of kind Number: Expected 'Identifier' after '.'".to_string();
+
+        assert_eq!(error("a.5"), (message.clone(), Some(Span::new(2, 3))));
+        assert_eq!(error("a . 5"), (message, Some(Span::new(4, 5))));
+    }
+
+    #[test]
+    fn tokens_after_a_complete_program_are_rejected_at_the_first_one() {
+        assert_eq!(error_token("x = 1 }"), (Some(6), Some(TokenKind::BraceCurlyClose)));
+        assert_eq!(error_token("x ;;"), (Some(3), Some(TokenKind::Semicolon)));
+        assert_eq!(error_token("1 2 )"), (Some(4), Some(TokenKind::BraceRoundClose)));
+
+        let ident = |name: &str, start| Token { kind: TokenKind::Identifier, start, end: start + 1, value: TokenValue::String(name.to_string()) };
+        let tokens = vec![ident("x", 0), Token { kind: TokenKind::Else, start: 1, end: 5, value: TokenValue::None }, ident("y", 5)];
+        let error = Parser::new(&tokens).parse().expect_err("tokens should fail to parse");
+        assert_eq!((error.token_index, error.span), (Some(1), Some(Span::new(1, 5))));
+    }
+
+    #[test]
+    fn parse_terminates_on_tokens_which_cannot_start_a_statement() {
+        let token = |kind| Token { kind, start: 0, end: 1, value: TokenValue::None };
+        let streams = [
+            vec![token(TokenKind::Semicolon)],
+            vec![token(TokenKind::BraceCurlyClose), token(TokenKind::BraceCurlyClose)],
+            vec![token(TokenKind::Whitespace), token(TokenKind::Comma), token(TokenKind::NewLine)],
+            vec![token(TokenKind::In)],
+            vec![token(TokenKind::FatArrow)],
+        ];
+
+        for tokens in streams {
+            let (sender, receiver) = mpsc::channel();
+            let kinds: Vec<TokenKind> = tokens.iter().map(|token| token.kind.clone()).collect();
+
+            thread::spawn(move || sender.send(Parser::new(&tokens).parse().map(|program| program.to_string())));
+
+            let result = receiver.recv_timeout(Duration::from_secs(10)).unwrap_or_else(|_| panic!("parse did not terminate on {kinds:?}"));
+            let error = result.expect_err("tokens should fail to parse");
+            assert!(error.message.starts_with("Unexpected"), "{kinds:?}: {}", error.message);
+            assert!(error.token_index.is_some(), "{kinds:?}");
+        }
+    }
+
+    /// The statements and tail of the block assigned in the source.
+    fn block(source: &str) -> (Vec<String>, Option<String>) {
+        let program = parse(source);
+        let [Expression::Infix(assignment)] = program.ast.as_slice() else { panic!("expected an assignment, got {:?}", program.ast) };
+        let Expression::Block(block) = &*assignment.right else { panic!("expected a block, got {:?}", assignment.right) };
+
+        (block.statements.iter().map(Expression::to_string).collect(), block.tail.as_ref().map(|tail| tail.to_string()))
+    }
+
+    #[test]
+    fn the_last_expression_of_a_block_is_its_tail() {
+        assert_eq!(block("x = { a; b }"), (vec!["a".to_string()], Some("b".to_string())));
+        assert_eq!(block("x = { a }"), (vec![], Some("a".to_string())));
+        assert_eq!(block("x = { a\n b\n }"), (vec!["a".to_string()], Some("b".to_string())));
+        assert_eq!(block("x = { if c { 1 } }"), (vec![], Some("if c { 1 }".to_string())));
+        assert_eq!(block("x = { if c { 1 } a }"), (vec!["if c { 1 }".to_string()], Some("a".to_string())));
+    }
+
+    #[test]
+    fn semicolons_separate_statements() {
+        let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(block("x = { a; b; c }"), (strings(&["a", "b"]), Some("c".to_string())));
+        assert_eq!(block("x = { a; b; c; }"), (strings(&["a", "b", "c"]), None));
+        assert_eq!(block("x = { a\n b; c }"), (strings(&["a", "b"]), Some("c".to_string())));
+        assert_eq!(block("x = { a;\n b;\n }"), (strings(&["a", "b"]), None));
+        assert_eq!(kinds("a; -b; c;"), ["identifier", "prefix", "identifier"]);
+    }
+
+    #[test]
+    fn programs_and_blocks_split_statements_alike() {
+        for body in ["a; b; c", "a\nb\nc", "a\n-b; c;", "if a { 1 }\n(b)", "x = 1 // c\n\n;y", "f(a,\nb)\n[1]"] {
+            let program: Vec<String> = parse(body).ast.iter().map(Expression::to_string).collect();
+            let (statements, tail) = block(&format!("x = {{\n{body}\n}}"));
+
+            assert_eq!(program, statements.into_iter().chain(tail).collect::<Vec<_>>(), "{body:?}");
+        }
+    }
+
+    #[test]
+    fn a_block_without_a_trailing_expression_has_no_tail() {
+        assert_eq!(block("x = {}"), (vec![], None));
+        assert_eq!(block("x = { a; b; }"), (vec!["a".to_string(), "b".to_string()], None));
+        assert_eq!(block("x = { if c { 1 }; }"), (vec!["if c { 1 }".to_string()], None));
+    }
+
+    #[test]
+    fn unique_function_parameters_parse() {
+        let program = parse("fn f(a, b, c) {}");
+        let [Expression::FunctionDeclaration(function)] = program.ast.as_slice() else { panic!("expected a function, got {:?}", program.ast) };
+
+        assert_eq!(function.parameters.len(), 3);
+    }
+
+    /// The name and type, as written, of each parameter of the function declared by the source.
+    fn parameters(source: &str) -> Vec<(String, Option<String>)> {
+        let program = parse(source);
+        let [Expression::FunctionDeclaration(function)] = program.ast.as_slice() else { panic!("expected a function, got {:?}", program.ast) };
+
+        function
+            .parameters
+            .iter()
+            .map(|parameter| (parameter.name.name.clone(), parameter.annotation.as_ref().map(|annotation| annotation.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn parses_typed_and_untyped_parameters() {
+        let typed = |name: &str, annotation: &str| (name.to_string(), Some(annotation.to_string()));
+        let untyped = |name: &str| (name.to_string(), None);
+
+        assert_eq!(parameters("fn f(a: Number, b: String) {}"), [typed("a", "Number"), typed("b", "String")]);
+        assert_eq!(parameters("fn f(a, b) {}"), [untyped("a"), untyped("b")]);
+        assert_eq!(parameters("fn f(a: []String, b) {}"), [typed("a", "[]String"), untyped("b")]);
+        assert_eq!(parameters("fn f(a: [][]T) {}"), [typed("a", "[][]T")]);
+        assert_eq!(parameters("fn f(a : Number) {}"), [typed("a", "Number")]);
+        assert_eq!(parse("fn f(a: Number, b) {}").to_string(), "fn f(a: Number, b) {}");
+    }
+
+    #[test]
+    fn typed_parameter_spans_cover_the_type() {
+        let program = parse("fn f(a: []String, b) {}");
+        let [Expression::FunctionDeclaration(function)] = program.ast.as_slice() else { panic!("expected a function") };
+
+        assert_eq!(*function.parameters[0].span, Span::new(5, 16));
+        assert_eq!(function.parameters[0].annotation.as_ref().unwrap().span(), Span::new(8, 16));
+        assert_eq!(*function.parameters[1].span, Span::new(18, 19));
+    }
+
+    #[test]
+    fn missing_parameter_type_after_colon_errors() {
+        assert_eq!(error("fn f(a:) {}"), ("Expected a type after ':'".to_string(), 7));
+        assert_eq!(error("fn f(a: 1) {}"), ("Expected a type after ':'".to_string(), 8));
+        assert_eq!(error("fn f(a:"), ("Expected a type after ':'".to_string(), 7));
+        assert_eq!(error_kind("fn f(a:"), ParserErrorKind::UnexpectedEof);
+        assert_eq!(error("fn f(a: [) {}").1, 9);
+    }
+
+    #[test]
+    fn duplicate_function_parameters_error_at_the_second_occurrence() {
+        assert_eq!(error("fn f(a, a) {}"), ("Duplicate parameter 'a'".to_string(), 8));
+        assert_eq!(error("fn f(a, b, a) {}"), ("Duplicate parameter 'a'".to_string(), 11));
+    }
+
+    #[test]
+    fn parse_source_never_panics_on_token_soup() {
+        let mut random = Random(0x5eed);
+
+        for _ in 0..20_000 {
+            let length = random.below(16) + 1;
+            let source: Vec<&str> = (0..length).map(|_| FRAGMENTS[random.below(FRAGMENTS.len())]).collect();
+
+            assert_no_panic(&source.join(" "));
+            assert_no_panic(&source.concat());
+        }
+    }
+
+    #[test]
+    fn parse_source_never_panics_on_truncated_examples() {
+        let source = "fn add(a: int, b: int) { return a + b }\nx := [1, 2]\nif x == 1 { $ echo ${x} } else { 'outer: loop { break 'outer } }\nmatch x { 1 => true, _ => false }";
+        let chars: Vec<char> = source.chars().collect();
+
+        for end in 0..=chars.len() {
+            assert_no_panic(&chars[..end].iter().collect::<String>());
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_keywords() {
+        let tokens = vec![Token { kind: TokenKind::Reserved, start: 0, end: 6, value: TokenValue::String("import".to_string()) }];
+        let error = Parser::new(&tokens).parse().expect_err("reserved keywords should fail to parse");
+
+        assert_eq!((error.message.as_str(), error.span), ("'import' is a reserved keyword", Some(Span::new(0, 6))));
+    }
+
+    #[test]
+    fn parses_loops() {
+        for source in ["loop { 1 }", "while x < 10 { x = x + 1 }", "for i in xs { f(i) }", "loop { if a { break } else { continue } }"] {
+            assert_eq!(parse(source).to_string(), source);
+        }
+
+        assert_eq!(kinds("loop { }
+1"), ["loop", "literal"]);
+        assert_eq!(error("for i xs { }").0, "Unexpected token of kind Identifier: Expected 'In' after the for loop binding");
     }
 }
@@ -1,6 +1,68 @@
+use common::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParserErrorKind {
+  /// The input is malformed.
+  Syntax,
+  /// The input ended before the expression being parsed was complete, e.g. `if x {`. More input
+  /// could make it valid, so a REPL can keep reading rather than report the error.
+  UnexpectedEof,
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error("Parser error: {message} at {position}")]
 pub struct ParserError {
+  pub kind: ParserErrorKind,
   pub message: String,
+  /// Char offset at which the error occurred.
   pub position: usize,
+  /// Index of the token at which parsing failed, or the number of tokens if parsing failed at the
+  /// end of the input.
+  pub token_index: Option<usize>,
+  /// The region of source the error applies to, if it's known.
+  pub span: Option<Span>,
+  /// A short note shown against the span in reports.
+  pub label: Option<String>,
+  /// Another region of source which explains the error, e.g. a bracket left open, and the note
+  /// shown against it. Boxed as it's rarely set, to keep results holding the error small.
+  pub secondary: Option<Box<(Span, String)>>,
+}
+
+impl ParserError {
+  pub fn new(message: impl Into<String>, position: usize) -> Self {
+    Self {
+      kind: ParserErrorKind::Syntax,
+      message: message.into(),
+      position,
+      token_index: None,
+      span: None,
+      label: None,
+      secondary: None,
+    }
+  }
+
+  pub fn with_kind(mut self, kind: ParserErrorKind) -> Self {
+    self.kind = kind;
+    self
+  }
+
+  pub fn with_token_index(mut self, token_index: usize) -> Self {
+    self.token_index = Some(token_index);
+    self
+  }
+
+  pub fn with_span(mut self, span: Span) -> Self {
+    self.span = Some(span);
+    self
+  }
+
+  pub fn with_label(mut self, label: impl Into<String>) -> Self {
+    self.label = Some(label.into());
+    self
+  }
+
+  pub fn with_secondary(mut self, span: Span, label: impl Into<String>) -> Self {
+    self.secondary = Some(Box::new((span, label.into())));
+    self
+  }
 }
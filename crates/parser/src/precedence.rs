@@ -0,0 +1,200 @@
+use std::fmt::{Display, Formatter};
+
+use crate::ast::{InfixOperatorKind, PostfixOperatorKind, PrefixOperatorKind};
+
+/// The left and right binding powers of an operator, see `Operator::binding_power`.
+pub type BindingPower = (Option<u8>, Option<u8>);
+
+/// An operator in the position it's parsed in. The same token may be several operators, e.g. `-`
+/// is both a prefix and an infix operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Prefix(PrefixOperatorKind),
+    Infix(InfixOperatorKind),
+    Postfix(PostfixOperatorKind),
+}
+
+impl Operator {
+    /// The left and right binding powers of the operator. Prefix operators have no left binding
+    /// power and postfix operators no right binding power. Higher binds tighter.
+    pub fn binding_power(&self) -> BindingPower {
+        match self {
+            Operator::Prefix(operator) => (None, Some(operator.prefix_binding_power().1)),
+            Operator::Infix(operator) => {
+                let (l_bp, r_bp) = operator.binding_power();
+
+                (Some(l_bp), Some(r_bp))
+            }
+            Operator::Postfix(operator) => (Some(operator.postfix_binding_power().0), None),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Prefix(operator) => operator.as_str(),
+            Operator::Infix(operator) => operator.as_str(),
+            Operator::Postfix(operator) => operator.as_str(),
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Every operator along with its binding powers, prefix operators first, then infix and postfix.
+/// This is the table the parser decides precedence and associativity by unless overridden with a
+/// `PrattConfig`, for tools such as formatters which need to agree with it.
+pub fn binding_power_table() -> Vec<(Operator, BindingPower)> {
+    PrefixOperatorKind::ALL
+        .into_iter()
+        .map(Operator::Prefix)
+        .chain(InfixOperatorKind::ALL.into_iter().map(Operator::Infix))
+        .chain(PostfixOperatorKind::ALL.into_iter().map(Operator::Postfix))
+        .map(|operator| (operator, operator.binding_power()))
+        .collect()
+}
+
+/// Binding powers which override the default ones of `binding_power_table` when parsing, for
+/// languages built on the parser which need different precedence. See `Parser::with_config`.
+///
+/// Expressions are always displayed with the default precedence, so a tree parsed with overrides
+/// may not display as source which parses back to it.
+#[derive(Debug, Clone, Default)]
+pub struct PrattConfig {
+    overrides: Vec<(Operator, BindingPower)>,
+}
+
+impl PrattConfig {
+    /// Sets the right binding power of a prefix operator.
+    pub fn with_prefix(self, operator: PrefixOperatorKind, r_bp: u8) -> Self {
+        self.with_binding_power(Operator::Prefix(operator), (None, Some(r_bp)))
+    }
+
+    /// Sets the left and right binding powers of an infix operator. The operator is left
+    /// associative if the right binding power is greater than the left, otherwise right
+    /// associative.
+    pub fn with_infix(self, operator: InfixOperatorKind, l_bp: u8, r_bp: u8) -> Self {
+        self.with_binding_power(Operator::Infix(operator), (Some(l_bp), Some(r_bp)))
+    }
+
+    /// Sets the left binding power of a postfix operator.
+    pub fn with_postfix(self, operator: PostfixOperatorKind, l_bp: u8) -> Self {
+        self.with_binding_power(Operator::Postfix(operator), (Some(l_bp), None))
+    }
+
+    fn with_binding_power(mut self, operator: Operator, binding_power: BindingPower) -> Self {
+        self.overrides.retain(|(existing, _)| *existing != operator);
+        self.overrides.push((operator, binding_power));
+
+        self
+    }
+
+    /// The binding powers of the operator, which are the default ones unless overridden.
+    pub fn binding_power(&self, operator: Operator) -> BindingPower {
+        self.overrides
+            .iter()
+            .find(|(existing, _)| *existing == operator)
+            .map_or_else(|| operator.binding_power(), |(_, binding_power)| *binding_power)
+    }
+
+    pub fn prefix_binding_power(&self, operator: PrefixOperatorKind) -> ((), u8) {
+        match self.binding_power(Operator::Prefix(operator)) {
+            (_, Some(r_bp)) => ((), r_bp),
+            _ => unreachable!("Prefix operators have a right binding power"),
+        }
+    }
+
+    pub fn infix_binding_power(&self, operator: InfixOperatorKind) -> (u8, u8) {
+        match self.binding_power(Operator::Infix(operator)) {
+            (Some(l_bp), Some(r_bp)) => (l_bp, r_bp),
+            _ => unreachable!("Infix operators have a left and right binding power"),
+        }
+    }
+
+    pub fn postfix_binding_power(&self, operator: PostfixOperatorKind) -> (u8, ()) {
+        match self.binding_power(Operator::Postfix(operator)) {
+            (Some(l_bp), _) => (l_bp, ()),
+            _ => unreachable!("Postfix operators have a left binding power"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{binding_power_table, Operator, PrattConfig};
+    use crate::ast::{InfixOperatorKind, PostfixOperatorKind, PrefixOperatorKind};
+
+    #[test]
+    fn the_table_assigns_every_operator_once() {
+        let table = binding_power_table();
+        let operators = PrefixOperatorKind::ALL.len() + InfixOperatorKind::ALL.len() + PostfixOperatorKind::ALL.len();
+
+        assert_eq!(table.len(), operators);
+
+        for (index, (operator, _)) in table.iter().enumerate() {
+            assert!(!table[..index].iter().any(|(other, _)| other == operator), "{operator:?} is listed twice");
+        }
+    }
+
+    #[test]
+    fn each_operator_binds_on_the_sides_it_has_operands() {
+        for (operator, binding_power) in binding_power_table() {
+            match (operator, binding_power) {
+                (Operator::Prefix(_), (None, Some(r_bp))) => assert!(r_bp > 0, "{operator}"),
+                (Operator::Infix(_), (Some(l_bp), Some(r_bp))) => assert!(l_bp > 0 && r_bp > 0, "{operator}"),
+                (Operator::Postfix(_), (Some(l_bp), None)) => assert!(l_bp > 0, "{operator}"),
+                _ => panic!("{operator:?} has binding power {binding_power:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn assignments_are_right_associative_and_loosest() {
+        let table = binding_power_table();
+        let loosest = table.iter().filter_map(|(_, (l_bp, _))| *l_bp).min().unwrap();
+
+        for (operator, binding_power) in &table {
+            if let Operator::Infix(infix) = operator {
+                let (Some(l_bp), Some(r_bp)) = binding_power else { unreachable!() };
+
+                assert_eq!(infix.is_assignment(), *l_bp == loosest, "{operator}");
+                assert_eq!(l_bp > r_bp, infix.is_assignment() || *infix == InfixOperatorKind::Caret, "{operator}");
+            }
+        }
+    }
+
+    #[test]
+    fn the_default_config_uses_the_table() {
+        let config = PrattConfig::default();
+
+        for (operator, binding_power) in binding_power_table() {
+            assert_eq!(config.binding_power(operator), binding_power, "{operator}");
+        }
+    }
+
+    #[test]
+    fn overrides_only_replace_their_operator() {
+        let config = PrattConfig::default()
+            .with_prefix(PrefixOperatorKind::Minus, 40)
+            .with_infix(InfixOperatorKind::Plus, 30, 31)
+            .with_postfix(PostfixOperatorKind::Bang, 50);
+
+        assert_eq!(config.prefix_binding_power(PrefixOperatorKind::Minus), ((), 40));
+        assert_eq!(config.infix_binding_power(InfixOperatorKind::Plus), (30, 31));
+        assert_eq!(config.postfix_binding_power(PostfixOperatorKind::Bang), (50, ()));
+
+        // The infix `-` is a different operator to the prefix one
+        assert_eq!(config.infix_binding_power(InfixOperatorKind::Minus), InfixOperatorKind::Minus.binding_power());
+        assert_eq!(config.infix_binding_power(InfixOperatorKind::Star), InfixOperatorKind::Star.binding_power());
+    }
+
+    #[test]
+    fn a_later_override_of_the_same_operator_wins() {
+        let config = PrattConfig::default().with_infix(InfixOperatorKind::Plus, 30, 31).with_infix(InfixOperatorKind::Plus, 5, 6);
+
+        assert_eq!(config.infix_binding_power(InfixOperatorKind::Plus), (5, 6));
+    }
+}
@@ -1,31 +1,124 @@
-use std::process::exit;
+use std::{
+    env, fs,
+    io::{self, Read},
+    process::exit,
+};
 
-use common::error::format_error_message_inline;
 use lexer::default_lexer;
-use parser::Parser;
+use parser::{diagnostic::Diagnostic, Parser};
+
+/// Reads the source from the file at the given path, or from stdin if the path is `-`.
+fn read_source(path: &str) -> Result<String, String> {
+    if path == "-" {
+        let mut source = String::new();
+
+        io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|error| format!("Failed to read from stdin: {}", error))?;
+
+        return Ok(source);
+    }
+
+    fs::read_to_string(path).map_err(|error| format!("Failed to read '{}': {}", path, error))
+}
+
+const USAGE: &str = "\
+Usage: command-script [--emit <mode>] <file>
+
+Pass '-' as the file to read from stdin.
+
+Options:
+  --emit <mode>  Output format: debug (default), ast-json or tokens-json";
+
+enum Emit {
+    Debug,
+    AstJson,
+    TokensJson,
+}
+
+struct Options {
+    path: String,
+    emit: Emit,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut path = None;
+    let mut emit = Emit::Debug;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--emit" => {
+                emit = match args.next().as_deref() {
+                    Some("debug") => Emit::Debug,
+                    Some("ast-json") => Emit::AstJson,
+                    Some("tokens-json") => Emit::TokensJson,
+                    Some(mode) => return Err(format!("Unknown emit mode '{}'", mode)),
+                    None => return Err("Expected a mode after '--emit'".to_string()),
+                }
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(Options {
+        path: path.ok_or("Expected a file")?,
+        emit,
+    })
+}
 
 fn main() {
-    let source = "1 + (2 + 1) * 3 / a.b.c - some_variable";
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            exit(1);
+        }
+    };
+
+    let source = match read_source(&options.path) {
+        Ok(source) => source,
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(1);
+        }
+    };
 
     let result = default_lexer().lex(&source);
 
-    if result.errors.len() > 0 {
-        result.errors.iter().for_each(move |error| {
-            println!("{}", format_error_message_inline(source, &error.message, error.position));
+    if result.has_errors() {
+        result.errors.into_iter().for_each(|error| {
+            println!("{}\n", Diagnostic::from(error).render(&source));
         });
 
         exit(1);
     }
 
-    println!("tokens = {:#?}", result.tokens);
+    match options.emit {
+        Emit::Debug => println!("tokens = {:#?}", result.tokens),
+        Emit::TokensJson => {
+            println!("{}", serde_json::to_string_pretty(&result.tokens).unwrap());
+
+            return;
+        }
+        Emit::AstJson => {}
+    }
 
     let result = Parser::new(&result.tokens).parse();
 
     if let Err(error) = result {
-        println!("{}", format_error_message_inline(source, &error.message, error.position));
+        println!("{}", Diagnostic::from(error).render(&source));
 
         exit(1);
     }
 
-    println!("program = {:#?}", result.unwrap());
+    // Warnings go to stderr so as not to interfere with the emitted output
+    result.as_ref().unwrap().warnings.iter().for_each(|warning| {
+        eprintln!("{}\n", warning.render(&source));
+    });
+
+    match options.emit {
+        Emit::AstJson => println!("{}", serde_json::to_string_pretty(&result.unwrap()).unwrap()),
+        _ => println!("program = {:#?}", result.unwrap()),
+    }
 }
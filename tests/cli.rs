@@ -0,0 +1,156 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+/// Writes the source to a file in the temp directory which is unique to the test.
+fn source_file(name: &str, source: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("command-script-{}-{}.cs", std::process::id(), name));
+    fs::write(&path, source).unwrap();
+
+    path
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_command-script"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn run_with_stdin(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_command-script"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn prints_the_program_for_a_valid_file() {
+    let path = source_file("valid", "x = 1 + 2\n");
+    let output = run(&[path.to_str().unwrap()]);
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("tokens = ["));
+    assert!(stdout(&output).contains("program = Program {"));
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn reads_the_source_from_stdin() {
+    let output = run_with_stdin(&["-"], "x = 1 + 2\n");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("program = Program {"));
+}
+
+#[test]
+fn prints_the_diagnostic_for_invalid_input() {
+    let path = source_file("invalid", "x = \n");
+    let output = run(&[path.to_str().unwrap()]);
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("error: Expected expression"));
+    assert!(stdout(&output).contains("1 | x = "));
+    assert!(!stdout(&output).contains("program ="));
+}
+
+#[test]
+fn reports_a_missing_file() {
+    let output = run(&["does/not/exist.cs"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("Failed to read 'does/not/exist.cs': "));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn reports_bad_arguments_with_the_usage() {
+    let cases: &[(&[&str], &str)] = &[
+        (&[], "Expected a file"),
+        (&["a.cs", "b.cs"], "Unexpected argument 'b.cs'"),
+        (&["--emit"], "Expected a mode after '--emit'"),
+        (&["--emit", "yaml", "a.cs"], "Unknown emit mode 'yaml'"),
+    ];
+
+    for (args, message) in cases {
+        let output = run(args);
+
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        assert!(stderr(&output).starts_with(message), "{:?}: {}", args, stderr(&output));
+        assert!(stderr(&output).contains("Usage: command-script"), "{:?}", args);
+        assert_eq!(stdout(&output), "", "{:?}", args);
+    }
+}
+
+fn emit_json(mode: &str, source: &str) -> serde_json::Value {
+    let output = run_with_stdin(&["--emit", mode, "-"], source);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    serde_json::from_str(&stdout(&output)).unwrap()
+}
+
+#[test]
+fn emits_the_tokens_as_json() {
+    let tokens = emit_json("tokens-json", "x = 1");
+    let tokens = tokens.as_array().unwrap();
+
+    let kinds: Vec<&str> = tokens.iter().map(|token| token["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["Identifier", "Whitespace", "Equals", "Whitespace", "Number"]);
+    assert_eq!(tokens[0]["start"], 0);
+    assert_eq!(tokens[0]["end"], 1);
+    assert_eq!(tokens[0]["value"]["String"], "x");
+}
+
+#[test]
+fn emits_the_ast_as_json() {
+    let program = emit_json("ast-json", "x = 1 + 2");
+    let ast = program["ast"].as_array().unwrap();
+
+    assert_eq!(ast.len(), 1);
+
+    let assignment = &ast[0]["Infix"];
+    assert_eq!(assignment["operator"], "Equals");
+    assert_eq!(assignment["span"]["start"], 0);
+    assert_eq!(assignment["span"]["end"], 9);
+    assert_eq!(assignment["left"]["Identifier"]["name"], "x");
+    assert_eq!(assignment["right"]["Infix"]["operator"], "Plus");
+    assert_eq!(assignment["right"]["Infix"]["left"]["Literal"]["value"]["Integer"]["value"], 1);
+}
+
+#[test]
+fn does_not_emit_json_for_invalid_input() {
+    let output = run_with_stdin(&["--emit", "ast-json", "-"], "x = ");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(serde_json::from_str::<serde_json::Value>(&stdout(&output)).is_err());
+}
+
+#[test]
+fn reports_deeply_nested_input_without_crashing() {
+    let depth = 10_000;
+    let output = run_with_stdin(&["-"], &format!("{}x{}", "(".repeat(depth), ")".repeat(depth)));
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("error: Expression nesting too deep"), "{}", stdout(&output));
+}